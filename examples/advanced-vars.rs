@@ -9,7 +9,7 @@ fn main() -> Result<(), fasteval::Error> {
             "y" => Some(4.0),
 
             // Custom function:
-            "sum" => Some(args.into_iter().fold(0.0, |s,f| s+f)),
+            "add" => Some(args.into_iter().fold(0.0, |s,f| s+f)),
 
             // Custom array-like objects:
             // The `args.get...` code is the same as:
@@ -22,7 +22,7 @@ fn main() -> Result<(), fasteval::Error> {
         }
     };
 
-    let val = fasteval::ez_eval("sum(x^2, y^2)^0.5 + data[0]",    &mut cb)?;
+    let val = fasteval::ez_eval("add(x^2, y^2)^0.5 + data[0]",    &mut cb)?;
     //                           |   |                   |
     //                           |   |                   square-brackets act like parenthesis
     //                           |   variables are like custom functions with zero args
@@ -36,7 +36,7 @@ fn main() -> Result<(), fasteval::Error> {
     //       Therefore, variables can receive arguments too,
     //       which will probably be ignored.
     //       Therefore, these two expressions evaluate to the same thing:
-    //           eval("x + y")  ==  eval("x(1,2,3) + y(x, y, sum(x,y))")
+    //           eval("x + y")  ==  eval("x(1,2,3) + y(x, y, add(x,y))")
     //                                      ^^^^^      ^^^^^^^^^^^^^^
     //                                      All this stuff is ignored.
     //
@@ -51,9 +51,11 @@ fn main() -> Result<(), fasteval::Error> {
     //           x         -- Uses the custom 'x' variable.
     //           x()       -- Uses the custom 'x' variable because there is no 'x' builtin.
     //           x(1,2,3)  -- Uses the custom 'x' variable.  The args are ignored.
-    //           sum       -- Uses the custom 'sum' function with no arguments.
-    //           sum()     -- Uses the custom 'sum' function with no arguments.
-    //           sum(1,2)  -- Uses the custom 'sum' function with two arguments.
+    //           add       -- Uses the custom 'add' function with no arguments.
+    //           add()     -- Uses the custom 'add' function with no arguments.
+    //           add(1,2)  -- Uses the custom 'add' function with two arguments.
+    //           sum(1,2)  -- Uses the custom 'sum' function if the namespace defines
+    //                        one, or the builtin 'sum' function otherwise.
 
     Ok(())
 }
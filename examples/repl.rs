@@ -34,6 +34,8 @@
 //! Exited scope[1]
 //! >>> a + b + c
 //! 10
+//! >>> let d = a + b;  d * d + c
+//! 16
 //! >>> 1+2*3/4^5%6 + log(100K) + log(e(),100) + [3*(3-3)/3] + (2<3) && 1.23
 //! 1.23
 //! >>> 1+2*3/4^5%6 + print("log(100K) =",log(100K)) + log(e(),100) + [3*(3-3)/3] + (2<3) && 1.23
@@ -72,7 +74,7 @@ fn repl() {
         if line == "" { continue; }
 
         let pieces : Vec<&str> = line.split_whitespace().collect();
-        if pieces[0] == "let" {
+        if pieces[0] == "let" && !line.contains(';') {  // 'let' with ';' is an in-expression binding, handled by the parser.
             if pieces.len()<4 || pieces[2]!="=" {
                 eprintln!("incorrect 'let' syntax.  Should be: let x = ...");
                 continue;
@@ -104,7 +106,7 @@ fn repl() {
         let expr_ref = match parser.parse(&line, &mut slab.ps) {
             Ok(expr_i) => slab.ps.get_expr(expr_i),
             Err(err) => {
-                eprintln!("parse error: {}", err);
+                eprintln!("{}", err.render(&line));
                 continue;
             }
         };
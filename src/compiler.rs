@@ -19,6 +19,7 @@
 //! * Built-in functions with constant arguments are evaluated.
 //! * Constant terms are combined.
 //! * Logical operator short-circuits are applied and no-op branches are discarded.
+//! * Ternaries with a constant condition are replaced by the branch that would be taken.
 //!
 //! ## Optimized Memory Layout and Execution
 //! * Variable-length `Expression`/`Value` AST nodes are converted into constant-sized `Instruction` nodes.
@@ -27,9 +28,11 @@
 
 
 use crate::slab::{ParseSlab, CompileSlab};
-use crate::parser::{Expression, ExprPair, Value, UnaryOp::{self, EPos, ENeg, ENot, EParentheses}, BinaryOp::{self, EOR, EAND, ENE, EEQ, EGTE, ELTE, EGT, ELT, EAdd, ESub, EMul, EDiv, EMod, EExp}, StdFunc::{self, EVar, EFunc, EFuncInt, EFuncCeil, EFuncFloor, EFuncAbs, EFuncSign, EFuncLog, EFuncRound, EFuncMin, EFuncMax, EFuncE, EFuncPi, EFuncSin, EFuncCos, EFuncTan, EFuncASin, EFuncACos, EFuncATan, EFuncSinH, EFuncCosH, EFuncTanH, EFuncASinH, EFuncACosH, EFuncATanH}, PrintFunc};
+use crate::parser::{Expression, ExpressionI, ExprPair, NativeFunc, StatFunc, Value, UnaryOp::{self, EPos, ENeg, ENot, EParentheses}, BinaryOp::{self, EOR, EAND, ENE, EEQ, EGTE, ELTE, EGT, ELT, EAdd, ESub, EMul, EDiv, EMod, EExp}, StdFunc::{self, EVar, ELocal, EFunc, EIndex, EUserFunc, ENamespaceFirst, ENativeFunc, EFuncInt, EFuncCeil, EFuncFloor, EFuncAbs, EFuncSign, EFuncLog, EFuncRound, EFuncMin, EFuncMax, EFuncStat, EFuncIf, EFuncSum, EFuncProd, EFuncE, EFuncPi, EFuncSin, EFuncCos, EFuncTan, EFuncSinD, EFuncCosD, EFuncTanD, EFuncASin, EFuncACos, EFuncATan, EFuncSinH, EFuncCosH, EFuncTanH, EFuncASinH, EFuncACosH, EFuncATanH, EFuncSqrt, EFuncCbrt, EFuncExp, EFuncLn, EFuncLog2, EFuncLog10, EFuncTrunc, EFuncFrac, EFuncDeg, EFuncRad, EFuncErf, EFuncGamma, EFuncLGamma, EFuncATan2, EFuncHypot, EFuncPow, EFuncFMod, EFuncClamp, EFuncStr}, StrFunc, PrintFunc};
 #[cfg(feature="unsafe-vars")]
 use crate::parser::StdFunc::EUnsafeVar;
+#[cfg(feature="bitwise")]
+use crate::parser::{UnaryOp::EBitNot, BinaryOp::{EBitOr, EBitXor, EBitAnd, EShr, EShl, EIntDiv}};
 
 
 /// `true` --> `1.0`,  `false` --> `0.0`
@@ -105,15 +108,49 @@ pub enum Instruction {
     IGTE(IC, IC),
     IGT(IC, IC),
 
+    //---- Chained Comparisons (see Parser.chain_cmp):
+    // The first operand, followed by the comparison ops and their right operands.  Stops at the first false comparison.
+    ICmpChain(IC, Vec<(BinaryOp, IC)>),
+
     //---- Binary Logic Ops:
     IOR(InstructionI, IC),
     IAND(InstructionI, IC),
 
+    //---- Ternary Op:
+    ITernary{cond:InstructionI, then:IC, otherwise:IC},
+
+    //---- Bitwise/Integer Ops (values are truncated to i64):
+    #[cfg(feature="bitwise")]
+    IBitNot(InstructionI),
+    #[cfg(feature="bitwise")]
+    IBitAnd(IC, IC),
+    #[cfg(feature="bitwise")]
+    IBitOr(IC, IC),
+    #[cfg(feature="bitwise")]
+    IBitXor(IC, IC),
+    #[cfg(feature="bitwise")]
+    IShl(IC, IC),
+    #[cfg(feature="bitwise")]
+    IShr(IC, IC),
+    #[cfg(feature="bitwise")]
+    IIntDiv(IC, IC),
+
+    //---- Local Variables (see Value::ELet):
+    ILocal(usize),
+    ILet{slot:usize, value:IC, body:IC},
+
+    //---- Functions defined within the expression (see Value::ELetFunc):
+    ILetFunc{func:usize, body:IC},
+    IUserFunc{func:usize, body:InstructionI, args:Vec<IC>},
+
     //---- Callables:
     IVar(String),
     #[cfg(feature="unsafe-vars")]
     IUnsafeVar{name:String, ptr:*const f64},
     IFunc{name:String, args:Vec<IC>},
+    IIndex{name:String, idx:IC},
+    INamespaceFirst{name:String, args:Vec<IC>, slot:usize, builtin:InstructionI},
+    INativeFunc{func:NativeFunc, args:Vec<IC>},
 
     IFuncInt(InstructionI),
     IFuncCeil(InstructionI),
@@ -124,10 +161,17 @@ pub enum Instruction {
     IFuncRound{modulus:IC, of:IC},
     IFuncMin(InstructionI, IC),
     IFuncMax(InstructionI, IC),
+    IFuncStat{func:StatFunc, skip_nan:bool, ns_first:Option<String>, args:Vec<IC>},
+    IFuncIf{cond:InstructionI, then:IC, otherwise:IC},
+    IFuncSum{ slot:usize, start:IC, end:IC, expr:IC},
+    IFuncProd{slot:usize, start:IC, end:IC, expr:IC},
 
     IFuncSin(InstructionI),
     IFuncCos(InstructionI),
     IFuncTan(InstructionI),
+    IFuncSinD(InstructionI),
+    IFuncCosD(InstructionI),
+    IFuncTanD(InstructionI),
     IFuncASin(InstructionI),
     IFuncACos(InstructionI),
     IFuncATan(InstructionI),
@@ -138,11 +182,35 @@ pub enum Instruction {
     IFuncACosH(InstructionI),
     IFuncATanH(InstructionI),
 
+    IFuncSqrt(InstructionI),
+    IFuncCbrt(InstructionI),
+    IFuncExp(InstructionI),
+    IFuncLn(InstructionI),
+    IFuncLog2(InstructionI),
+    IFuncLog10(InstructionI),
+    IFuncTrunc(InstructionI),
+    IFuncFrac(InstructionI),
+    IFuncDeg(InstructionI),
+    IFuncRad(InstructionI),
+    IFuncErf(InstructionI),
+    IFuncGamma(InstructionI),
+    IFuncLGamma(InstructionI),
+    IFuncATan2(IC, IC),
+    IFuncHypot(IC, IC),
+    IFuncPow(IC, IC),
+    IFuncFMod(IC, IC),
+    IFuncClamp{x:IC, min:IC, max:IC},
+
+    IFuncStr{func:StrFunc, args:Vec<ExpressionI>},  // The args might be strings, so they stay parsed.
+    IValExpr(ExpressionI),  // An expression that might contain strings or arrays.  Not optimized.
+
     IPrintFunc(PrintFunc),  // Not optimized (it would be pointless because of i/o bottleneck).
 }
-use Instruction::{IConst, INeg, INot, IInv, IAdd, IMul, IMod, IExp, ILT, ILTE, IEQ, INE, IGTE, IGT, IOR, IAND, IVar, IFunc, IFuncInt, IFuncCeil, IFuncFloor, IFuncAbs, IFuncSign, IFuncLog, IFuncRound, IFuncMin, IFuncMax, IFuncSin, IFuncCos, IFuncTan, IFuncASin, IFuncACos, IFuncATan, IFuncSinH, IFuncCosH, IFuncTanH, IFuncASinH, IFuncACosH, IFuncATanH, IPrintFunc};
+use Instruction::{IConst, INeg, INot, IInv, IAdd, IMul, IMod, IExp, ILT, ILTE, IEQ, INE, IGTE, IGT, ICmpChain, IOR, IAND, ITernary, ILocal, ILet, ILetFunc, IUserFunc, INamespaceFirst, INativeFunc, IVar, IFunc, IIndex, IFuncInt, IFuncCeil, IFuncFloor, IFuncAbs, IFuncSign, IFuncLog, IFuncRound, IFuncMin, IFuncMax, IFuncStat, IFuncIf, IFuncSum, IFuncProd, IFuncSin, IFuncCos, IFuncTan, IFuncSinD, IFuncCosD, IFuncTanD, IFuncASin, IFuncACos, IFuncATan, IFuncSinH, IFuncCosH, IFuncTanH, IFuncASinH, IFuncACosH, IFuncATanH, IFuncSqrt, IFuncCbrt, IFuncExp, IFuncLn, IFuncLog2, IFuncLog10, IFuncTrunc, IFuncFrac, IFuncDeg, IFuncRad, IFuncErf, IFuncGamma, IFuncLGamma, IFuncATan2, IFuncHypot, IFuncPow, IFuncFMod, IFuncClamp, IFuncStr, IValExpr, IPrintFunc};
 #[cfg(feature="unsafe-vars")]
 use Instruction::IUnsafeVar;
+#[cfg(feature="bitwise")]
+use Instruction::{IBitNot, IBitAnd, IBitOr, IBitXor, IShl, IShr, IIntDiv};
 
 impl Default for Instruction {
     fn default() -> Self { IConst(std::f64::NAN) }
@@ -162,24 +230,26 @@ pub trait Compiler {
 struct ExprSlice<'s> {
     first: &'s Value,
     pairs: Vec<&'s ExprPair>,
+    chain_cmp: bool,
 }
 impl<'s> ExprSlice<'s> {
-    fn new(first:&Value) -> ExprSlice<'_> {
+    fn new(first:&Value, chain_cmp:bool) -> ExprSlice<'_> {
         ExprSlice{
             first,
             pairs:Vec::with_capacity(8),
+            chain_cmp,
         }
     }
     fn from_expr(expr:&Expression) -> ExprSlice<'_> {
-        let mut sl = ExprSlice::new(&expr.first);
+        let mut sl = ExprSlice::new(&expr.first, expr.chain_cmp());
         for exprpairref in expr.pairs.iter() { sl.pairs.push(exprpairref) }
         sl
     }
     fn split(&self, bop:BinaryOp, dst:&mut Vec<ExprSlice<'s>>) {
-        dst.push(ExprSlice::new(&self.first));
+        dst.push(ExprSlice::new(&self.first, self.chain_cmp));
         for exprpair in self.pairs.iter() {
             if exprpair.0==bop {
-                dst.push(ExprSlice::new(&exprpair.1));
+                dst.push(ExprSlice::new(&exprpair.1, self.chain_cmp));
             } else {
                 match dst.last_mut() {
                     Some(cur) => cur.pairs.push(exprpair),
@@ -189,10 +259,10 @@ impl<'s> ExprSlice<'s> {
         }
     }
     fn split_multi(&self, search:&[BinaryOp], xsdst:&mut Vec<ExprSlice<'s>>, opdst:&mut Vec<&'s BinaryOp>) {
-        xsdst.push(ExprSlice::new(&self.first));
+        xsdst.push(ExprSlice::new(&self.first, self.chain_cmp));
         for exprpair in self.pairs.iter() {
             if search.contains(&exprpair.0) {
-                xsdst.push(ExprSlice::new(&exprpair.1));
+                xsdst.push(ExprSlice::new(&exprpair.1, self.chain_cmp));
                 opdst.push(&exprpair.0);
             } else {
                 match xsdst.last_mut() {
@@ -314,6 +384,49 @@ fn compile_add(instrs:Vec<Instruction>, cslab:&mut CompileSlab) -> Instruction {
     }
     out
 }
+// The number of index values in a sum() or prod() range:  start, start+1, ... <= end.
+pub(crate) fn series_len(start:f64, end:f64) -> f64 {
+    if end>=start { (end-start).floor()+1.0 }
+    else { 0.0 }  // Also handles NaN.
+}
+
+// The most body compilations that folding a series (including any series nested inside it)
+// may spend.  Each nested fold multiplies the work, so this keeps compile time bounded.
+const FOLD_LIMIT:usize = 10_000;
+
+// Tries to compute a sum() or prod() at compile time, by compiling `expr` with
+// each index value as a constant.  Returns None if `expr` isn't constant, or if folding
+// would exceed `FOLD_LIMIT`; the series is then evaluated at run time instead.
+// Either way, the instructions from these trial compilations are dropped afterwards.
+fn fold_series(is_sum:bool, slot:usize, start:f64, n:f64, expr:ExpressionI, pslab:&ParseSlab, cslab:&mut CompileSlab) -> Option<f64> {
+    let outermost = cslab.fold_budget.is_none();
+    let budget = cslab.fold_budget.unwrap_or(FOLD_LIMIT);
+    if n>budget as f64 { return None; }
+    cslab.fold_budget = Some(budget);
+    let instrs_len = cslab.instrs.len();
+
+    let mut acc = if is_sum { 0.0 } else { 1.0 };
+    let mut k = 0.0;
+    let mut out = Some(acc);
+    while k<n {
+        match cslab.fold_budget {
+            Some(b) if b>0 => cslab.fold_budget = Some(b-1),
+            _ => { out=None; break; }
+        }
+        cslab.set_local_const(slot, Some(start+k));
+        match get_expr!(pslab,expr).compile(pslab,cslab) {
+            IConst(c) => if is_sum { acc+=c } else { acc*=c },
+            _ => { out=None; break; }
+        }
+        k+=1.0;
+        out = Some(acc);
+    }
+    cslab.set_local_const(slot, None);
+    cslab.truncate_instrs(instrs_len);
+    if outermost { cslab.fold_budget = None; }
+    out
+}
+
 pub(crate) fn log(base:f64, n:f64) -> f64 {
     // Can't use floating point in 'match' patterns.  :(
     if f64_eq!(base,2.0) { return n.log2(); }
@@ -321,6 +434,208 @@ pub(crate) fn log(base:f64, n:f64) -> f64 {
     n.log(base)
 }
 
+// Unlike f64::clamp(), this doesn't panic when min>max or a bound is NaN.  It returns NaN instead.
+pub(crate) fn clamp(x:f64, min:f64, max:f64) -> f64 {
+    if x.is_nan() || min.is_nan() || max.is_nan() || min>max { return std::f64::NAN; }
+    x.max(min).min(max)
+}
+
+// Reduces an angle in degrees to (-180,180], so that exact multiples of 90 can be recognized.
+fn reduce_deg(x:f64) -> f64 {
+    let r = x % 360.0;
+    if r>180.0 { r-360.0 } else if r<=-180.0 { r+360.0 } else { r }
+}
+
+// The trig functions of an angle in degrees.  Unlike `x.to_radians().cos()`, these give
+// exact results at multiples of 90, like cosd(90)==0, which can't be represented in radians.
+pub(crate) fn sind(x:f64) -> f64 {
+    let r = reduce_deg(x);
+    if r==0.0 || r==180.0 { 0.0 }
+    else if r==90.0 { 1.0 }
+    else if r==-90.0 { -1.0 }
+    else { r.to_radians().sin() }
+}
+pub(crate) fn cosd(x:f64) -> f64 {
+    let r = reduce_deg(x);
+    if r==90.0 || r==-90.0 { 0.0 }
+    else if r==0.0 { 1.0 }
+    else if r==180.0 { -1.0 }
+    else { r.to_radians().cos() }
+}
+pub(crate) fn tand(x:f64) -> f64 {
+    let r = reduce_deg(x);
+    if r==0.0 || r==180.0 { 0.0 }
+    else if r==45.0 || r==-135.0 { 1.0 }
+    else if r==-45.0 || r==135.0 { -1.0 }
+    else if r==90.0 { std::f64::INFINITY }
+    else if r==-90.0 { std::f64::NEG_INFINITY }
+    else { r.to_radians().tan() }
+}
+
+// Accumulates the values of a statistics function one at a time, so that evaluating it doesn't
+// allocate.  Only median() and percentile() need to keep the values.  For `StatFunc::Percentile`,
+// the first value is the percentile.  When `skip_nan` is set, NaN values are ignored; otherwise
+// any NaN makes the result NaN.
+pub(crate) struct Stat {
+    func    :StatFunc,
+    skip_nan:bool,
+    p       :Option<f64>,
+    n       :f64,
+    sum     :f64,
+    mean    :f64,
+    m2      :f64,  // The sum of squared differences from the mean, for Welford's variance.
+    vals    :Vec<f64>,
+}
+impl Stat {
+    pub(crate) fn new(func:StatFunc, skip_nan:bool) -> Self {
+        Self{func, skip_nan, p:None, n:0.0, sum:0.0, mean:0.0, m2:0.0, vals:Vec::new()}
+    }
+    pub(crate) fn push(&mut self, v:f64) {
+        if self.func==StatFunc::Percentile && self.p.is_none() { self.p=Some(v); return; }
+        if self.skip_nan && v.is_nan() { return; }
+        self.n+=1.0;
+        match self.func {
+            StatFunc::Median | StatFunc::Percentile => self.vals.push(v),
+            _ => {
+                self.sum+=v;
+                let d = v-self.mean;
+                self.mean+=d/self.n;
+                self.m2+=d*(v-self.mean);
+            }
+        }
+    }
+    pub(crate) fn finish(self) -> f64 {
+        // The sample variance:
+        let variance = if self.n<2.0 { std::f64::NAN } else { self.m2/(self.n-1.0) };
+        match self.func {
+            StatFunc::Count => self.n,
+            StatFunc::Sum => self.sum,
+            StatFunc::Mean => self.sum / self.n,  // 0/0 is NaN for an empty list.
+            StatFunc::Variance => variance,
+            StatFunc::StdDev => variance.sqrt(),
+            StatFunc::Median | StatFunc::Percentile => percentile(self.p.unwrap_or(50.0), self.vals),
+        }
+    }
+}
+
+// Linear interpolation between the closest ranks, with `p` between 0 and 100.
+fn percentile(p:f64, mut vals:Vec<f64>) -> f64 {
+    if !(0.0..=100.0).contains(&p) || vals.is_empty() || vals.iter().any(|v| v.is_nan()) { return std::f64::NAN; }
+    vals.sort_by(|a,b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+    let rank = p/100.0 * (vals.len()-1) as f64;
+    let lo = rank.floor() as usize;
+    let hi = rank.ceil() as usize;
+    let frac = rank - lo as f64;
+    if lo==hi { vals[lo] } else { vals[lo]*(1.0-frac) + vals[hi]*frac }
+}
+
+// The error function.  Computed with the series  erf(x) = 2/sqrt(pi) * exp(-x^2) * sum( 2^n x^(2n+1) / (1*3*...*(2n+1)) ),
+// which is accurate to full precision because all of its terms are positive.
+pub(crate) fn erf(x:f64) -> f64 {
+    let ax = x.abs();
+    if ax.is_nan() { return x; }
+    if ax>=6.0 { return x.signum(); }  // erfc(6) < 1e-16
+    let x2 = ax*ax;
+    let mut term = ax;
+    let mut sum = ax;
+    let mut n = 0.0;
+    while term>sum*1e-17 {
+        n+=1.0;
+        term *= 2.0*x2/(2.0*n+1.0);
+        sum += term;
+    }
+    (2.0/std::f64::consts::PI.sqrt() * (-x2).exp() * sum).min(1.0).copysign(x)
+}
+
+// Lanczos approximation coefficients, for g=7 and n=9.
+const LANCZOS_G : f64 = 7.0;
+const LANCZOS_COEF : [f64; 9] = [0.999_999_999_999_809_9,
+                                 676.520_368_121_885_1,
+                                 -1_259.139_216_722_402_8,
+                                 771.323_428_777_653_1,
+                                 -176.615_029_162_140_6,
+                                 12.507_343_278_686_905,
+                                 -0.138_571_095_265_720_12,
+                                 9.984_369_578_019_572e-6,
+                                 1.505_632_735_149_311_6e-7];
+// Returns (t, a) such that  Gamma(x) = sqrt(2*pi) * t^(x-0.5) * exp(-t) * a,  for x >= 0.5.
+fn lanczos(x:f64) -> (f64, f64) {
+    let x = x-1.0;
+    let mut a = LANCZOS_COEF[0];
+    for (i,c) in LANCZOS_COEF.iter().enumerate().skip(1) {
+        a += c/(x+i as f64);
+    }
+    (x+LANCZOS_G+0.5, a)
+}
+// For large x, the Stirling series is more accurate than Lanczos:
+//     Gamma(x) = sqrt(2*pi) * x^(x-0.5) * exp(-x) * exp(stirling(x)),  for x >= 15.
+fn stirling(x:f64) -> f64 {
+    const COEF : [f64; 8] = [1.0/12.0, -1.0/360.0, 1.0/1260.0, -1.0/1680.0, 1.0/1188.0, -691.0/360_360.0, 1.0/156.0, -3617.0/122_400.0];
+    let z = 1.0/(x*x);
+    COEF.iter().rev().fold(0.0, |acc,c| acc*z + c) / x
+}
+
+// The gamma function.  Like C's tgamma(), Gamma(±0) is ±inf and negative integers produce NaN.
+pub(crate) fn gamma(x:f64) -> f64 {
+    use std::f64::consts::PI;
+    if x==0.0 { return 1.0/x; }
+    if x<0.5 {
+        if x==x.floor() { return std::f64::NAN; }  // Also handles -inf.
+        return PI / ((PI*x).sin() * gamma(1.0-x));  // Reflection formula.
+    }
+    if x>171.7 { return std::f64::INFINITY; }  // Also handles +inf.  NaN falls through.
+    if x==x.floor() {
+        // Exact factorials, for as long as f64 can represent them:
+        let mut out = 1.0;
+        let mut k = 2.0;
+        while k<x { out*=k; k+=1.0; }
+        return out;
+    }
+    if x>=15.0 {
+        let p = x.powf((x-0.5)/2.0);  // Split the power in half to avoid overflow.
+        return (2.0*PI).sqrt() * p * (p*(-x).exp()) * stirling(x).exp();
+    }
+    let (t, a) = lanczos(x);
+    (2.0*PI).sqrt() * t.powf(x-0.5) * (-t).exp() * a
+}
+
+// The natural log of the absolute value of the gamma function.  Like C's lgamma(), the poles produce +inf.
+pub(crate) fn lgamma(x:f64) -> f64 {
+    use std::f64::consts::PI;
+    if x.is_infinite() { return std::f64::INFINITY; }
+    if x<0.5 {
+        if x==x.floor() { return std::f64::INFINITY; }
+        return (PI/(PI*x).sin().abs()).ln() - lgamma(1.0-x);  // Reflection formula.
+    }
+    if f64_eq!(x,1.0) || f64_eq!(x,2.0) { return 0.0; }
+    if x>=15.0 { return 0.5*(2.0*PI).ln() + (x-0.5)*x.ln() - x + stirling(x); }
+    let (t, a) = lanczos(x);
+    0.5*(2.0*PI).ln() + (x-0.5)*t.ln() - t + a.ln()
+}
+
+// The bitwise operators truncate their operands to i64 ('as' saturates, and NaN becomes 0).
+// Operations that have no integer result (like division by zero, or shifting by
+// a negative amount or more than 63 bits) produce NaN.
+#[cfg(feature="bitwise")]
+pub(crate) fn bitwise(op:BinaryOp, l:f64, r:f64) -> f64 {
+    let (l,r) = (l as i64, r as i64);
+    match op {
+        EBitAnd => (l&r) as f64,
+        EBitOr => (l|r) as f64,
+        EBitXor => (l^r) as f64,
+        EShl => if (0..64).contains(&r) { (l<<r) as f64 } else { std::f64::NAN },
+        EShr => if (0..64).contains(&r) { (l>>r) as f64 } else { std::f64::NAN },
+        EIntDiv => {
+            // Floor division, like Python:  -7 // 2 == -4
+            match l.checked_div(r) {
+                Some(q) => if l%r!=0 && (l<0)!=(r<0) { (q-1) as f64 } else { q as f64 },
+                None => std::f64::NAN,
+            }
+        }
+        _ => std::f64::NAN,  // unreachable
+    }
+}
+
 // Can't inline recursive functions:
 fn push_mul_leaves(instrs:&mut Vec<Instruction>, cslab:&mut CompileSlab, li:InstructionI, ric:IC) {
     // Take 'r' before 'l' for a chance for more efficient memory usage:
@@ -391,11 +706,23 @@ impl Compiler for ExprSlice<'_> {
             if exprpair.0<lowest_op { lowest_op=exprpair.0 }
         }
 
+        // Both shifts have equal precedence, and are processed left-to-right:
+        #[cfg(feature="bitwise")]
+        {
+            if lowest_op==EShl || lowest_op==EShr {
+                let mut ops = Vec::<&BinaryOp>::with_capacity(4);
+                let mut xss = Vec::<ExprSlice>::with_capacity(ops.len()+1);
+                self.split_multi(&[EShl, EShr], &mut xss, &mut ops);
+                return compile_bitwise_ltor(&xss, &ops, pslab, cslab);
+            }
+        }
+
         // All comparisons have equal precedence:
         if lowest_op==EEQ || lowest_op==ENE || lowest_op==ELT || lowest_op==EGT || lowest_op==ELTE || lowest_op==EGTE {
             let mut ops = Vec::<&BinaryOp>::with_capacity(4);
             let mut xss = Vec::<ExprSlice>::with_capacity(ops.len()+1);
             self.split_multi(&[EEQ, ENE, ELT, EGT, ELTE, EGTE], &mut xss, &mut ops);
+            if self.chain_cmp && ops.len()>1 { return compile_cmp_chain(&xss, &ops, pslab, cslab); }
             let mut out = match xss.first() {
                 Some(xs) => xs.compile(pslab,cslab),
                 None => IConst(std::f64::NAN),  // unreachable
@@ -643,13 +970,109 @@ impl Compiler for ExprSlice<'_> {
 //              }
 //              IExp{base:cslab.push_instr(base), power:cslab.push_instr(power)}
 //          }
+            #[cfg(feature="bitwise")]
+            EBitOr | EBitXor | EBitAnd | EIntDiv => {
+                let mut xss = Vec::<ExprSlice>::with_capacity(4);
+                self.split(lowest_op, &mut xss);
+                let ops = vec![&lowest_op; xss.len().saturating_sub(1)];
+                compile_bitwise_ltor(&xss, &ops, pslab, cslab)
+            }
+            #[cfg(feature="bitwise")]
+            EShl | EShr => IConst(std::f64::NAN),  // unreachable
             ENE | EEQ | EGTE | ELTE | EGT | ELT => IConst(std::f64::NAN),  // unreachable
         }
     }
 }
 
+// Folds 'xss[0] ops[0] xss[1] ops[1] ...' from left to right, calculating constant pairs at compile time:
+#[cfg(feature="bitwise")]
+fn compile_bitwise_ltor(xss:&[ExprSlice], ops:&[&BinaryOp], pslab:&ParseSlab, cslab:&mut CompileSlab) -> Instruction {
+    let mut out = match xss.first() {
+        Some(xs) => xs.compile(pslab,cslab),
+        None => IConst(std::f64::NAN),  // unreachable
+    };
+    for (i,op) in ops.iter().enumerate() {
+        let instr = match xss.get(i+1) {
+            Some(xs) => xs.compile(pslab,cslab),
+            None => IConst(std::f64::NAN),  // unreachable
+        };
+        if let IConst(l) = out {
+            if let IConst(r) = instr {
+                out = IConst(bitwise(**op,l,r));
+                continue;
+            }
+        }
+        out = match op {
+            EBitAnd => IBitAnd(instr_to_ic!(cslab,out), instr_to_ic!(cslab,instr)),
+            EBitOr => IBitOr(instr_to_ic!(cslab,out), instr_to_ic!(cslab,instr)),
+            EBitXor => IBitXor(instr_to_ic!(cslab,out), instr_to_ic!(cslab,instr)),
+            EShl => IShl(instr_to_ic!(cslab,out), instr_to_ic!(cslab,instr)),
+            EShr => IShr(instr_to_ic!(cslab,out), instr_to_ic!(cslab,instr)),
+            EIntDiv => IIntDiv(instr_to_ic!(cslab,out), instr_to_ic!(cslab,instr)),
+            _ => IConst(std::f64::NAN),  // unreachable
+        };
+    }
+    out
+}
+
+// a < b <= c  -->  a < b && b <= c, with b only evaluated once:
+fn compile_cmp_chain(xss:&[ExprSlice], ops:&[&BinaryOp], pslab:&ParseSlab, cslab:&mut CompileSlab) -> Instruction {
+    let mut instrs = Vec::<Instruction>::with_capacity(xss.len());
+    for xs in xss { instrs.push(xs.compile(pslab,cslab)); }
+
+    // All operands are constant:  Fold the whole chain.
+    let mut consts = Vec::<f64>::with_capacity(instrs.len());
+    for instr in instrs.iter() {
+        if let IConst(c) = instr { consts.push(*c) }
+    }
+    if consts.len()==instrs.len() {
+        for (i,op) in ops.iter().enumerate() {
+            let res = op.binaryop_eval(consts.get(i), consts.get(i+1));
+            if f64_eq!(res,0.0) { return IConst(0.0); }
+        }
+        return IConst(1.0);
+    }
+
+    let mut instrs = instrs.into_iter();
+    let first = match instrs.next() {
+        Some(instr) => instr_to_ic!(cslab,instr),
+        None => return IConst(std::f64::NAN),  // unreachable
+    };
+    let mut rest = Vec::<(BinaryOp,IC)>::with_capacity(ops.len());
+    for (op,instr) in ops.iter().zip(instrs) {
+        rest.push((**op, instr_to_ic!(cslab,instr)));
+    }
+    ICmpChain(first, rest)
+}
+
+// Compiles the definition of a function into its own Instruction, and records it in the CompileSlab.
+// The Instruction is registered before the definition is compiled, so that recursive calls can refer to it.
+fn compile_user_func(func:usize, pslab:&ParseSlab, cslab:&mut CompileSlab) -> InstructionI {
+    let i = cslab.push_instr(IConst(std::f64::NAN));  // Placeholder.
+    if cslab.funcs.len()<=func { cslab.funcs.resize(func+1, None); }
+    if let Some(f) = cslab.funcs.get_mut(func) { *f = Some(i); }
+    let body = match pslab.funcs.get(func) {
+        Some(uf) => uf.body,
+        None => return i,  // unreachable
+    };
+    let instr = get_expr!(pslab,body).compile(pslab,cslab);
+    cslab.set_instr(i, instr);
+    i
+}
+
+// Stands in for an IValExpr that can't be located, so that evaluating it returns
+// `Error::Unreachable` rather than a NaN that looks like a real result.
+fn unreachable_instr() -> Instruction { IValExpr(ExpressionI(std::usize::MAX)) }
+
 impl Compiler for Expression {
     fn compile(&self, pslab:&ParseSlab, cslab:&mut CompileSlab) -> Instruction {
+        if self.needs_val() {
+            // Strings and arrays aren't supported by Instructions, so evaluate the parsed expression instead:
+            return match self.index {
+                Some(i) => IValExpr(i),
+                None => unreachable_instr(),  // `self` isn't stored in a slab.
+            };
+        }
         let top = ExprSlice::from_expr(&self);
         top.compile(pslab,cslab)
     }
@@ -659,9 +1082,33 @@ impl Compiler for Value {
     fn compile(&self, pslab:&ParseSlab, cslab:&mut CompileSlab) -> Instruction {
         match self {
             Value::EConstant(c) => IConst(*c),
+            Value::EString(_) | Value::EArray(_) => unreachable_instr(),  // Expressions with strings or arrays become IValExpr.
             Value::EUnaryOp(u) => u.compile(pslab,cslab),
             Value::EStdFunc(f) => f.compile(pslab,cslab),
             Value::EPrintFunc(pf) => IPrintFunc(pf.clone()),
+            Value::ETernary{cond, then, otherwise} => {
+                let cond = get_expr!(pslab,cond).compile(pslab,cslab);
+                if let IConst(c) = cond {
+                    // Constant condition: Only compile the branch that will be taken.
+                    if f64_eq!(c,0.0) { get_expr!(pslab,otherwise).compile(pslab,cslab) }
+                    else { get_expr!(pslab,then).compile(pslab,cslab) }
+                } else {
+                    let then = get_expr!(pslab,then).compile(pslab,cslab);
+                    let otherwise = get_expr!(pslab,otherwise).compile(pslab,cslab);
+                    ITernary{cond:cslab.push_instr(cond), then:instr_to_ic!(cslab,then), otherwise:instr_to_ic!(cslab,otherwise)}
+                }
+            }
+            Value::ELet{slot, value, body} => {
+                let value = get_expr!(pslab,value).compile(pslab,cslab);
+                let body = get_expr!(pslab,body).compile(pslab,cslab);
+                if let (IConst(_), IConst(_)) = (&value, &body) { return body; }
+                ILet{slot:*slot, value:instr_to_ic!(cslab,value), body:instr_to_ic!(cslab,body)}
+            }
+            Value::ELetFunc{func, body} => {
+                compile_user_func(*func,pslab,cslab);  // Always re-compile, in case the CompileSlab has old definitions.
+                let body = get_expr!(pslab,body).compile(pslab,cslab);
+                ILetFunc{func:*func, body:instr_to_ic!(cslab,body)}
+            }
         }
     }
 }
@@ -687,6 +1134,15 @@ impl Compiler for UnaryOp {
                 }
             }
             EParentheses(i) => get_expr!(pslab,i).compile(pslab,cslab),
+            #[cfg(feature="bitwise")]
+            EBitNot(i) => {
+                let instr = get_val!(pslab,i).compile(pslab,cslab);
+                if let IConst(c) = instr {
+                    IConst(!(c as i64) as f64)
+                } else {
+                    IBitNot(cslab.push_instr(instr))
+                }
+            }
         }
     }
 }
@@ -695,6 +1151,22 @@ impl Compiler for StdFunc {
     fn compile(&self, pslab:&ParseSlab, cslab:&mut CompileSlab) -> Instruction {
         match self {
             EVar(name) => IVar(name.clone()),
+            ELocal(slot) => match cslab.get_local_const(*slot) {
+                Some(c) => IConst(c),  // A sum() or prod() index that is being folded.
+                None => ILocal(*slot),
+            },
+            EUserFunc{func, args:xis} => {
+                let mut args = Vec::<IC>::with_capacity(xis.len());
+                for xi in xis {
+                    let instr = get_expr!(pslab,xi).compile(pslab,cslab);
+                    args.push(instr_to_ic!(cslab,instr));
+                }
+                let body = match cslab.funcs.get(*func) {
+                    Some(Some(i)) => *i,
+                    _ => compile_user_func(*func,pslab,cslab),
+                };
+                IUserFunc{func:*func, body, args}
+            }
             #[cfg(feature="unsafe-vars")]
             EUnsafeVar{name,ptr} => IUnsafeVar{name:name.clone(), ptr:*ptr},
             EFunc{name, args:xis} => {
@@ -705,6 +1177,31 @@ impl Compiler for StdFunc {
                 }
                 IFunc{name:name.clone(), args}
             }
+            EIndex{name, idx} => {
+                let instr = get_expr!(pslab,idx).compile(pslab,cslab);
+                IIndex{name:name.clone(), idx:instr_to_ic!(cslab,instr)}
+            }
+            ENativeFunc{func, args:xis} => {
+                let mut args = Vec::<IC>::with_capacity(xis.len());
+                let mut consts = Vec::<f64>::with_capacity(xis.len());
+                for xi in xis {
+                    let instr = get_expr!(pslab,xi).compile(pslab,cslab);
+                    if let IConst(c) = instr { consts.push(c); }
+                    args.push(instr_to_ic!(cslab,instr));
+                }
+                if func.pure && consts.len()==args.len() { IConst((func.func)(&consts)) }
+                else { INativeFunc{func:*func, args} }
+            }
+            ENamespaceFirst{name, args:xis, slot, builtin} => {
+                let mut args = Vec::<IC>::with_capacity(xis.len());
+                for xi in xis {
+                    let instr = get_expr!(pslab,xi).compile(pslab,cslab);
+                    args.push(instr_to_ic!(cslab,instr));
+                }
+                // The namespace can't be consulted at compile time, so this is never folded:
+                let builtin = get_expr!(pslab,builtin).compile(pslab,cslab);
+                INamespaceFirst{name:name.clone(), args, slot:*slot, builtin:cslab.push_instr(builtin)}
+            }
 
             EFuncInt(i) => {
                 let instr = get_expr!(pslab,i).compile(pslab,cslab);
@@ -809,10 +1306,27 @@ impl Compiler for StdFunc {
                         out = IConst(const_min);
                         // out_set = true;  // Comment out so the compiler doesn't complain about unused assignments.
                     }
+                } else if is.is_empty() {
+                    // A single argument might be an array, so keep the call to reduce it:
+                    out = IFuncMin(cslab.push_instr(out), IC::C(std::f64::INFINITY));
                 }
                 //assert!(out_set);
                 out
             }
+            EFuncStat{func, skip_nan, ns_first, first, rest} => {
+                let mut args = Vec::<IC>::with_capacity(rest.len()+1);
+                let mut consts = Stat::new(*func, *skip_nan);
+                let mut is_const = true;
+                for xi in std::iter::once(first).chain(rest.iter()) {
+                    let instr = get_expr!(pslab,xi).compile(pslab,cslab);
+                    if let IConst(c) = instr { consts.push(c); } else { is_const=false; }
+                    args.push(instr_to_ic!(cslab,instr));
+                }
+                // The namespace must be asked at runtime:
+                if is_const && ns_first.is_none() { IConst(consts.finish()) }
+                else { IFuncStat{func:*func, skip_nan:*skip_nan, ns_first:ns_first.clone(), args} }
+            }
+            EFuncStr{func, args} => IFuncStr{func:*func, args:args.clone()},
             EFuncMax{first:fi, rest:is} => {
                 let first = get_expr!(pslab,fi).compile(pslab,cslab);
                 let mut rest = Vec::<Instruction>::with_capacity(is.len());
@@ -850,11 +1364,42 @@ impl Compiler for StdFunc {
                         out = IConst(const_max);
                         // out_set = true;  // Comment out so the compiler doesn't complain about unused assignments.
                     }
+                } else if is.is_empty() {
+                    // A single argument might be an array, so keep the call to reduce it:
+                    out = IFuncMax(cslab.push_instr(out), IC::C(std::f64::NEG_INFINITY));
                 }
                 //assert!(out_set);
                 out
             }
 
+            EFuncIf{cond, then, otherwise} => {
+                let cond = get_expr!(pslab,cond).compile(pslab,cslab);
+                if let IConst(c) = cond {
+                    if f64_eq!(c,0.0) { get_expr!(pslab,otherwise).compile(pslab,cslab) }
+                    else { get_expr!(pslab,then).compile(pslab,cslab) }
+                } else {
+                    let then = get_expr!(pslab,then).compile(pslab,cslab);
+                    let otherwise = get_expr!(pslab,otherwise).compile(pslab,cslab);
+                    IFuncIf{cond:cslab.push_instr(cond), then:instr_to_ic!(cslab,then), otherwise:instr_to_ic!(cslab,otherwise)}
+                }
+            }
+
+            EFuncSum{slot, start, end, expr} | EFuncProd{slot, start, end, expr} => {
+                let is_sum = matches!(self, EFuncSum{..});
+                let start = get_expr!(pslab,start).compile(pslab,cslab);
+                let end = get_expr!(pslab,end).compile(pslab,cslab);
+                if let (IConst(s), IConst(e)) = (&start, &end) {
+                    let n = series_len(*s,*e);
+                    if n<=pslab.iter_limit as f64 {
+                        if let Some(c) = fold_series(is_sum, *slot, *s, n, *expr, pslab, cslab) { return IConst(c); }
+                    }
+                }
+                let expr = get_expr!(pslab,expr).compile(pslab,cslab);
+                let (slot, start, end, expr) = (*slot, instr_to_ic!(cslab,start), instr_to_ic!(cslab,end), instr_to_ic!(cslab,expr));
+                if is_sum { IFuncSum{slot, start, end, expr} }
+                else { IFuncProd{slot, start, end, expr} }
+            }
+
             EFuncE => IConst(std::f64::consts::E),
             EFuncPi => IConst(std::f64::consts::PI),
 
@@ -882,6 +1427,30 @@ impl Compiler for StdFunc {
                     IFuncTan(cslab.push_instr(instr))
                 }
             }
+            EFuncSinD(i) => {
+                let instr = get_expr!(pslab,i).compile(pslab,cslab);
+                if let IConst(c) = instr {
+                    IConst(sind(c))
+                } else {
+                    IFuncSinD(cslab.push_instr(instr))
+                }
+            }
+            EFuncCosD(i) => {
+                let instr = get_expr!(pslab,i).compile(pslab,cslab);
+                if let IConst(c) = instr {
+                    IConst(cosd(c))
+                } else {
+                    IFuncCosD(cslab.push_instr(instr))
+                }
+            }
+            EFuncTanD(i) => {
+                let instr = get_expr!(pslab,i).compile(pslab,cslab);
+                if let IConst(c) = instr {
+                    IConst(tand(c))
+                } else {
+                    IFuncTanD(cslab.push_instr(instr))
+                }
+            }
             EFuncASin(i) => {
                 let instr = get_expr!(pslab,i).compile(pslab,cslab);
                 if let IConst(c) = instr {
@@ -954,6 +1523,142 @@ impl Compiler for StdFunc {
                     IFuncATanH(cslab.push_instr(instr))
                 }
             }
+
+            EFuncSqrt(i) => {
+                let instr = get_expr!(pslab,i).compile(pslab,cslab);
+                if let IConst(c) = instr {
+                    IConst(c.sqrt())
+                } else {
+                    IFuncSqrt(cslab.push_instr(instr))
+                }
+            }
+            EFuncCbrt(i) => {
+                let instr = get_expr!(pslab,i).compile(pslab,cslab);
+                if let IConst(c) = instr {
+                    IConst(c.cbrt())
+                } else {
+                    IFuncCbrt(cslab.push_instr(instr))
+                }
+            }
+            EFuncExp(i) => {
+                let instr = get_expr!(pslab,i).compile(pslab,cslab);
+                if let IConst(c) = instr {
+                    IConst(c.exp())
+                } else {
+                    IFuncExp(cslab.push_instr(instr))
+                }
+            }
+            EFuncLn(i) => {
+                let instr = get_expr!(pslab,i).compile(pslab,cslab);
+                if let IConst(c) = instr {
+                    IConst(c.ln())
+                } else {
+                    IFuncLn(cslab.push_instr(instr))
+                }
+            }
+            EFuncLog2(i) => {
+                let instr = get_expr!(pslab,i).compile(pslab,cslab);
+                if let IConst(c) = instr {
+                    IConst(c.log2())
+                } else {
+                    IFuncLog2(cslab.push_instr(instr))
+                }
+            }
+            EFuncLog10(i) => {
+                let instr = get_expr!(pslab,i).compile(pslab,cslab);
+                if let IConst(c) = instr {
+                    IConst(c.log10())
+                } else {
+                    IFuncLog10(cslab.push_instr(instr))
+                }
+            }
+            EFuncTrunc(i) => {
+                let instr = get_expr!(pslab,i).compile(pslab,cslab);
+                if let IConst(c) = instr {
+                    IConst(c.trunc())
+                } else {
+                    IFuncTrunc(cslab.push_instr(instr))
+                }
+            }
+            EFuncFrac(i) => {
+                let instr = get_expr!(pslab,i).compile(pslab,cslab);
+                if let IConst(c) = instr {
+                    IConst(c.fract())
+                } else {
+                    IFuncFrac(cslab.push_instr(instr))
+                }
+            }
+            EFuncDeg(i) => {
+                let instr = get_expr!(pslab,i).compile(pslab,cslab);
+                if let IConst(c) = instr {
+                    IConst(c.to_degrees())
+                } else {
+                    IFuncDeg(cslab.push_instr(instr))
+                }
+            }
+            EFuncRad(i) => {
+                let instr = get_expr!(pslab,i).compile(pslab,cslab);
+                if let IConst(c) = instr {
+                    IConst(c.to_radians())
+                } else {
+                    IFuncRad(cslab.push_instr(instr))
+                }
+            }
+            EFuncErf(i) => {
+                let instr = get_expr!(pslab,i).compile(pslab,cslab);
+                if let IConst(c) = instr {
+                    IConst(erf(c))
+                } else {
+                    IFuncErf(cslab.push_instr(instr))
+                }
+            }
+            EFuncGamma(i) => {
+                let instr = get_expr!(pslab,i).compile(pslab,cslab);
+                if let IConst(c) = instr {
+                    IConst(gamma(c))
+                } else {
+                    IFuncGamma(cslab.push_instr(instr))
+                }
+            }
+            EFuncLGamma(i) => {
+                let instr = get_expr!(pslab,i).compile(pslab,cslab);
+                if let IConst(c) = instr {
+                    IConst(lgamma(c))
+                } else {
+                    IFuncLGamma(cslab.push_instr(instr))
+                }
+            }
+            EFuncATan2(ai, bi) => {
+                let a = get_expr!(pslab,ai).compile(pslab,cslab);
+                let b = get_expr!(pslab,bi).compile(pslab,cslab);
+                if let (IConst(a), IConst(b)) = (&a, &b) { return IConst(a.atan2(*b)); }
+                IFuncATan2(instr_to_ic!(cslab,a), instr_to_ic!(cslab,b))
+            }
+            EFuncHypot(ai, bi) => {
+                let a = get_expr!(pslab,ai).compile(pslab,cslab);
+                let b = get_expr!(pslab,bi).compile(pslab,cslab);
+                if let (IConst(a), IConst(b)) = (&a, &b) { return IConst(a.hypot(*b)); }
+                IFuncHypot(instr_to_ic!(cslab,a), instr_to_ic!(cslab,b))
+            }
+            EFuncPow(ai, bi) => {
+                let a = get_expr!(pslab,ai).compile(pslab,cslab);
+                let b = get_expr!(pslab,bi).compile(pslab,cslab);
+                if let (IConst(a), IConst(b)) = (&a, &b) { return IConst(a.powf(*b)); }
+                IFuncPow(instr_to_ic!(cslab,a), instr_to_ic!(cslab,b))
+            }
+            EFuncFMod(ai, bi) => {
+                let a = get_expr!(pslab,ai).compile(pslab,cslab);
+                let b = get_expr!(pslab,bi).compile(pslab,cslab);
+                if let (IConst(a), IConst(b)) = (&a, &b) { return IConst(*a%*b); }
+                IFuncFMod(instr_to_ic!(cslab,a), instr_to_ic!(cslab,b))
+            }
+            EFuncClamp{x:xi, min:mini, max:maxi} => {
+                let x = get_expr!(pslab,xi).compile(pslab,cslab);
+                let min = get_expr!(pslab,mini).compile(pslab,cslab);
+                let max = get_expr!(pslab,maxi).compile(pslab,cslab);
+                if let (IConst(x), IConst(min), IConst(max)) = (&x, &min, &max) { return IConst(clamp(*x,*min,*max)); }
+                IFuncClamp{x:instr_to_ic!(cslab,x), min:instr_to_ic!(cslab,min), max:instr_to_ic!(cslab,max)}
+            }
         }
     }
 }
@@ -2,6 +2,7 @@
 //! that can be produced by the `fasteval` API.
 
 use std::fmt;
+use std::ops::Range;
 
 /// This is the error type used in `fasteval`'s `Result`s.
 ///
@@ -73,6 +74,22 @@ pub enum Error {
     /// The `String` field contains information about the expected arguments.
     WrongArgs(String),
 
+    /// A string was used where a number was expected, or the other way around.
+    ///
+    /// The `String` field describes what was expected.
+    TypeMismatch(String),
+
+    /// A function defined within the expression (like `f(x) = ...;`) recursed
+    /// more deeply than `Parser.call_depth_limit` during evaluation.
+    ///
+    /// The `String` field contains the name of the function.
+    RecursionLimit(String),
+
+    /// A `sum()` or `prod()` range had more than `Parser.iter_limit` items.
+    ///
+    /// The `String` field contains the name of the function.
+    IterationLimit(String),
+
     /// The expression tried to use an undefined variable/function.
     ///
     /// You can define variables/functions with a Namespace.
@@ -82,15 +99,120 @@ pub enum Error {
     /// that should never execute.  This is more performant than using the
     /// `unreachable!()` macro.
     Unreachable,
+
+    /// An error that occurred while parsing, along with its location.
+    ///
+    /// `Parser::parse()` wraps every error it returns in this variant.
+    /// `start` and `end` are byte offsets into the original expression
+    /// string, so `&expr_str[start..end]` is the offending input.  (For
+    /// errors like `EofWhileParsing`, the span is empty: `start==end`.)
+    ///
+    /// Use [`span()`](#method.span) and [`unspanned()`](#method.unspanned)
+    /// if you don't want to match on this variant directly.
+    Spanned{err:Box<Error>, start:usize, end:usize},
+}
+
+impl Error {
+    /// Returns the byte range of the expression string where this error
+    /// occurred, or `None` if the location is unknown (for example, errors
+    /// that occur during `eval()`).
+    pub fn span(&self) -> Option<Range<usize>> {
+        match self {
+            Error::Spanned{start, end, ..} => Some(*start..*end),
+            _ => None,
+        }
+    }
+
+    /// Returns the underlying error, without its location information.
+    pub fn unspanned(&self) -> &Error {
+        match self {
+            Error::Spanned{err, ..} => err.unspanned(),
+            _ => self,
+        }
+    }
+
+    /// Renders a multi-line, human-readable diagnostic for this error.
+    ///
+    /// `expr_str` must be the same string that was given to `Parser::parse()`.
+    /// If the error has a span, the offending line of `expr_str` is shown
+    /// with carets under the failing position:
+    ///
+    /// ```
+    /// use fasteval::{Parser, Slab};
+    ///
+    /// let expr_str = "(1 x";
+    /// let err = Parser::new().parse(expr_str, &mut Slab::new().ps).unwrap_err();
+    /// assert_eq!(err.render(expr_str),
+    /// "error: expected ')'
+    ///  --> 1:4
+    ///   |
+    /// 1 | (1 x
+    ///   |    ^");
+    /// ```
+    ///
+    /// Errors without a span (such as errors from `eval()`) only produce the
+    /// first line.
+    pub fn render(&self, expr_str:&str) -> String {
+        let span = match self.span() {
+            Some(span) => span,
+            None => return format!("error: {}", self.unspanned()),
+        };
+
+        let mut start = span.start.min(expr_str.len());
+        while !expr_str.is_char_boundary(start) { start-=1; }
+        let line_start = expr_str[..start].rfind('\n').map_or(0, |i| i+1);
+        let line_end = expr_str[start..].find('\n').map_or(expr_str.len(), |i| start+i);
+        let mut end = span.end.max(start).min(line_end);
+        while !expr_str.is_char_boundary(end) { end-=1; }
+
+        let line = &expr_str[line_start..line_end];
+        let line_num = expr_str[..line_start].matches('\n').count()+1;
+        let col = expr_str[line_start..start].chars().count()+1;
+        let gutter = " ".repeat(line_num.to_string().len());
+
+        // Keep tabs so that the carets line up with the source line:
+        let pad : String = expr_str[line_start..start].chars().map(|c| if c=='\t' {'\t'} else {' '}).collect();
+        let carets = "^".repeat(expr_str[start..end].chars().count().max(1));
+
+        format!("error: {}\n{} --> {}:{}\n{} |\n{} | {}\n{} | {}{}",
+                self.unspanned(), &gutter[1..], line_num, col, gutter, line_num, line, gutter, pad, carets)
+    }
 }
 
 impl std::error::Error for Error {
-    // The defaults are fine for now.
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Spanned{err, ..} => Some(err.as_ref()),
+            _ => None,
+        }
+    }
 }
 
 impl fmt::Display for Error {
     fn fmt(&self, f:&mut fmt::Formatter) -> Result<(), fmt::Error> {
-        write!(f, "{:?}", self)  // Re-use Debug for now...
+        match self {
+            Error::SlabOverflow => write!(f, "too many items for the Slab's capacity (use Slab::with_capacity() to make a bigger one)"),
+            Error::AlreadyExists => write!(f, "an entry with the same name already exists in the Namespace"),
+            Error::EOF => write!(f, "unexpected end of input"),
+            Error::EofWhileParsing(s) => write!(f, "unexpected end of input while parsing {}", s),
+            Error::Utf8ErrorWhileParsing(s) => write!(f, "invalid UTF-8 while parsing {}", s),
+            Error::TooLong => write!(f, "the expression is too long"),
+            Error::TooDeep => write!(f, "the expression is nested too deeply"),
+            Error::UnparsedTokensRemaining(s) => write!(f, "unexpected input after the end of the expression: {:?}", s),
+            Error::InvalidValue => write!(f, "expected a value (a number, string, variable, function call, or parenthesized expression)"),
+            Error::ParseF64(s) => write!(f, "invalid number: {:?}", s),
+            Error::Expected(s) => {
+                if s.contains('\'') { write!(f, "expected {}", s) }
+                else { write!(f, "expected '{}'", s) }
+            }
+            Error::WrongArgs(s) => write!(f, "wrong arguments: {}", s),
+            Error::TypeMismatch(s) => write!(f, "type mismatch: {}", s),
+            Error::RecursionLimit(s) => write!(f, "too much recursion in function: {}", s),
+            Error::IterationLimit(s) => write!(f, "too many iterations in function: {}", s),
+            Error::Undefined(s) => write!(f, "undefined variable or function: {}", s),
+            Error::Unreachable => write!(f, "internal error: reached a code path that should never execute"),
+            Error::Spanned{err, start, ..} => write!(f, "{} at byte {}", err, start),
+        }
     }
 }
 
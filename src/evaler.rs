@@ -11,23 +11,29 @@ use crate as fasteval;
 use crate::error::Error;
 use crate::slab::Slab;
 use crate::evalns::EvalNamespace;
-use crate::parser::{Expression,
-                    Value::{self, EConstant, EUnaryOp, EStdFunc, EPrintFunc},
+use crate::printf::{sprintf, Arg};
+use crate::parser::{ExpressionI, Expression,
+                    Value::{self, EConstant, EString, EArray, EUnaryOp, EStdFunc, EPrintFunc, ETernary, ELet, ELetFunc},
                     UnaryOp::{self, EPos, ENeg, ENot, EParentheses},
                     BinaryOp::{self, EAdd, ESub, EMul, EDiv, EMod, EExp, ELT, ELTE, EEQ, ENE, EGTE, EGT, EOR, EAND},
-                    StdFunc::{self, EVar, EFunc, EFuncInt, EFuncCeil, EFuncFloor, EFuncAbs, EFuncSign, EFuncLog, EFuncRound, EFuncMin, EFuncMax, EFuncE, EFuncPi, EFuncSin, EFuncCos, EFuncTan, EFuncASin, EFuncACos, EFuncATan, EFuncSinH, EFuncCosH, EFuncTanH, EFuncASinH, EFuncACosH, EFuncATanH},
-                    PrintFunc,
+                    StdFunc::{self, EVar, ELocal, EFunc, EIndex, EUserFunc, ENamespaceFirst, ENativeFunc, EFuncInt, EFuncCeil, EFuncFloor, EFuncAbs, EFuncSign, EFuncLog, EFuncRound, EFuncMin, EFuncMax, EFuncStat, EFuncIf, EFuncSum, EFuncProd, EFuncE, EFuncPi, EFuncSin, EFuncCos, EFuncTan, EFuncSinD, EFuncCosD, EFuncTanD, EFuncASin, EFuncACos, EFuncATan, EFuncSinH, EFuncCosH, EFuncTanH, EFuncASinH, EFuncACosH, EFuncATanH, EFuncSqrt, EFuncCbrt, EFuncExp, EFuncLn, EFuncLog2, EFuncLog10, EFuncTrunc, EFuncFrac, EFuncDeg, EFuncRad, EFuncErf, EFuncGamma, EFuncLGamma, EFuncATan2, EFuncHypot, EFuncPow, EFuncFMod, EFuncClamp, EFuncStr},
+                    StrFunc, PrintFunc,
                     ExpressionOrString::{EExpr, EStr},
                     remove_no_panic};
 #[cfg(feature="unsafe-vars")]
 use crate::parser::StdFunc::EUnsafeVar;
-use crate::compiler::{log, IC, Instruction::{self, IConst, INeg, INot, IInv, IAdd, IMul, IMod, IExp, ILT, ILTE, IEQ, INE, IGTE, IGT, IOR, IAND, IVar, IFunc, IFuncInt, IFuncCeil, IFuncFloor, IFuncAbs, IFuncSign, IFuncLog, IFuncRound, IFuncMin, IFuncMax, IFuncSin, IFuncCos, IFuncTan, IFuncASin, IFuncACos, IFuncATan, IFuncSinH, IFuncCosH, IFuncTanH, IFuncASinH, IFuncACosH, IFuncATanH, IPrintFunc}};
+use crate::compiler::{log, sind, cosd, tand, erf, gamma, lgamma, clamp, Stat, series_len, IC, Instruction::{self, IConst, INeg, INot, IInv, IAdd, IMul, IMod, IExp, ILT, ILTE, IEQ, INE, IGTE, IGT, ICmpChain, IOR, IAND, ITernary, ILocal, ILet, ILetFunc, IUserFunc, INamespaceFirst, INativeFunc, IVar, IFunc, IIndex, IFuncInt, IFuncCeil, IFuncFloor, IFuncAbs, IFuncSign, IFuncLog, IFuncRound, IFuncMin, IFuncMax, IFuncStat, IFuncIf, IFuncSum, IFuncProd, IFuncSin, IFuncCos, IFuncTan, IFuncSinD, IFuncCosD, IFuncTanD, IFuncASin, IFuncACos, IFuncATan, IFuncSinH, IFuncCosH, IFuncTanH, IFuncASinH, IFuncACosH, IFuncATanH, IFuncSqrt, IFuncCbrt, IFuncExp, IFuncLn, IFuncLog2, IFuncLog10, IFuncTrunc, IFuncFrac, IFuncDeg, IFuncRad, IFuncErf, IFuncGamma, IFuncLGamma, IFuncATan2, IFuncHypot, IFuncPow, IFuncFMod, IFuncClamp, IFuncStr, IValExpr, IPrintFunc}};
 #[cfg(feature="unsafe-vars")]
 use crate::compiler::Instruction::IUnsafeVar;
+#[cfg(feature="bitwise")]
+use crate::parser::{UnaryOp::EBitNot, BinaryOp::{EBitOr, EBitXor, EBitAnd, EShr, EShl, EIntDiv}};
+#[cfg(feature="bitwise")]
+use crate::compiler::{bitwise, Instruction::{IBitNot, IBitAnd, IBitOr, IBitXor, IShl, IShr, IIntDiv}};
 
 use std::collections::BTreeSet;
 use std::f64::consts;
 use std::fmt;
+use std::ops::{Deref, DerefMut};
 
 
 
@@ -110,7 +116,7 @@ macro_rules! eval_compiled_ref {
 }
 
 macro_rules! eval_ic_ref {
-    ($ic:ident, $slab_ref:ident, $ns_mut:expr) => {
+    ($ic:ident, $slab_ref:ident, $ns_mut:expr, $locals:expr) => {
         match $ic {
             IC::C(c) => *c,
             IC::I(i) => {
@@ -121,25 +127,129 @@ macro_rules! eval_ic_ref {
                     if let fasteval::IUnsafeVar{ptr, ..} = instr_ref {
                         unsafe { **ptr }
                     } else {
-                        instr_ref.eval($slab_ref, $ns_mut)?
+                        instr_ref._eval($slab_ref, $ns_mut, $locals)?
                     }
                 }
 
                 #[cfg(not(feature="unsafe-vars"))]
-                instr_ref.eval($slab_ref, $ns_mut)?
+                instr_ref._eval($slab_ref, $ns_mut, $locals)?
             }
         }
     }
 }
 
+// Like eval_compiled_ref!(), but continues an evaluation that is already in progress:
+macro_rules! eval_instr_ref {
+    ($evaler:expr, $slab_ref:expr, $ns_mut:expr, $locals:expr) => {
+        {
+            let evaler = $evaler;
+            if let fasteval::IConst(c) = evaler {
+                *c
+            } else {
+                #[cfg(feature="unsafe-vars")]
+                {
+                    if let fasteval::IUnsafeVar{ptr, ..} = evaler {
+                        unsafe { **ptr }
+                    } else {
+                        evaler._eval($slab_ref, $ns_mut, $locals)?
+                    }
+                }
+
+                #[cfg(not(feature="unsafe-vars"))]
+                evaler._eval($slab_ref, $ns_mut, $locals)?
+            }
+        }
+    };
+}
+
+
+
+/// A value that can be a number, a string, or an array of numbers.
+///
+/// Numbers are the common case, and `eval()` returns them directly.
+/// Use `eval_val()` when an expression might produce a string or an array.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Val {
+    Num(f64),
+    Str(String),
+    Arr(Vec<f64>),
+}
 
+impl Val {
+    /// Returns the number, or `Error::TypeMismatch` for a string or an array.
+    pub fn num(&self) -> Result<f64,Error> {
+        match self {
+            Val::Num(f) => Ok(*f),
+            _ => Err(Error::TypeMismatch(format!("expected a number, not {}", self.kind()))),
+        }
+    }
+
+    // For error messages:
+    pub(crate) fn kind(&self) -> &'static str {
+        match self {
+            Val::Num(_) => "a number",
+            Val::Str(_) => "a string",
+            Val::Arr(_) => "an array",
+        }
+    }
+}
+
+impl fmt::Display for Val {
+    fn fmt(&self, f:&mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Val::Num(n) => write!(f, "{}", n),
+            Val::Str(s) => write!(f, "{}", s),
+            Val::Arr(a) => {
+                write!(f, "[")?;
+                for (i,n) in a.iter().enumerate() {
+                    if i>0 { write!(f, ", ")?; }
+                    write!(f, "{}", n)?;
+                }
+                write!(f, "]")
+            }
+        }
+    }
+}
+
+impl From<f64> for Val {
+    fn from(f:f64) -> Self { Val::Num(f) }
+}
+impl From<&str> for Val {
+    fn from(s:&str) -> Self { Val::Str(s.to_string()) }
+}
+impl From<String> for Val {
+    fn from(s:String) -> Self { Val::Str(s) }
+}
+impl From<Vec<f64>> for Val {
+    fn from(a:Vec<f64>) -> Self { Val::Arr(a) }
+}
 
 /// You must `use` this trait so you can call `.eval()`.
 pub trait Evaler : fmt::Debug {
     /// Evaluate this `Expression`/`Instruction` and return an `f64`.
     ///
     /// Returns a `fasteval::Error` if there are any problems, such as undefined variables.
-    fn eval(&self, slab:&Slab, ns:&mut impl EvalNamespace) -> Result<f64,Error>;
+    fn eval(&self, slab:&Slab, ns:&mut impl EvalNamespace) -> Result<f64,Error> {
+        self._eval(slab, ns, &mut Locals::default())
+    }
+
+    /// Evaluate this `Expression`/`Instruction` and return a `Val`, which might be a string or an array.
+    ///
+    /// Unlike `eval()`, this also looks up string and array variables with `EvalNamespace::lookup_val()`.
+    fn eval_val(&self, slab:&Slab, ns:&mut impl EvalNamespace) -> Result<Val,Error> {
+        self._eval_val(slab, ns, &mut Locals::default())
+    }
+
+    /// Don't call this directly.  Use `eval()` instead.
+    ///
+    /// This continues an evaluation that is already in progress, so that
+    /// nested values can see the local variables that are bound around them.
+    fn _eval(&self, slab:&Slab, ns:&mut impl EvalNamespace, locals:&mut Locals) -> Result<f64,Error>;
+
+    /// Don't call this directly.  Use `eval_val()` instead.
+    fn _eval_val(&self, slab:&Slab, ns:&mut impl EvalNamespace, locals:&mut Locals) -> Result<Val,Error> {
+        Ok(Val::Num(self._eval(slab,ns,locals)?))
+    }
 
     /// Don't call this directly.  Use `var_names()` instead.
     ///
@@ -163,7 +273,10 @@ impl Evaler for Expression {
             pair.1._var_names(slab,dst);
         }
     }
-    fn eval(&self, slab:&Slab, ns:&mut impl EvalNamespace) -> Result<f64,Error> {
+    fn _eval(&self, slab:&Slab, ns:&mut impl EvalNamespace, locals:&mut Locals) -> Result<f64,Error> {
+        // Expressions that might contain strings or arrays take the slower path:
+        if self.needs_val() { return self._eval_val(slab,ns,locals)?.num(); }
+
         // Order of operations: 1) ^  2) */  3) +-
         // Exponentiation should be processed right-to-left.  Think of what 2^3^4 should mean:
         //     2^(3^4)=2417851639229258349412352   <--- I choose this one.  https://codeplea.com/exponentiation-associativity-options
@@ -203,10 +316,10 @@ impl Evaler for Expression {
         // Code for new Expression data structure:
         let mut vals = Vec::<f64>::with_capacity(self.pairs.len()+1);
         let mut ops  = Vec::<BinaryOp>::with_capacity(self.pairs.len());
-        vals.push(self.first.eval(slab,ns)?);
+        vals.push(self.first._eval(slab,ns,locals)?);
         for pair in self.pairs.iter() {
             ops.push(pair.0);
-            vals.push(pair.1.eval(slab,ns)?);
+            vals.push(pair.1._eval(slab,ns,locals)?);
         }
 
 
@@ -285,14 +398,59 @@ impl Evaler for Expression {
             }
         }
 
+        // Like ltor_multi(), but each run of adjacent ops is treated as a chain:  a<b<c means a<b && b<c.
+        #[inline(always)]
+        fn chain_multi(vals:&mut Vec<f64>, ops:&mut Vec<BinaryOp>, search:&[BinaryOp]) {
+            let mut i = 0;
+            loop {
+                match ops.get(i) {
+                    None => break,
+                    Some(op) => {
+                        if search.contains(op) {
+                            let mut res = op.binaryop_eval(vals.get(i), vals.get(i+1));
+                            // Fold the rest of the chain into this result:
+                            while let Some(next) = ops.get(i+1) {
+                                if !search.contains(next) { break }
+                                if f64_eq!(next.binaryop_eval(vals.get(i+1), vals.get(i+2)),0.0) { res=0.0; }
+                                remove_no_panic(vals, i+1);
+                                remove_no_panic(ops, i+1);
+                            }
+                            match vals.get_mut(i) {
+                                Some(val_ref) => *val_ref=res,
+                                None => (),  // unreachable
+                            };
+                            remove_no_panic(vals, i+1);
+                            remove_no_panic(ops, i);
+                        } else {
+                            i+=1;
+                        }
+                    }
+                }
+            }
+        }
+
         // Keep the order of these statements in-sync with parser.rs BinaryOp priority values:
         rtol(&mut vals, &mut ops, EExp);  // https://codeplea.com/exponentiation-associativity-options
         ltor(&mut vals, &mut ops, EMod);
+        #[cfg(feature="bitwise")]
+        ltor(&mut vals, &mut ops, EIntDiv);
         ltor(&mut vals, &mut ops, EDiv);
         rtol(&mut vals, &mut ops, EMul);
         ltor(&mut vals, &mut ops, ESub);
         rtol(&mut vals, &mut ops, EAdd);
-        ltor_multi(&mut vals, &mut ops, &[ELT, EGT, ELTE, EGTE, EEQ, ENE]);  // TODO: Implement Python-style a<b<c ternary comparison... might as well generalize to N comparisons.
+        #[cfg(feature="bitwise")]
+        ltor_multi(&mut vals, &mut ops, &[EShl, EShr]);
+        if self.chain_cmp() {
+            chain_multi(&mut vals, &mut ops, &[ELT, EGT, ELTE, EGTE, EEQ, ENE]);
+        } else {
+            ltor_multi(&mut vals, &mut ops, &[ELT, EGT, ELTE, EGTE, EEQ, ENE]);
+        }
+        #[cfg(feature="bitwise")]
+        {
+            ltor(&mut vals, &mut ops, EBitAnd);
+            ltor(&mut vals, &mut ops, EBitXor);
+            ltor(&mut vals, &mut ops, EBitOr);
+        }
         ltor(&mut vals, &mut ops, EAND);
         ltor(&mut vals, &mut ops, EOR);
 
@@ -303,23 +461,156 @@ impl Evaler for Expression {
             None => Err(Error::Unreachable),
         }
     }
+    fn _eval_val(&self, slab:&Slab, ns:&mut impl EvalNamespace, locals:&mut Locals) -> Result<Val,Error> {
+        if self.pairs.is_empty() { return self.first._eval_val(slab,ns,locals); }
+
+        let mut vals = Vec::<Val>::with_capacity(self.pairs.len()+1);
+        let mut ops  = Vec::<BinaryOp>::with_capacity(self.pairs.len());
+        vals.push(self.first._eval_val(slab,ns,locals)?);
+        for pair in self.pairs.iter() {
+            ops.push(pair.0);
+            vals.push(pair.1._eval_val(slab,ns,locals)?);
+        }
+
+        // Like the passes in eval(), but for any kind of value.  With strings, addition
+        // is processed left-to-right so that "a"+1+2 is "a12", like other languages.
+        fn pass(vals:&mut Vec<Val>, ops:&mut Vec<BinaryOp>, search:&[BinaryOp], rtol:bool, chain:bool) -> Result<(),Error> {
+            let mut i = if rtol { ops.len() } else { 0 };
+            loop {
+                if rtol {
+                    if i==0 { break }
+                    i-=1;
+                }
+                let op = match ops.get(i) {
+                    Some(op) => *op,
+                    None => break,
+                };
+                if !search.contains(&op) {
+                    if !rtol { i+=1; }
+                    continue;
+                }
+                let mut res = op.binaryop_eval_val(vals.get(i), vals.get(i+1))?;
+                if chain {
+                    // Fold the rest of the chain into this result:  a<b<c means a<b && b<c.
+                    while let Some(next) = ops.get(i+1) {
+                        if !search.contains(next) { break }
+                        if next.binaryop_eval_val(vals.get(i+1), vals.get(i+2))? == Val::Num(0.0) { res=Val::Num(0.0); }
+                        remove_no_panic(vals, i+1);
+                        remove_no_panic(ops, i+1);
+                    }
+                }
+                if let Some(val_ref) = vals.get_mut(i) { *val_ref=res; }
+                remove_no_panic(vals, i+1);
+                remove_no_panic(ops, i);
+            }
+            Ok(())
+        }
+
+        // Keep the order of these statements in-sync with eval():
+        pass(&mut vals, &mut ops, &[EExp], true, false)?;
+        pass(&mut vals, &mut ops, &[EMod], false, false)?;
+        #[cfg(feature="bitwise")]
+        pass(&mut vals, &mut ops, &[EIntDiv], false, false)?;
+        pass(&mut vals, &mut ops, &[EDiv], false, false)?;
+        pass(&mut vals, &mut ops, &[EMul], true, false)?;
+        pass(&mut vals, &mut ops, &[ESub], false, false)?;
+        pass(&mut vals, &mut ops, &[EAdd], !self.strings(), false)?;
+        #[cfg(feature="bitwise")]
+        pass(&mut vals, &mut ops, &[EShl, EShr], false, false)?;
+        pass(&mut vals, &mut ops, &[ELT, EGT, ELTE, EGTE, EEQ, ENE], false, self.chain_cmp())?;
+        #[cfg(feature="bitwise")]
+        {
+            pass(&mut vals, &mut ops, &[EBitAnd], false, false)?;
+            pass(&mut vals, &mut ops, &[EBitXor], false, false)?;
+            pass(&mut vals, &mut ops, &[EBitOr], false, false)?;
+        }
+        pass(&mut vals, &mut ops, &[EAND], false, false)?;
+        pass(&mut vals, &mut ops, &[EOR], false, false)?;
+
+        if !ops.is_empty() || vals.len()!=1 { return Err(Error::Unreachable); }
+        vals.pop().ok_or(Error::Unreachable)
+    }
 }
 
 impl Evaler for Value {
     fn _var_names(&self, slab:&Slab, dst:&mut BTreeSet<String>) {
         match self {
-            EConstant(_) => (),
+            EConstant(_) | EString(_) => (),
+            EArray(xis) => {
+                for xi in xis {
+                    get_expr!(slab.ps,xi)._var_names(slab,dst);
+                }
+            }
             EUnaryOp(u) => u._var_names(slab,dst),
             EStdFunc(f) => f._var_names(slab,dst),
             EPrintFunc(f) => f._var_names(slab,dst),
+            ETernary{cond, then, otherwise} => {
+                get_expr!(slab.ps,cond)._var_names(slab,dst);
+                get_expr!(slab.ps,then)._var_names(slab,dst);
+                get_expr!(slab.ps,otherwise)._var_names(slab,dst);
+            }
+            ELet{value, body, ..} => {
+                get_expr!(slab.ps,value)._var_names(slab,dst);
+                get_expr!(slab.ps,body)._var_names(slab,dst);
+            }
+            ELetFunc{func, body} => {
+                // The definition is visited here (rather than at each call) so that recursion doesn't loop forever:
+                if let Some(uf) = slab.ps.funcs.get(*func) {
+                    let def = uf.body;
+                    get_expr!(slab.ps,def)._var_names(slab,dst);
+                }
+                get_expr!(slab.ps,body)._var_names(slab,dst);
+            }
         };
     }
-    fn eval(&self, slab:&Slab, ns:&mut impl EvalNamespace) -> Result<f64,Error> {
+    fn _eval(&self, slab:&Slab, ns:&mut impl EvalNamespace, locals:&mut Locals) -> Result<f64,Error> {
         match self {
             EConstant(c) => Ok(*c),
-            EUnaryOp(u) => u.eval(slab,ns),
-            EStdFunc(f) => f.eval(slab,ns),
-            EPrintFunc(f) => f.eval(slab,ns),
+            EString(_) => Err(Error::TypeMismatch("expected a number, not a string".to_string())),
+            EArray(_) => Err(Error::TypeMismatch("expected a number, not an array".to_string())),
+            EUnaryOp(u) => u._eval(slab,ns,locals),
+            EStdFunc(f) => f._eval(slab,ns,locals),
+            EPrintFunc(f) => f._eval(slab,ns,locals),
+            ETernary{cond, then, otherwise} => {
+                let c = get_expr!(slab.ps,cond)._eval(slab,ns,locals)?;
+                if f64_eq!(c,0.0) { get_expr!(slab.ps,otherwise)._eval(slab,ns,locals) }
+                else { get_expr!(slab.ps,then)._eval(slab,ns,locals) }
+            }
+            ELet{slot, value, body} => {
+                let val = get_expr!(slab.ps,value)._eval(slab,ns,locals)?;
+                with_local(locals, *slot, val, |locals| get_expr!(slab.ps,body)._eval(slab,ns,locals))
+            }
+            ELetFunc{body, ..} => get_expr!(slab.ps,body)._eval(slab,ns,locals),
+        }
+    }
+    fn _eval_val(&self, slab:&Slab, ns:&mut impl EvalNamespace, locals:&mut Locals) -> Result<Val,Error> {
+        match self {
+            EString(s) => Ok(Val::Str(s.clone())),
+            EArray(xis) => {
+                // Arrays within arrays are flattened:
+                let mut out = Vec::with_capacity(xis.len());
+                for xi in xis {
+                    match get_expr!(slab.ps,xi)._eval_val(slab,ns,locals)? {
+                        Val::Num(f) => out.push(f),
+                        Val::Arr(a) => out.extend(a),
+                        Val::Str(_) => return Err(Error::TypeMismatch("arrays can only hold numbers".to_string())),
+                    }
+                }
+                Ok(Val::Arr(out))
+            }
+            EUnaryOp(u) => u._eval_val(slab,ns,locals),
+            EStdFunc(f) => f._eval_val(slab,ns,locals),
+            ETernary{cond, then, otherwise} => {
+                let c = get_expr!(slab.ps,cond)._eval_val(slab,ns,locals)?.num()?;
+                if f64_eq!(c,0.0) { get_expr!(slab.ps,otherwise)._eval_val(slab,ns,locals) }
+                else { get_expr!(slab.ps,then)._eval_val(slab,ns,locals) }
+            }
+            ELet{slot, value, body} => {
+                let val = get_expr!(slab.ps,value)._eval(slab,ns,locals)?;
+                with_local(locals, *slot, val, |locals| get_expr!(slab.ps,body)._eval_val(slab,ns,locals))
+            }
+            ELetFunc{body, ..} => get_expr!(slab.ps,body)._eval_val(slab,ns,locals),
+            _ => Ok(Val::Num(self._eval(slab,ns,locals)?)),
         }
     }
 }
@@ -329,21 +620,44 @@ impl Evaler for UnaryOp {
         match self {
             EPos(val_i) | ENeg(val_i) | ENot(val_i) => get_val!(slab.ps,val_i)._var_names(slab,dst),
             EParentheses(expr_i) => get_expr!(slab.ps,expr_i)._var_names(slab,dst),
+            #[cfg(feature="bitwise")]
+            EBitNot(val_i) => get_val!(slab.ps,val_i)._var_names(slab,dst),
         }
     }
-    fn eval(&self, slab:&Slab, ns:&mut impl EvalNamespace) -> Result<f64,Error> {
+    fn _eval(&self, slab:&Slab, ns:&mut impl EvalNamespace, locals:&mut Locals) -> Result<f64,Error> {
         match self {
-            EPos(val_i) => get_val!(slab.ps,val_i).eval(slab,ns),
-            ENeg(val_i) => Ok(-get_val!(slab.ps,val_i).eval(slab,ns)?),
-            ENot(val_i) => Ok(bool_to_f64!(f64_eq!(get_val!(slab.ps,val_i).eval(slab,ns)?,0.0))),
-            EParentheses(expr_i) => get_expr!(slab.ps,expr_i).eval(slab,ns),
+            EPos(val_i) => get_val!(slab.ps,val_i)._eval(slab,ns,locals),
+            ENeg(val_i) => Ok(-get_val!(slab.ps,val_i)._eval(slab,ns,locals)?),
+            ENot(val_i) => Ok(bool_to_f64!(f64_eq!(get_val!(slab.ps,val_i)._eval(slab,ns,locals)?,0.0))),
+            EParentheses(expr_i) => get_expr!(slab.ps,expr_i)._eval(slab,ns,locals),
+            #[cfg(feature="bitwise")]
+            EBitNot(val_i) => Ok(!(get_val!(slab.ps,val_i)._eval(slab,ns,locals)? as i64) as f64),
         }
     }
+    fn _eval_val(&self, slab:&Slab, ns:&mut impl EvalNamespace, locals:&mut Locals) -> Result<Val,Error> {
+        match self {
+            EPos(val_i) => get_val!(slab.ps,val_i)._eval_val(slab,ns,locals),
+            ENeg(val_i) => elementwise(get_val!(slab.ps,val_i)._eval_val(slab,ns,locals)?, |x| -x),
+            ENot(val_i) => elementwise(get_val!(slab.ps,val_i)._eval_val(slab,ns,locals)?, |x| bool_to_f64!(f64_eq!(x,0.0))),
+            EParentheses(expr_i) => get_expr!(slab.ps,expr_i)._eval_val(slab,ns,locals),
+            #[cfg(feature="bitwise")]
+            EBitNot(_) => Ok(Val::Num(self._eval(slab,ns,locals)?)),
+        }
+    }
+}
+
+// Applies a unary operation to a number, or to each element of an array.
+fn elementwise(val:Val, f:impl Fn(f64)->f64) -> Result<Val,Error> {
+    match val {
+        Val::Num(x) => Ok(Val::Num(f(x))),
+        Val::Arr(a) => Ok(Val::Arr(a.into_iter().map(f).collect())),
+        Val::Str(_) => Err(Error::TypeMismatch("expected a number, not a string".to_string())),
+    }
 }
 
 impl BinaryOp {
     // Non-standard eval interface (not generalized yet):
-    fn binaryop_eval(self, left_opt:Option<&f64>, right_opt:Option<&f64>) -> f64 {  // Passing 'self' by value is more efficient than pass-by-reference.
+    pub(crate) fn binaryop_eval(self, left_opt:Option<&f64>, right_opt:Option<&f64>) -> f64 {  // Passing 'self' by value is more efficient than pass-by-reference.
         let left = match left_opt {
             Some(l) => *l,
             None => return std::f64::NAN,
@@ -369,6 +683,45 @@ impl BinaryOp {
                    else { right },
             EAND => if f64_eq!(left,0.0) { left }
                     else { right },
+            #[cfg(feature="bitwise")]
+            EBitOr | EBitXor | EBitAnd | EShr | EShl | EIntDiv => bitwise(self,left,right),
+        }
+    }
+
+    // Like binaryop_eval(), but strings can be concatenated with '+', and compared with each other,
+    // and arrays are processed element-wise.  A number is used for every element of an array.
+    pub(crate) fn binaryop_eval_val(self, left_opt:Option<&Val>, right_opt:Option<&Val>) -> Result<Val,Error> {
+        let (left, right) = match (left_opt, right_opt) {
+            (Some(l), Some(r)) => (l, r),
+            _ => return Err(Error::Unreachable),
+        };
+        match (left, right) {
+            (Val::Num(l), Val::Num(r)) => return Ok(Val::Num(self.binaryop_eval(Some(l), Some(r)))),
+            (Val::Arr(a), Val::Num(r)) => return Ok(Val::Arr(a.iter().map(|l| self.binaryop_eval(Some(l), Some(r))).collect())),
+            (Val::Num(l), Val::Arr(a)) => return Ok(Val::Arr(a.iter().map(|r| self.binaryop_eval(Some(l), Some(r))).collect())),
+            (Val::Arr(a), Val::Arr(b)) => {
+                if a.len()!=b.len() { return Err(Error::TypeMismatch(format!("arrays have different lengths ({} and {})", a.len(), b.len()))); }
+                return Ok(Val::Arr(a.iter().zip(b).map(|(l,r)| self.binaryop_eval(Some(l), Some(r))).collect()));
+            }
+            _ => (),  // At least one string.
+        }
+        match self {
+            EAdd => Ok(Val::Str(format!("{}{}", left, right))),
+            EEQ => Ok(Val::Num(bool_to_f64!(left==right))),
+            ENE => Ok(Val::Num(bool_to_f64!(left!=right))),
+            ELT | ELTE | EGTE | EGT => {
+                let (l, r) = match (left, right) {
+                    (Val::Str(l), Val::Str(r)) => (l, r),
+                    (Val::Str(_), other) | (other, _) => return Err(Error::TypeMismatch(format!("cannot compare a string with {}", other.kind()))),
+                };
+                Ok(Val::Num(bool_to_f64!(match self {
+                    ELT => l<r,
+                    ELTE => l<=r,
+                    EGTE => l>=r,
+                    _ => l>r,
+                })))
+            }
+            _ => Err(Error::TypeMismatch("strings only support '+' and comparisons".to_string())),
         }
     }
 }
@@ -382,6 +735,125 @@ macro_rules! eval_var {
     };
 }
 
+/// The values of local variables and function parameters during one evaluation.
+///
+/// `eval()` and `eval_val()` start each evaluation with empty `Locals`, so a
+/// namespace callback can evaluate other expressions (even from the same `Slab`)
+/// without disturbing the evaluation that called it.  This state is kept out of
+/// the `Slab` so that a `Slab` can be shared between threads.
+#[derive(Debug, Default)]
+pub struct Locals {
+    vals :Vec<f64>,
+    depth:usize,  // The number of user function calls in progress.
+}
+
+impl Locals {
+    // Returns the value that was most recently assigned to a local slot.
+    #[inline]
+    fn get(&self, slot:usize) -> f64 {
+        self.vals.get(slot).copied().unwrap_or(std::f64::NAN)
+    }
+
+    // Assigns a value to a local slot, and returns the previous value.
+    #[inline]
+    fn set(&mut self, slot:usize, val:f64) -> f64 {
+        if self.vals.len()<=slot { self.vals.resize(slot+1, std::f64::NAN); }
+        match self.vals.get_mut(slot) {
+            Some(v) => std::mem::replace(v, val),
+            None => std::f64::NAN,
+        }
+    }
+}
+
+// Binds `val` to `slot` while `f` is evaluated.
+#[inline]
+fn with_local<T>(locals:&mut Locals, slot:usize, val:f64, f:impl FnOnce(&mut Locals)->T) -> T {
+    let saved = locals.set(slot, val);
+    let out = f(locals);
+    locals.set(slot, saved);
+    out
+}
+
+// Calls the namespace's version of a builtin function, or `eval_builtin` if the namespace doesn't define it.
+// The builtin reads its args from the local slots starting at `slot`.  They are bound before the lookup,
+// because `lookup()` takes ownership of `args`, and the previous slot values are restored afterwards.
+fn eval_namespace_first<NS:EvalNamespace>(ns:&mut NS, locals:&mut Locals, name:&str, args:Vec<f64>, slot:usize, keybuf:&mut String, eval_builtin:impl FnOnce(&mut NS, &mut Locals)->Result<f64,Error>) -> Result<f64,Error> {
+    let saved : Vec<f64> = args.iter().enumerate().map(|(i,&arg)| locals.set(slot+i, arg)).collect();
+    let out = match ns.lookup(name, args, keybuf) {
+        Some(f) => Ok(f),
+        None => eval_builtin(ns, locals),
+    };
+    for (i,val) in saved.into_iter().enumerate() { locals.set(slot+i, val); }
+    out
+}
+
+// Binds the arguments of a call to a function defined within the expression.  The previous
+// parameter values and call depth are restored when the Frame is dropped, so recursive calls
+// don't clobber each other, even when the evaluation fails part-way through.
+struct Frame<'a> {
+    locals:&'a mut Locals,
+    saved :Vec<(usize,f64)>,
+}
+
+impl<'a> Frame<'a> {
+    fn call(locals:&'a mut Locals, slab:&Slab, func:usize, args:&[f64]) -> Result<Self,Error> {
+        let uf = match slab.ps.funcs.get(func) {
+            Some(uf) => uf,
+            None => return Err(Error::Unreachable),
+        };
+        if locals.depth>=slab.ps.call_depth_limit { return Err(Error::RecursionLimit(uf.name.clone())); }
+
+        let mut saved = Vec::with_capacity(uf.params.len());
+        for (&slot,&arg) in uf.params.iter().zip(args) {
+            saved.push((slot, locals.set(slot, arg)));
+        }
+        locals.depth+=1;
+        Ok(Frame{locals, saved})
+    }
+}
+
+impl Drop for Frame<'_> {
+    fn drop(&mut self) {
+        self.locals.depth-=1;
+        for &(slot,val) in self.saved.iter().rev() {
+            self.locals.set(slot, val);
+        }
+    }
+}
+
+impl Deref for Frame<'_> {
+    type Target = Locals;
+    fn deref(&self) -> &Locals { self.locals }
+}
+impl DerefMut for Frame<'_> {
+    fn deref_mut(&mut self) -> &mut Locals { self.locals }
+}
+
+// Evaluates sum() or prod().  `eval_expr` is called once per index value, after it has been stored in `slot`.
+// The previous value of `slot` is restored afterwards, in case of recursion.
+fn eval_series(slab:&Slab, locals:&mut Locals, is_sum:bool, slot:usize, start:f64, end:f64, mut eval_expr:impl FnMut(&mut Locals)->Result<f64,Error>) -> Result<f64,Error> {
+    let n = series_len(start,end);
+    if n>slab.ps.iter_limit as f64 { return Err(Error::IterationLimit((if is_sum { "sum" } else { "prod" }).to_string())); }
+
+    let saved = locals.get(slot);
+    let mut acc = if is_sum { 0.0 } else { 1.0 };
+    let mut k = 0.0;
+    while k<n {
+        locals.set(slot, start+k);
+        let val = match eval_expr(locals) {
+            Ok(val) => val,
+            Err(err) => {
+                locals.set(slot, saved);
+                return Err(err);
+            }
+        };
+        if is_sum { acc+=val } else { acc*=val }
+        k+=1.0;
+    }
+    locals.set(slot, saved);
+    Ok(acc)
+}
+
 impl Evaler for StdFunc {
     fn _var_names(&self, slab:&Slab, dst:&mut BTreeSet<String>) {
         match self {
@@ -390,8 +862,30 @@ impl Evaler for StdFunc {
 
             EVar(s) => { dst.insert(s.clone()); }
             EFunc{name, ..} => { dst.insert(name.clone()); }
+            EIndex{name, idx} => {
+                dst.insert(name.clone());
+                get_expr!(slab.ps,idx)._var_names(slab,dst);
+            }
+            ENativeFunc{args:xis, ..} => {
+                for xi in xis {
+                    get_expr!(slab.ps,xi)._var_names(slab,dst);
+                }
+            }
+            ENamespaceFirst{name, args:xis, ..} => {
+                dst.insert(name.clone());
+                for xi in xis {
+                    get_expr!(slab.ps,xi)._var_names(slab,dst);
+                }
+            }
 
-            EFuncInt(xi) | EFuncCeil(xi) | EFuncFloor(xi) | EFuncAbs(xi) | EFuncSign(xi) | EFuncSin(xi) | EFuncCos(xi) | EFuncTan(xi) | EFuncASin(xi) | EFuncACos(xi) | EFuncATan(xi) | EFuncSinH(xi) | EFuncCosH(xi) | EFuncTanH(xi) | EFuncASinH(xi) | EFuncACosH(xi) | EFuncATanH(xi) => get_expr!(slab.ps,xi)._var_names(slab,dst),
+            ELocal(_) => (),  // Bound within the expression, so it's not an external variable.
+            EUserFunc{args:xis, ..} => {
+                for xi in xis {
+                    get_expr!(slab.ps,xi)._var_names(slab,dst);
+                }
+            }
+
+            EFuncInt(xi) | EFuncCeil(xi) | EFuncFloor(xi) | EFuncAbs(xi) | EFuncSign(xi) | EFuncSin(xi) | EFuncCos(xi) | EFuncTan(xi) | EFuncSinD(xi) | EFuncCosD(xi) | EFuncTanD(xi) | EFuncASin(xi) | EFuncACos(xi) | EFuncATan(xi) | EFuncSinH(xi) | EFuncCosH(xi) | EFuncTanH(xi) | EFuncASinH(xi) | EFuncACosH(xi) | EFuncATanH(xi) | EFuncSqrt(xi) | EFuncCbrt(xi) | EFuncExp(xi) | EFuncLn(xi) | EFuncLog2(xi) | EFuncLog10(xi) | EFuncTrunc(xi) | EFuncFrac(xi) | EFuncDeg(xi) | EFuncRad(xi) | EFuncErf(xi) | EFuncGamma(xi) | EFuncLGamma(xi) => get_expr!(slab.ps,xi)._var_names(slab,dst),
 
             EFuncE | EFuncPi => (),
 
@@ -402,15 +896,78 @@ impl Evaler for StdFunc {
                 }
                 get_expr!(slab.ps,expr)._var_names(slab,dst);
             }
-            EFuncMin{first,rest} | EFuncMax{first,rest} => {
+            EFuncMin{first,rest} | EFuncMax{first,rest} | EFuncStat{first,rest,..} => {
                 get_expr!(slab.ps,first)._var_names(slab,dst);
                 for xi in rest {
                     get_expr!(slab.ps,xi)._var_names(slab,dst);
                 }
             }
+            EFuncIf{cond, then, otherwise} => {
+                get_expr!(slab.ps,cond)._var_names(slab,dst);
+                get_expr!(slab.ps,then)._var_names(slab,dst);
+                get_expr!(slab.ps,otherwise)._var_names(slab,dst);
+            }
+            EFuncATan2(a, b) | EFuncHypot(a, b) | EFuncPow(a, b) | EFuncFMod(a, b) => {
+                get_expr!(slab.ps,a)._var_names(slab,dst);
+                get_expr!(slab.ps,b)._var_names(slab,dst);
+            }
+            EFuncClamp{x, min, max} => {
+                get_expr!(slab.ps,x)._var_names(slab,dst);
+                get_expr!(slab.ps,min)._var_names(slab,dst);
+                get_expr!(slab.ps,max)._var_names(slab,dst);
+            }
+            EFuncSum{start, end, expr, ..} | EFuncProd{start, end, expr, ..} => {
+                get_expr!(slab.ps,start)._var_names(slab,dst);
+                get_expr!(slab.ps,end)._var_names(slab,dst);
+                get_expr!(slab.ps,expr)._var_names(slab,dst);
+            }
+            EFuncStr{args:xis, ..} => {
+                for xi in xis {
+                    get_expr!(slab.ps,xi)._var_names(slab,dst);
+                }
+            }
         };
     }
-    fn eval(&self, slab:&Slab, ns:&mut impl EvalNamespace) -> Result<f64,Error> {
+    fn _eval_val(&self, slab:&Slab, ns:&mut impl EvalNamespace, locals:&mut Locals) -> Result<Val,Error> {
+        match self {
+            EVar(name) => match ns.lookup_val(name, Vec::new(), &mut String::new()) {
+                Some(v) => Ok(v),
+                None => Err(Error::Undefined(name.to_string())),
+            },
+            EFunc{name, args:xis} => {
+                let mut args = Vec::with_capacity(xis.len());
+                for xi in xis {
+                    args.push(get_expr!(slab.ps,xi)._eval(slab,ns,locals)?)
+                }
+                match ns.lookup_val(name, args, &mut String::new()) {
+                    Some(v) => Ok(v),
+                    None => Err(Error::Undefined(name.to_string())),
+                }
+            }
+            EIndex{name, idx} => {
+                let i = get_expr!(slab.ps,idx)._eval(slab,ns,locals)?;
+                let keybuf = &mut String::new();
+                match ns.lookup_val(name, vec![i], keybuf) {
+                    Some(v) => Ok(v),
+                    None => Ok(Val::Num(index_var(ns, name, i, keybuf)?)),
+                }
+            }
+            EFuncMin{first, rest} | EFuncMax{first, rest} => {
+                let is_max = matches!(self, EFuncMax{..});
+                let mut out = min_max(get_expr!(slab.ps,first)._eval_val(slab,ns,locals)?, is_max)?;
+                for x_i in rest.iter() {
+                    let f = min_max(get_expr!(slab.ps,x_i)._eval_val(slab,ns,locals)?, is_max)?;
+                    if out.is_nan() || f.is_nan() { out = std::f64::NAN; }
+                    else if is_max { out = out.max(f); }
+                    else { out = out.min(f); }
+                }
+                Ok(Val::Num(out))
+            }
+            EFuncStr{func, args} => eval_str_func(*func, args, slab, ns, locals),
+            _ => Ok(Val::Num(self._eval(slab,ns,locals)?)),
+        }
+    }
+    fn _eval(&self, slab:&Slab, ns:&mut impl EvalNamespace, locals:&mut Locals) -> Result<f64,Error> {
         match self {
             // These match arms are ordered in a way that I feel should deliver good performance.
             // (I don't think this ordering actually affects the generated code, though.)
@@ -419,68 +976,162 @@ impl Evaler for StdFunc {
             EUnsafeVar{ptr, ..} => unsafe { Ok(**ptr) },
 
             EVar(name) => eval_var!(ns, name, Vec::new(), unsafe{ &mut *(&slab.ps.char_buf as *const _ as *mut _) }),
+            ELocal(slot) => Ok(locals.get(*slot)),
+            EUserFunc{func, args:xis} => {
+                let mut args = Vec::with_capacity(xis.len());
+                for xi in xis {
+                    args.push(get_expr!(slab.ps,xi)._eval(slab,ns,locals)?)
+                }
+                let body = match slab.ps.funcs.get(*func) {
+                    Some(uf) => uf.body,
+                    None => return Err(Error::Unreachable),
+                };
+                let mut frame = Frame::call(locals, slab, *func, &args)?;
+                get_expr!(slab.ps,body)._eval(slab,ns,&mut frame)
+            }
             EFunc{name, args:xis} => {
                 let mut args = Vec::with_capacity(xis.len());
                 for xi in xis {
-                    args.push(get_expr!(slab.ps,xi).eval(slab,ns)?)
+                    args.push(get_expr!(slab.ps,xi)._eval(slab,ns,locals)?)
                 }
                 eval_var!(ns, name, args, unsafe{ &mut *(&slab.ps.char_buf as *const _ as *mut _) })
             }
+            EIndex{name, idx} => {
+                let i = get_expr!(slab.ps,idx)._eval(slab,ns,locals)?;
+                let keybuf = &mut String::new();
+                match ns.lookup(name, vec![i], keybuf) {
+                    Some(f) => Ok(f),
+                    None => index_var(ns, name, i, keybuf),
+                }
+            }
+            ENativeFunc{func, args:xis} => {
+                let mut args = Vec::with_capacity(xis.len());
+                for xi in xis {
+                    args.push(get_expr!(slab.ps,xi)._eval(slab,ns,locals)?)
+                }
+                Ok((func.func)(&args))
+            }
+            ENamespaceFirst{name, args:xis, slot, builtin} => {
+                let mut args = Vec::with_capacity(xis.len());
+                for xi in xis {
+                    args.push(get_expr!(slab.ps,xi)._eval(slab,ns,locals)?)
+                }
+                eval_namespace_first(ns, locals, name, args, *slot, unsafe{ &mut *(&slab.ps.char_buf as *const _ as *mut _) },
+                                     |ns, locals| get_expr!(slab.ps,builtin)._eval(slab,ns,locals))
+            }
 
             EFuncLog{base:base_opt, expr:expr_i} => {
                 let base = match base_opt {
-                    Some(b_expr_i) => get_expr!(slab.ps,b_expr_i).eval(slab,ns)?,
+                    Some(b_expr_i) => get_expr!(slab.ps,b_expr_i)._eval(slab,ns,locals)?,
                     None => 10.0,
                 };
-                let n = get_expr!(slab.ps,expr_i).eval(slab,ns)?;
+                let n = get_expr!(slab.ps,expr_i)._eval(slab,ns,locals)?;
                 Ok(log(base,n))
             }
 
-            EFuncSin(expr_i) => Ok(get_expr!(slab.ps,expr_i).eval(slab,ns)?.sin()),
-            EFuncCos(expr_i) => Ok(get_expr!(slab.ps,expr_i).eval(slab,ns)?.cos()),
-            EFuncTan(expr_i) => Ok(get_expr!(slab.ps,expr_i).eval(slab,ns)?.tan()),
-            EFuncASin(expr_i) => Ok(get_expr!(slab.ps,expr_i).eval(slab,ns)?.asin()),
-            EFuncACos(expr_i) => Ok(get_expr!(slab.ps,expr_i).eval(slab,ns)?.acos()),
-            EFuncATan(expr_i) => Ok(get_expr!(slab.ps,expr_i).eval(slab,ns)?.atan()),
-            EFuncSinH(expr_i) => Ok(get_expr!(slab.ps,expr_i).eval(slab,ns)?.sinh()),
-            EFuncCosH(expr_i) => Ok(get_expr!(slab.ps,expr_i).eval(slab,ns)?.cosh()),
-            EFuncTanH(expr_i) => Ok(get_expr!(slab.ps,expr_i).eval(slab,ns)?.tanh()),
-            EFuncASinH(expr_i) => Ok(get_expr!(slab.ps,expr_i).eval(slab,ns)?.asinh()),
-            EFuncACosH(expr_i) => Ok(get_expr!(slab.ps,expr_i).eval(slab,ns)?.acosh()),
-            EFuncATanH(expr_i) => Ok(get_expr!(slab.ps,expr_i).eval(slab,ns)?.atanh()),
+            EFuncSin(expr_i) => Ok(get_expr!(slab.ps,expr_i)._eval(slab,ns,locals)?.sin()),
+            EFuncCos(expr_i) => Ok(get_expr!(slab.ps,expr_i)._eval(slab,ns,locals)?.cos()),
+            EFuncTan(expr_i) => Ok(get_expr!(slab.ps,expr_i)._eval(slab,ns,locals)?.tan()),
+            EFuncSinD(expr_i) => Ok(sind(get_expr!(slab.ps,expr_i)._eval(slab,ns,locals)?)),
+            EFuncCosD(expr_i) => Ok(cosd(get_expr!(slab.ps,expr_i)._eval(slab,ns,locals)?)),
+            EFuncTanD(expr_i) => Ok(tand(get_expr!(slab.ps,expr_i)._eval(slab,ns,locals)?)),
+            EFuncASin(expr_i) => Ok(get_expr!(slab.ps,expr_i)._eval(slab,ns,locals)?.asin()),
+            EFuncACos(expr_i) => Ok(get_expr!(slab.ps,expr_i)._eval(slab,ns,locals)?.acos()),
+            EFuncATan(expr_i) => Ok(get_expr!(slab.ps,expr_i)._eval(slab,ns,locals)?.atan()),
+            EFuncSinH(expr_i) => Ok(get_expr!(slab.ps,expr_i)._eval(slab,ns,locals)?.sinh()),
+            EFuncCosH(expr_i) => Ok(get_expr!(slab.ps,expr_i)._eval(slab,ns,locals)?.cosh()),
+            EFuncTanH(expr_i) => Ok(get_expr!(slab.ps,expr_i)._eval(slab,ns,locals)?.tanh()),
+            EFuncASinH(expr_i) => Ok(get_expr!(slab.ps,expr_i)._eval(slab,ns,locals)?.asinh()),
+            EFuncACosH(expr_i) => Ok(get_expr!(slab.ps,expr_i)._eval(slab,ns,locals)?.acosh()),
+            EFuncATanH(expr_i) => Ok(get_expr!(slab.ps,expr_i)._eval(slab,ns,locals)?.atanh()),
+
+            EFuncSqrt(expr_i) => Ok(get_expr!(slab.ps,expr_i)._eval(slab,ns,locals)?.sqrt()),
+            EFuncCbrt(expr_i) => Ok(get_expr!(slab.ps,expr_i)._eval(slab,ns,locals)?.cbrt()),
+            EFuncExp(expr_i) => Ok(get_expr!(slab.ps,expr_i)._eval(slab,ns,locals)?.exp()),
+            EFuncLn(expr_i) => Ok(get_expr!(slab.ps,expr_i)._eval(slab,ns,locals)?.ln()),
+            EFuncLog2(expr_i) => Ok(get_expr!(slab.ps,expr_i)._eval(slab,ns,locals)?.log2()),
+            EFuncLog10(expr_i) => Ok(get_expr!(slab.ps,expr_i)._eval(slab,ns,locals)?.log10()),
+            EFuncTrunc(expr_i) => Ok(get_expr!(slab.ps,expr_i)._eval(slab,ns,locals)?.trunc()),
+            EFuncFrac(expr_i) => Ok(get_expr!(slab.ps,expr_i)._eval(slab,ns,locals)?.fract()),
+            EFuncDeg(expr_i) => Ok(get_expr!(slab.ps,expr_i)._eval(slab,ns,locals)?.to_degrees()),
+            EFuncRad(expr_i) => Ok(get_expr!(slab.ps,expr_i)._eval(slab,ns,locals)?.to_radians()),
+            EFuncErf(expr_i) => Ok(erf(get_expr!(slab.ps,expr_i)._eval(slab,ns,locals)?)),
+            EFuncGamma(expr_i) => Ok(gamma(get_expr!(slab.ps,expr_i)._eval(slab,ns,locals)?)),
+            EFuncLGamma(expr_i) => Ok(lgamma(get_expr!(slab.ps,expr_i)._eval(slab,ns,locals)?)),
+
+            EFuncATan2(a, b) => {
+                let a = get_expr!(slab.ps,a)._eval(slab,ns,locals)?;
+                let b = get_expr!(slab.ps,b)._eval(slab,ns,locals)?;
+                Ok(a.atan2(b))
+            }
+            EFuncHypot(a, b) => {
+                let a = get_expr!(slab.ps,a)._eval(slab,ns,locals)?;
+                let b = get_expr!(slab.ps,b)._eval(slab,ns,locals)?;
+                Ok(a.hypot(b))
+            }
+            EFuncPow(a, b) => {
+                let a = get_expr!(slab.ps,a)._eval(slab,ns,locals)?;
+                let b = get_expr!(slab.ps,b)._eval(slab,ns,locals)?;
+                Ok(a.powf(b))
+            }
+            EFuncFMod(a, b) => {
+                let a = get_expr!(slab.ps,a)._eval(slab,ns,locals)?;
+                let b = get_expr!(slab.ps,b)._eval(slab,ns,locals)?;
+                Ok(a%b)
+            }
+            EFuncClamp{x, min, max} => {
+                let x = get_expr!(slab.ps,x)._eval(slab,ns,locals)?;
+                let min = get_expr!(slab.ps,min)._eval(slab,ns,locals)?;
+                let max = get_expr!(slab.ps,max)._eval(slab,ns,locals)?;
+                Ok(clamp(x,min,max))
+            }
 
             EFuncRound{modulus:modulus_opt, expr:expr_i} => {
                 let modulus = match modulus_opt {
-                    Some(m_expr_i) => get_expr!(slab.ps,m_expr_i).eval(slab,ns)?,
+                    Some(m_expr_i) => get_expr!(slab.ps,m_expr_i)._eval(slab,ns,locals)?,
                     None => 1.0,
                 };
-                Ok((get_expr!(slab.ps,expr_i).eval(slab,ns)?/modulus).round() * modulus)
+                Ok((get_expr!(slab.ps,expr_i)._eval(slab,ns,locals)?/modulus).round() * modulus)
             }
 
-            EFuncAbs(expr_i) => Ok(get_expr!(slab.ps,expr_i).eval(slab,ns)?.abs()),
-            EFuncSign(expr_i) => Ok(get_expr!(slab.ps,expr_i).eval(slab,ns)?.signum()),
-            EFuncInt(expr_i) => Ok(get_expr!(slab.ps,expr_i).eval(slab,ns)?.trunc()),
-            EFuncCeil(expr_i) => Ok(get_expr!(slab.ps,expr_i).eval(slab,ns)?.ceil()),
-            EFuncFloor(expr_i) => Ok(get_expr!(slab.ps,expr_i).eval(slab,ns)?.floor()),
-            EFuncMin{first:first_i, rest} => {
-                let mut min = get_expr!(slab.ps,first_i).eval(slab,ns)?;
-                let mut saw_nan = min.is_nan();
-                for x_i in rest.iter() {
-                    min = min.min(get_expr!(slab.ps,x_i).eval(slab,ns)?);
-                    saw_nan = saw_nan || min.is_nan();
+            EFuncAbs(expr_i) => Ok(get_expr!(slab.ps,expr_i)._eval(slab,ns,locals)?.abs()),
+            EFuncSign(expr_i) => Ok(get_expr!(slab.ps,expr_i)._eval(slab,ns,locals)?.signum()),
+            EFuncInt(expr_i) => Ok(get_expr!(slab.ps,expr_i)._eval(slab,ns,locals)?.trunc()),
+            EFuncCeil(expr_i) => Ok(get_expr!(slab.ps,expr_i)._eval(slab,ns,locals)?.ceil()),
+            EFuncFloor(expr_i) => Ok(get_expr!(slab.ps,expr_i)._eval(slab,ns,locals)?.floor()),
+            // Any argument might be an array, which eval_val() reduces:
+            EFuncMin{..} | EFuncMax{..} => self._eval_val(slab,ns,locals)?.num(),
+            EFuncStat{func, skip_nan, ns_first, first, rest} => {
+                let mut st = Stat::new(*func, *skip_nan);
+                match ns_first {
+                    None => {
+                        push_flat(&mut st, get_expr!(slab.ps,first)._eval_val(slab,ns,locals)?)?;
+                        for x_i in rest.iter() {
+                            push_flat(&mut st, get_expr!(slab.ps,x_i)._eval_val(slab,ns,locals)?)?;
+                        }
+                    }
+                    Some(name) => {
+                        let mut vals = Vec::with_capacity(rest.len()+1);
+                        for x_i in std::iter::once(first).chain(rest.iter()) {
+                            vals.push(get_expr!(slab.ps,x_i)._eval_val(slab,ns,locals)?);
+                        }
+                        if let Some(f) = lookup_stat(ns, name, &vals, unsafe{ &mut *(&slab.ps.char_buf as *const _ as *mut _) }) { return Ok(f); }
+                        for val in vals { push_flat(&mut st, val)?; }
+                    }
                 }
-                if saw_nan { Ok(std::f64::NAN)
-                } else { Ok(min) }
+                Ok(st.finish())
             }
-            EFuncMax{first:first_i, rest} => {
-                let mut max = get_expr!(slab.ps,first_i).eval(slab,ns)?;
-                let mut saw_nan = max.is_nan();
-                for x_i in rest.iter() {
-                    max = max.max(get_expr!(slab.ps,x_i).eval(slab,ns)?);
-                    saw_nan = saw_nan || max.is_nan();
-                }
-                if saw_nan { Ok(std::f64::NAN)
-                } else { Ok(max) }
+            EFuncStr{func, args} => eval_str_func(*func, args, slab, ns, locals)?.num(),
+            EFuncIf{cond, then, otherwise} => {
+                let c = get_expr!(slab.ps,cond)._eval(slab,ns,locals)?;
+                if f64_eq!(c,0.0) { get_expr!(slab.ps,otherwise)._eval(slab,ns,locals) }
+                else { get_expr!(slab.ps,then)._eval(slab,ns,locals) }
+            }
+            EFuncSum{slot, start, end, expr} | EFuncProd{slot, start, end, expr} => {
+                let is_sum = matches!(self, EFuncSum{..});
+                let start = get_expr!(slab.ps,start)._eval(slab,ns,locals)?;
+                let end = get_expr!(slab.ps,end)._eval(slab,ns,locals)?;
+                eval_series(slab, locals, is_sum, *slot, start, end, |locals| get_expr!(slab.ps,expr)._eval(slab,ns,locals))
             }
 
             EFuncE => Ok(consts::E),
@@ -489,6 +1140,97 @@ impl Evaler for StdFunc {
     }
 }
 
+// When the namespace doesn't know the function `xs(i)`, `xs[i]` indexes an array named `xs`
+// instead.  Indexes start at 0, and are truncated like the integer operators.  Indexes that are
+// out of range give NaN.
+fn index_var(ns:&mut impl EvalNamespace, name:&str, i:f64, keybuf:&mut String) -> Result<f64,Error> {
+    if let Some(Val::Arr(a)) = ns.lookup_val(name, Vec::new(), keybuf) {
+        if i<0.0 { return Ok(std::f64::NAN); }  // Also false for NaN, which becomes 0 as a usize.
+        return Ok(a.get(i as usize).copied().unwrap_or(std::f64::NAN));
+    }
+    Err(Error::Undefined(name.to_string()))
+}
+
+// The reductions (min, max, and the statistics) also accept arrays:
+fn push_flat(dst:&mut Stat, val:Val) -> Result<(),Error> {
+    match val {
+        Val::Num(f) => dst.push(f),
+        Val::Arr(a) => for f in a { dst.push(f) },
+        Val::Str(_) => return Err(Error::TypeMismatch("expected a number or an array, not a string".to_string())),
+    }
+    Ok(())
+}
+// Asks the namespace for its own version of an aggregate function.  Namespace functions only take
+// numbers, so it isn't asked when one of the args is an array:
+fn lookup_stat(ns:&mut impl EvalNamespace, name:&str, vals:&[Val], keybuf:&mut String) -> Option<f64> {
+    let mut args = Vec::with_capacity(vals.len());
+    for val in vals {
+        match val {
+            Val::Num(f) => args.push(*f),
+            _ => return None,
+        }
+    }
+    ns.lookup(name, args, keybuf)
+}
+// Reduces one min()/max() argument.  Like min() and max() themselves, any NaN makes the result NaN.
+fn min_max(val:Val, is_max:bool) -> Result<f64,Error> {
+    match val {
+        Val::Num(f) => Ok(f),
+        Val::Arr(a) => {
+            let mut out = match a.first() {
+                Some(f) => *f,
+                None => return Ok(std::f64::NAN),
+            };
+            for &f in &a {
+                if f.is_nan() { return Ok(std::f64::NAN); }
+                out = if is_max { out.max(f) } else { out.min(f) };
+            }
+            Ok(out)
+        }
+        Val::Str(_) => Err(Error::TypeMismatch("expected a number or an array, not a string".to_string())),
+    }
+}
+
+// Evaluates the string functions.  This is shared by StdFunc and Instruction,
+// because compiled string functions still evaluate their parsed args.
+fn eval_str_func(func:StrFunc, args:&[ExpressionI], slab:&Slab, ns:&mut impl EvalNamespace, locals:&mut Locals) -> Result<Val,Error> {
+    let mut vals = Vec::with_capacity(args.len());
+    for xi in args {
+        vals.push(get_expr!(slab.ps,xi)._eval_val(slab,ns,locals)?);
+    }
+    if func==StrFunc::Str {
+        return match vals.first() {
+            Some(v) => Ok(Val::Str(v.to_string())),
+            None => Err(Error::Unreachable),
+        };
+    }
+
+    if let (StrFunc::Len, Some(Val::Arr(a))) = (func, vals.first()) {
+        return Ok(Val::Num(a.len() as f64));
+    }
+
+    let mut strs = Vec::with_capacity(vals.len());
+    for v in &vals {
+        match v {
+            Val::Str(s) => strs.push(s.as_str()),
+            _ => return Err(Error::TypeMismatch(format!("{}: expected a string", func.name()))),
+        }
+    }
+    let (a, b) = match (strs.first(), strs.get(1)) {
+        (Some(a), b) => (*a, b.copied().unwrap_or("")),
+        (None, _) => return Err(Error::Unreachable),
+    };
+    Ok(match func {
+        StrFunc::Len => Val::Num(a.chars().count() as f64),
+        StrFunc::Upper => Val::Str(a.to_uppercase()),
+        StrFunc::Lower => Val::Str(a.to_lowercase()),
+        StrFunc::Contains => Val::Num(bool_to_f64!(a.contains(b))),
+        StrFunc::StartsWith => Val::Num(bool_to_f64!(a.starts_with(b))),
+        StrFunc::EndsWith => Val::Num(bool_to_f64!(a.ends_with(b))),
+        StrFunc::Str => return Err(Error::Unreachable),
+    })
+}
+
 impl Evaler for PrintFunc {
     fn _var_names(&self, slab:&Slab, dst:&mut BTreeSet<String>) {
         for x_or_s in &self.0 {
@@ -498,7 +1240,7 @@ impl Evaler for PrintFunc {
             };
         }
     }
-    fn eval(&self, slab:&Slab, ns:&mut impl EvalNamespace) -> Result<f64,Error> {
+    fn _eval(&self, slab:&Slab, ns:&mut impl EvalNamespace, locals:&mut Locals) -> Result<f64,Error> {
         let mut val = 0f64;
 
         fn process_str(s:&str) -> String {
@@ -508,12 +1250,24 @@ impl Evaler for PrintFunc {
         if let Some(EStr(fmtstr)) = self.0.first() {
             if fmtstr.contains('%') {
                 // printf mode:
-
-                //let fmtstr = process_str(fmtstr);
-
-                return Err(Error::WrongArgs("printf formatting is not yet implemented".to_string()));  // TODO: Make a pure-rust sprintf libarary.
-
-                //return Ok(val);
+                let mut args = Vec::with_capacity(self.0.len()-1);
+                for a in &self.0[1..] {
+                    match a {
+                        EExpr(e_i) => {
+                            match get_expr!(slab.ps,e_i)._eval_val(slab,ns,locals)? {
+                                Val::Num(f) => {
+                                    val = f;
+                                    args.push(Arg::Num(f));
+                                }
+                                Val::Str(s) => args.push(Arg::Str(s)),
+                                arr@Val::Arr(_) => args.push(Arg::Str(arr.to_string())),
+                            }
+                        }
+                        EStr(s) => args.push(Arg::Str(process_str(s))),
+                    }
+                }
+                ns.print(&sprintf(&process_str(fmtstr), &args)?);
+                return Ok(val);
             }
         }
 
@@ -523,13 +1277,20 @@ impl Evaler for PrintFunc {
             if i>0 { out.push(' '); }
             match a {
                 EExpr(e_i) => {
-                    val = get_expr!(slab.ps,e_i).eval(slab,ns)?;
-                    out.push_str(&val.to_string());
+                    match get_expr!(slab.ps,e_i)._eval_val(slab,ns,locals)? {
+                        Val::Num(f) => {
+                            val = f;
+                            out.push_str(&f.to_string());
+                        }
+                        Val::Str(s) => out.push_str(&s),
+                        arr@Val::Arr(_) => out.push_str(&arr.to_string()),
+                    }
                 }
                 EStr(s) => out.push_str(&process_str(s))
             }
         }
-        eprintln!("{}", out);
+        out.push('\n');
+        ns.print(&out);
 
         Ok(val)
     }
@@ -543,10 +1304,34 @@ impl Evaler for Instruction {
 
             IVar(s) => { dst.insert(s.clone()); }
             IFunc{name, ..} => { dst.insert(name.clone()); }
+            IIndex{name, idx} => {
+                dst.insert(name.clone());
+                let iconst : Instruction;
+                ic_to_instr!(slab.cs,iconst,idx)._var_names(slab,dst);
+            }
+            INativeFunc{args:ics, ..} | IFuncStat{args:ics, ..} => {
+                let mut iconst : Instruction;
+                for ic in ics {
+                    ic_to_instr!(slab.cs,iconst,ic)._var_names(slab,dst);
+                }
+            }
+            IFuncStr{args:xis, ..} => {
+                for xi in xis {
+                    get_expr!(slab.ps,xi)._var_names(slab,dst);
+                }
+            }
+            IValExpr(xi) => get_expr!(slab.ps,xi)._var_names(slab,dst),
+            INamespaceFirst{name, args:ics, ..} => {
+                dst.insert(name.clone());
+                let mut iconst : Instruction;
+                for ic in ics {
+                    ic_to_instr!(slab.cs,iconst,ic)._var_names(slab,dst);
+                }
+            }
 
             IConst(_) => (),
 
-            INeg(ii) | INot(ii) | IInv(ii) | IFuncInt(ii) | IFuncCeil(ii) | IFuncFloor(ii) | IFuncAbs(ii) | IFuncSign(ii) | IFuncSin(ii) | IFuncCos(ii) | IFuncTan(ii) | IFuncASin(ii) | IFuncACos(ii) | IFuncATan(ii) | IFuncSinH(ii) | IFuncCosH(ii) | IFuncTanH(ii) | IFuncASinH(ii) | IFuncACosH(ii) | IFuncATanH(ii) => get_instr!(slab.cs,ii)._var_names(slab,dst),
+            INeg(ii) | INot(ii) | IInv(ii) | IFuncInt(ii) | IFuncCeil(ii) | IFuncFloor(ii) | IFuncAbs(ii) | IFuncSign(ii) | IFuncSin(ii) | IFuncCos(ii) | IFuncTan(ii) | IFuncSinD(ii) | IFuncCosD(ii) | IFuncTanD(ii) | IFuncASin(ii) | IFuncACos(ii) | IFuncATan(ii) | IFuncSinH(ii) | IFuncCosH(ii) | IFuncTanH(ii) | IFuncASinH(ii) | IFuncACosH(ii) | IFuncATanH(ii) | IFuncSqrt(ii) | IFuncCbrt(ii) | IFuncExp(ii) | IFuncLn(ii) | IFuncLog2(ii) | IFuncLog10(ii) | IFuncTrunc(ii) | IFuncFrac(ii) | IFuncDeg(ii) | IFuncRad(ii) | IFuncErf(ii) | IFuncGamma(ii) | IFuncLGamma(ii) => get_instr!(slab.cs,ii)._var_names(slab,dst),
 
             ILT(lic,ric) | ILTE(lic,ric) | IEQ(lic,ric) | INE(lic,ric) | IGTE(lic,ric) | IGT(lic,ric) | IMod{dividend:lic, divisor:ric} | IExp{base:lic, power:ric} | IFuncLog{base:lic, of:ric} | IFuncRound{modulus:lic, of:ric} => {
                 let mut iconst : Instruction;
@@ -560,138 +1345,322 @@ impl Evaler for Instruction {
                 ic_to_instr!(slab.cs,iconst,ric)._var_names(slab,dst);
             }
 
+            ICmpChain(first, rest) => {
+                let mut iconst : Instruction;
+                ic_to_instr!(slab.cs,iconst,first)._var_names(slab,dst);
+                for (_,ic) in rest {
+                    ic_to_instr!(slab.cs,iconst,ic)._var_names(slab,dst);
+                }
+            }
+
+            ILocal(_) => (),
+            ILet{value, body, ..} => {
+                let mut iconst : Instruction;
+                ic_to_instr!(slab.cs,iconst,value)._var_names(slab,dst);
+                ic_to_instr!(slab.cs,iconst,body)._var_names(slab,dst);
+            }
+            ILetFunc{func, body} => {
+                if let Some(Some(def)) = slab.cs.funcs.get(*func) {
+                    get_instr!(slab.cs,def)._var_names(slab,dst);
+                }
+                let iconst : Instruction;
+                ic_to_instr!(slab.cs,iconst,body)._var_names(slab,dst);
+            }
+            IUserFunc{args, ..} => {
+                let mut iconst : Instruction;
+                for ic in args {
+                    ic_to_instr!(slab.cs,iconst,ic)._var_names(slab,dst);
+                }
+            }
+
+            ITernary{cond, then, otherwise} | IFuncIf{cond, then, otherwise} => {
+                get_instr!(slab.cs,cond)._var_names(slab,dst);
+                let mut iconst : Instruction;
+                ic_to_instr!(slab.cs,iconst,then)._var_names(slab,dst);
+                ic_to_instr!(slab.cs,iconst,otherwise)._var_names(slab,dst);
+            }
+            IFuncATan2(a, b) | IFuncHypot(a, b) | IFuncPow(a, b) | IFuncFMod(a, b) => {
+                let mut iconst : Instruction;
+                ic_to_instr!(slab.cs,iconst,a)._var_names(slab,dst);
+                ic_to_instr!(slab.cs,iconst,b)._var_names(slab,dst);
+            }
+            IFuncClamp{x, min, max} => {
+                let mut iconst : Instruction;
+                ic_to_instr!(slab.cs,iconst,x)._var_names(slab,dst);
+                ic_to_instr!(slab.cs,iconst,min)._var_names(slab,dst);
+                ic_to_instr!(slab.cs,iconst,max)._var_names(slab,dst);
+            }
+            IFuncSum{start, end, expr, ..} | IFuncProd{start, end, expr, ..} => {
+                let mut iconst : Instruction;
+                ic_to_instr!(slab.cs,iconst,start)._var_names(slab,dst);
+                ic_to_instr!(slab.cs,iconst,end)._var_names(slab,dst);
+                ic_to_instr!(slab.cs,iconst,expr)._var_names(slab,dst);
+            }
+
+            #[cfg(feature="bitwise")]
+            IBitNot(ii) => get_instr!(slab.cs,ii)._var_names(slab,dst),
+            #[cfg(feature="bitwise")]
+            IBitAnd(lic,ric) | IBitOr(lic,ric) | IBitXor(lic,ric) | IShl(lic,ric) | IShr(lic,ric) | IIntDiv(lic,ric) => {
+                let mut iconst : Instruction;
+                ic_to_instr!(slab.cs,iconst,lic)._var_names(slab,dst);
+                ic_to_instr!(slab.cs,iconst,ric)._var_names(slab,dst);
+            }
+
             IPrintFunc(pf) => pf._var_names(slab,dst),
         }
     }
-    fn eval(&self, slab:&Slab, ns:&mut impl EvalNamespace) -> Result<f64,Error> {
+    fn _eval(&self, slab:&Slab, ns:&mut impl EvalNamespace, locals:&mut Locals) -> Result<f64,Error> {
         match self {
             // I have manually ordered these match arms in a way that I feel should deliver good performance.
             // (I don't think this ordering actually affects the generated code, though.)
 
             IMul(li,ric) => {
-                Ok( eval_compiled_ref!(get_instr!(slab.cs,li), slab, ns) *
-                    eval_ic_ref!(ric,slab,ns) )
+                Ok( eval_instr_ref!(get_instr!(slab.cs,li), slab, ns, locals) *
+                    eval_ic_ref!(ric, slab, ns, locals) )
             }
             IAdd(li,ric) => {
-                Ok( eval_compiled_ref!(get_instr!(slab.cs,li), slab, ns) +
-                    eval_ic_ref!(ric, slab, ns) )
+                Ok( eval_instr_ref!(get_instr!(slab.cs,li), slab, ns, locals) +
+                    eval_ic_ref!(ric, slab, ns, locals) )
             }
             IExp{base, power} => {
-                Ok( eval_ic_ref!(base, slab, ns).powf(
-                    eval_ic_ref!(power, slab, ns) ) )
+                Ok( eval_ic_ref!(base, slab, ns, locals).powf(
+                    eval_ic_ref!(power, slab, ns, locals) ) )
             }
 
-            INeg(i) => Ok(-eval_compiled_ref!(get_instr!(slab.cs,i), slab, ns)),
-            IInv(i) => Ok(1.0/eval_compiled_ref!(get_instr!(slab.cs,i), slab, ns)),
+            INeg(i) => Ok(-eval_instr_ref!(get_instr!(slab.cs,i), slab, ns, locals)),
+            IInv(i) => Ok(1.0/eval_instr_ref!(get_instr!(slab.cs,i), slab, ns, locals)),
+
+            ILocal(slot) => Ok(locals.get(*slot)),
+            ILet{slot, value, body} => {
+                let val = eval_ic_ref!(value, slab, ns, locals);
+                with_local(locals, *slot, val, |locals| Ok(eval_ic_ref!(body, slab, ns, locals)))
+            }
+            ILetFunc{body, ..} => Ok(eval_ic_ref!(body, slab, ns, locals)),
+            IUserFunc{func, body, args:ics} => {
+                let mut args = Vec::with_capacity(ics.len());
+                for ic in ics {
+                    args.push( eval_ic_ref!(ic, slab, ns, locals) );
+                }
+                let mut frame = Frame::call(locals, slab, *func, &args)?;
+                Ok(eval_instr_ref!(get_instr!(slab.cs,body), slab, ns, &mut frame))
+            }
 
             IVar(name) => eval_var!(ns, name, Vec::new(), unsafe{ &mut *(&slab.ps.char_buf as *const _ as *mut _) }),
             IFunc{name, args:ics} => {
                 let mut args = Vec::with_capacity(ics.len());
                 for ic in ics {
-                    args.push( eval_ic_ref!(ic, slab, ns) );
+                    args.push( eval_ic_ref!(ic, slab, ns, locals) );
                 }
                 eval_var!(ns, name, args, unsafe{ &mut *(&slab.ps.char_buf as *const _ as *mut _) })
             },
+            IIndex{name, idx} => {
+                let i = eval_ic_ref!(idx, slab, ns, locals);
+                let keybuf = &mut String::new();
+                match ns.lookup(name, vec![i], keybuf) {
+                    Some(f) => Ok(f),
+                    None => index_var(ns, name, i, keybuf),
+                }
+            },
+            INativeFunc{func, args:ics} => {
+                let mut args = Vec::with_capacity(ics.len());
+                for ic in ics {
+                    args.push( eval_ic_ref!(ic, slab, ns, locals) );
+                }
+                Ok((func.func)(&args))
+            },
+            INamespaceFirst{name, args:ics, slot, builtin} => {
+                let mut args = Vec::with_capacity(ics.len());
+                for ic in ics {
+                    args.push( eval_ic_ref!(ic, slab, ns, locals) );
+                }
+                eval_namespace_first(ns, locals, name, args, *slot, unsafe{ &mut *(&slab.ps.char_buf as *const _ as *mut _) },
+                                     |ns, locals| Ok(eval_instr_ref!(get_instr!(slab.cs,builtin), slab, ns, locals)))
+            },
 
             IFuncLog{base:baseic, of:ofic} => {
-                let base = eval_ic_ref!(baseic, slab, ns);
-                let of = eval_ic_ref!(ofic, slab, ns);
+                let base = eval_ic_ref!(baseic, slab, ns, locals);
+                let of = eval_ic_ref!(ofic, slab, ns, locals);
                 Ok(log(base,of))
             }
 
-            IFuncSin(i) => Ok( eval_compiled_ref!(get_instr!(slab.cs,i), slab, ns).sin() ),
-            IFuncCos(i) => Ok( eval_compiled_ref!(get_instr!(slab.cs,i), slab, ns).cos() ),
-            IFuncTan(i) => Ok( eval_compiled_ref!(get_instr!(slab.cs,i), slab, ns).tan() ),
-            IFuncASin(i) => Ok( eval_compiled_ref!(get_instr!(slab.cs,i), slab, ns).asin() ),
-            IFuncACos(i) => Ok( eval_compiled_ref!(get_instr!(slab.cs,i), slab, ns).acos() ),
-            IFuncATan(i) => Ok( eval_compiled_ref!(get_instr!(slab.cs,i), slab, ns).atan() ),
-            IFuncSinH(i) => Ok( eval_compiled_ref!(get_instr!(slab.cs,i), slab, ns).sinh() ),
-            IFuncCosH(i) => Ok( eval_compiled_ref!(get_instr!(slab.cs,i), slab, ns).cosh() ),
-            IFuncTanH(i) => Ok( eval_compiled_ref!(get_instr!(slab.cs,i), slab, ns).tanh() ),
-            IFuncASinH(i) => Ok( eval_compiled_ref!(get_instr!(slab.cs,i), slab, ns).asinh() ),
-            IFuncACosH(i) => Ok( eval_compiled_ref!(get_instr!(slab.cs,i), slab, ns).acosh() ),
-            IFuncATanH(i) => Ok( eval_compiled_ref!(get_instr!(slab.cs,i), slab, ns).atanh() ),
+            IFuncSin(i) => Ok( eval_instr_ref!(get_instr!(slab.cs,i), slab, ns, locals).sin() ),
+            IFuncCos(i) => Ok( eval_instr_ref!(get_instr!(slab.cs,i), slab, ns, locals).cos() ),
+            IFuncTan(i) => Ok( eval_instr_ref!(get_instr!(slab.cs,i), slab, ns, locals).tan() ),
+            IFuncSinD(i) => Ok( sind(eval_instr_ref!(get_instr!(slab.cs,i), slab, ns, locals)) ),
+            IFuncCosD(i) => Ok( cosd(eval_instr_ref!(get_instr!(slab.cs,i), slab, ns, locals)) ),
+            IFuncTanD(i) => Ok( tand(eval_instr_ref!(get_instr!(slab.cs,i), slab, ns, locals)) ),
+            IFuncASin(i) => Ok( eval_instr_ref!(get_instr!(slab.cs,i), slab, ns, locals).asin() ),
+            IFuncACos(i) => Ok( eval_instr_ref!(get_instr!(slab.cs,i), slab, ns, locals).acos() ),
+            IFuncATan(i) => Ok( eval_instr_ref!(get_instr!(slab.cs,i), slab, ns, locals).atan() ),
+            IFuncSinH(i) => Ok( eval_instr_ref!(get_instr!(slab.cs,i), slab, ns, locals).sinh() ),
+            IFuncCosH(i) => Ok( eval_instr_ref!(get_instr!(slab.cs,i), slab, ns, locals).cosh() ),
+            IFuncTanH(i) => Ok( eval_instr_ref!(get_instr!(slab.cs,i), slab, ns, locals).tanh() ),
+            IFuncASinH(i) => Ok( eval_instr_ref!(get_instr!(slab.cs,i), slab, ns, locals).asinh() ),
+            IFuncACosH(i) => Ok( eval_instr_ref!(get_instr!(slab.cs,i), slab, ns, locals).acosh() ),
+            IFuncATanH(i) => Ok( eval_instr_ref!(get_instr!(slab.cs,i), slab, ns, locals).atanh() ),
+
+            IFuncSqrt(i) => Ok( eval_instr_ref!(get_instr!(slab.cs,i), slab, ns, locals).sqrt() ),
+            IFuncCbrt(i) => Ok( eval_instr_ref!(get_instr!(slab.cs,i), slab, ns, locals).cbrt() ),
+            IFuncExp(i) => Ok( eval_instr_ref!(get_instr!(slab.cs,i), slab, ns, locals).exp() ),
+            IFuncLn(i) => Ok( eval_instr_ref!(get_instr!(slab.cs,i), slab, ns, locals).ln() ),
+            IFuncLog2(i) => Ok( eval_instr_ref!(get_instr!(slab.cs,i), slab, ns, locals).log2() ),
+            IFuncLog10(i) => Ok( eval_instr_ref!(get_instr!(slab.cs,i), slab, ns, locals).log10() ),
+            IFuncTrunc(i) => Ok( eval_instr_ref!(get_instr!(slab.cs,i), slab, ns, locals).trunc() ),
+            IFuncFrac(i) => Ok( eval_instr_ref!(get_instr!(slab.cs,i), slab, ns, locals).fract() ),
+            IFuncDeg(i) => Ok( eval_instr_ref!(get_instr!(slab.cs,i), slab, ns, locals).to_degrees() ),
+            IFuncRad(i) => Ok( eval_instr_ref!(get_instr!(slab.cs,i), slab, ns, locals).to_radians() ),
+            IFuncErf(i) => Ok( erf(eval_instr_ref!(get_instr!(slab.cs,i), slab, ns, locals)) ),
+            IFuncGamma(i) => Ok( gamma(eval_instr_ref!(get_instr!(slab.cs,i), slab, ns, locals)) ),
+            IFuncLGamma(i) => Ok( lgamma(eval_instr_ref!(get_instr!(slab.cs,i), slab, ns, locals)) ),
+
+            IFuncATan2(a, b) => {
+                let a = eval_ic_ref!(a, slab, ns, locals);
+                let b = eval_ic_ref!(b, slab, ns, locals);
+                Ok(a.atan2(b))
+            }
+            IFuncHypot(a, b) => {
+                let a = eval_ic_ref!(a, slab, ns, locals);
+                let b = eval_ic_ref!(b, slab, ns, locals);
+                Ok(a.hypot(b))
+            }
+            IFuncPow(a, b) => {
+                let a = eval_ic_ref!(a, slab, ns, locals);
+                let b = eval_ic_ref!(b, slab, ns, locals);
+                Ok(a.powf(b))
+            }
+            IFuncFMod(a, b) => {
+                let a = eval_ic_ref!(a, slab, ns, locals);
+                let b = eval_ic_ref!(b, slab, ns, locals);
+                Ok(a%b)
+            }
+            IFuncClamp{x, min, max} => {
+                let x = eval_ic_ref!(x, slab, ns, locals);
+                let min = eval_ic_ref!(min, slab, ns, locals);
+                let max = eval_ic_ref!(max, slab, ns, locals);
+                Ok(clamp(x,min,max))
+            }
 
             IFuncRound{modulus:modic, of:ofic} => {
-                let modulus = eval_ic_ref!(modic, slab, ns);
-                let of = eval_ic_ref!(ofic, slab, ns);
+                let modulus = eval_ic_ref!(modic, slab, ns, locals);
+                let of = eval_ic_ref!(ofic, slab, ns, locals);
                 Ok( (of/modulus).round() * modulus )
             }
             IMod{dividend, divisor} => {
-                Ok( eval_ic_ref!(dividend, slab, ns) %
-                    eval_ic_ref!(divisor, slab, ns) )
-            }
-
-            IFuncAbs(i) => Ok( eval_compiled_ref!(get_instr!(slab.cs,i), slab, ns).abs() ),
-            IFuncSign(i) => Ok( eval_compiled_ref!(get_instr!(slab.cs,i), slab, ns).signum() ),
-            IFuncInt(i) => Ok( eval_compiled_ref!(get_instr!(slab.cs,i), slab, ns).trunc() ),
-            IFuncCeil(i) => Ok( eval_compiled_ref!(get_instr!(slab.cs,i), slab, ns).ceil() ),
-            IFuncFloor(i) => Ok( eval_compiled_ref!(get_instr!(slab.cs,i), slab, ns).floor() ),
-            IFuncMin(li,ric) => {
-                let left = eval_compiled_ref!(get_instr!(slab.cs,li), slab, ns);
-                let right = eval_ic_ref!(ric, slab, ns);
-                if left.is_nan() || right.is_nan() { return Ok(std::f64::NAN) }  // I need to implement NAN checks myself because the f64.min() function says that if one number is NaN, the other will be returned.
-                if left<right {
-                    Ok(left)
-                } else {
-                    Ok(right)
-                }
+                Ok( eval_ic_ref!(dividend, slab, ns, locals) %
+                    eval_ic_ref!(divisor, slab, ns, locals) )
             }
-            IFuncMax(li,ric) => {
-                let left = eval_compiled_ref!(get_instr!(slab.cs,li), slab, ns);
-                let right = eval_ic_ref!(ric, slab, ns);
-                if left.is_nan() || right.is_nan() { return Ok(std::f64::NAN) }
-                if left>right {
-                    Ok(left)
-                } else {
-                    Ok(right)
+
+            IFuncAbs(i) => Ok( eval_instr_ref!(get_instr!(slab.cs,i), slab, ns, locals).abs() ),
+            IFuncSign(i) => Ok( eval_instr_ref!(get_instr!(slab.cs,i), slab, ns, locals).signum() ),
+            IFuncInt(i) => Ok( eval_instr_ref!(get_instr!(slab.cs,i), slab, ns, locals).trunc() ),
+            IFuncCeil(i) => Ok( eval_instr_ref!(get_instr!(slab.cs,i), slab, ns, locals).ceil() ),
+            IFuncFloor(i) => Ok( eval_instr_ref!(get_instr!(slab.cs,i), slab, ns, locals).floor() ),
+            // Any argument might be an array, which eval_val() reduces:
+            IFuncMin(..) | IFuncMax(..) => self._eval_val(slab,ns,locals)?.num(),
+            IFuncStat{func, skip_nan, ns_first, args:ics} => {
+                let mut st = Stat::new(*func, *skip_nan);
+                match ns_first {
+                    None => for ic in ics {
+                        push_flat(&mut st, eval_val_ic(ic, slab, ns, locals)?)?;
+                    },
+                    Some(name) => {
+                        let mut vals = Vec::with_capacity(ics.len());
+                        for ic in ics {
+                            vals.push(eval_val_ic(ic, slab, ns, locals)?);
+                        }
+                        if let Some(f) = lookup_stat(ns, name, &vals, unsafe{ &mut *(&slab.ps.char_buf as *const _ as *mut _) }) { return Ok(f); }
+                        for val in vals { push_flat(&mut st, val)?; }
+                    }
                 }
+                Ok(st.finish())
             }
+            IFuncStr{func, args:xis} => eval_str_func(*func, xis, slab, ns, locals)?.num(),
+            IValExpr(xi) => match slab.ps.exprs.get(xi.0) {
+                Some(expr) => expr._eval(slab,ns,locals),
+                None => Err(Error::Unreachable),
+            },
 
 
             IEQ(left, right) => {
-                Ok( bool_to_f64!(f64_eq!(eval_ic_ref!(left, slab, ns),
-                                         eval_ic_ref!(right, slab, ns))) )
+                Ok( bool_to_f64!(f64_eq!(eval_ic_ref!(left, slab, ns, locals),
+                                         eval_ic_ref!(right, slab, ns, locals))) )
             }
             INE(left, right) => {
-                Ok( bool_to_f64!(f64_ne!(eval_ic_ref!(left, slab, ns),
-                                         eval_ic_ref!(right, slab, ns))) )
+                Ok( bool_to_f64!(f64_ne!(eval_ic_ref!(left, slab, ns, locals),
+                                         eval_ic_ref!(right, slab, ns, locals))) )
             }
             ILT(left, right) => {
-                Ok( bool_to_f64!(eval_ic_ref!(left, slab, ns) <
-                                 eval_ic_ref!(right, slab, ns)) )
+                Ok( bool_to_f64!(eval_ic_ref!(left, slab, ns, locals) <
+                                 eval_ic_ref!(right, slab, ns, locals)) )
             }
             ILTE(left, right) => {
-                Ok( bool_to_f64!(eval_ic_ref!(left, slab, ns) <=
-                                 eval_ic_ref!(right, slab, ns)) )
+                Ok( bool_to_f64!(eval_ic_ref!(left, slab, ns, locals) <=
+                                 eval_ic_ref!(right, slab, ns, locals)) )
             }
             IGTE(left, right) => {
-                Ok( bool_to_f64!(eval_ic_ref!(left, slab, ns) >=
-                                 eval_ic_ref!(right, slab, ns)) )
+                Ok( bool_to_f64!(eval_ic_ref!(left, slab, ns, locals) >=
+                                 eval_ic_ref!(right, slab, ns, locals)) )
             }
             IGT(left, right) => {
-                Ok( bool_to_f64!(eval_ic_ref!(left, slab, ns) >
-                                 eval_ic_ref!(right, slab, ns)) )
+                Ok( bool_to_f64!(eval_ic_ref!(left, slab, ns, locals) >
+                                 eval_ic_ref!(right, slab, ns, locals)) )
+            }
+            ICmpChain(first, rest) => {
+                let mut left = eval_ic_ref!(first, slab, ns, locals);
+                for (op,ic) in rest {
+                    let right = eval_ic_ref!(ic, slab, ns, locals);
+                    if f64_eq!(op.binaryop_eval(Some(&left), Some(&right)),0.0) { return Ok(0.0); }
+                    left = right;
+                }
+                Ok(1.0)
             }
 
-            INot(i) => Ok(bool_to_f64!(f64_eq!(eval_compiled_ref!(get_instr!(slab.cs,i), slab, ns),0.0))),
+            INot(i) => Ok(bool_to_f64!(f64_eq!(eval_instr_ref!(get_instr!(slab.cs,i), slab, ns, locals),0.0))),
             IAND(lefti, rightic) => {
-                let left = eval_compiled_ref!(get_instr!(slab.cs,lefti), slab, ns);
+                let left = eval_instr_ref!(get_instr!(slab.cs,lefti), slab, ns, locals);
                 if f64_eq!(left,0.0) { Ok(left) }
                 else {
-                    Ok(eval_ic_ref!(rightic, slab, ns))
+                    Ok(eval_ic_ref!(rightic, slab, ns, locals))
                 }
             }
             IOR(lefti, rightic) => {
-                let left = eval_compiled_ref!(get_instr!(slab.cs,lefti), slab, ns);
+                let left = eval_instr_ref!(get_instr!(slab.cs,lefti), slab, ns, locals);
                 if f64_ne!(left,0.0) { Ok(left) }
                 else {
-                    Ok(eval_ic_ref!(rightic, slab, ns))
+                    Ok(eval_ic_ref!(rightic, slab, ns, locals))
                 }
             }
 
 
-            IPrintFunc(pf) => pf.eval(slab,ns),
+            ITernary{cond, then, otherwise} | IFuncIf{cond, then, otherwise} => {
+                let c = eval_instr_ref!(get_instr!(slab.cs,cond), slab, ns, locals);
+                if f64_eq!(c,0.0) { Ok(eval_ic_ref!(otherwise, slab, ns, locals)) }
+                else { Ok(eval_ic_ref!(then, slab, ns, locals)) }
+            }
+            IFuncSum{slot, start, end, expr} | IFuncProd{slot, start, end, expr} => {
+                let is_sum = matches!(self, IFuncSum{..});
+                let start = eval_ic_ref!(start, slab, ns, locals);
+                let end = eval_ic_ref!(end, slab, ns, locals);
+                eval_series(slab, locals, is_sum, *slot, start, end, |locals| Ok(eval_ic_ref!(expr, slab, ns, locals)))
+            }
+
+
+            #[cfg(feature="bitwise")]
+            IBitNot(i) => Ok(!(eval_instr_ref!(get_instr!(slab.cs,i), slab, ns, locals) as i64) as f64),
+            #[cfg(feature="bitwise")]
+            IBitAnd(left, right) => Ok(bitwise(EBitAnd, eval_ic_ref!(left, slab, ns, locals), eval_ic_ref!(right, slab, ns, locals))),
+            #[cfg(feature="bitwise")]
+            IBitOr(left, right) => Ok(bitwise(EBitOr, eval_ic_ref!(left, slab, ns, locals), eval_ic_ref!(right, slab, ns, locals))),
+            #[cfg(feature="bitwise")]
+            IBitXor(left, right) => Ok(bitwise(EBitXor, eval_ic_ref!(left, slab, ns, locals), eval_ic_ref!(right, slab, ns, locals))),
+            #[cfg(feature="bitwise")]
+            IShl(left, right) => Ok(bitwise(EShl, eval_ic_ref!(left, slab, ns, locals), eval_ic_ref!(right, slab, ns, locals))),
+            #[cfg(feature="bitwise")]
+            IShr(left, right) => Ok(bitwise(EShr, eval_ic_ref!(left, slab, ns, locals), eval_ic_ref!(right, slab, ns, locals))),
+            #[cfg(feature="bitwise")]
+            IIntDiv(left, right) => Ok(bitwise(EIntDiv, eval_ic_ref!(left, slab, ns, locals), eval_ic_ref!(right, slab, ns, locals))),
+
+            IPrintFunc(pf) => pf._eval(slab,ns,locals),
 
 
             // Put these last because you should be using the eval_compiled*!() macros to eliminate function calls.
@@ -700,5 +1669,87 @@ impl Evaler for Instruction {
             IUnsafeVar{ptr, ..} => unsafe { Ok(**ptr) },
         }
     }
+    fn _eval_val(&self, slab:&Slab, ns:&mut impl EvalNamespace, locals:&mut Locals) -> Result<Val,Error> {
+        // Variables can hold arrays, so the arithmetic instructions need to handle them too:
+        let binop = |op:BinaryOp, l:&IC, r:&IC, ns:&mut _, locals:&mut _| op.binaryop_eval_val(Some(&eval_val_ic(l,slab,ns,locals)?), Some(&eval_val_ic(r,slab,ns,locals)?));
+        match self {
+            IValExpr(xi) => match slab.ps.exprs.get(xi.0) {
+                Some(expr) => expr._eval_val(slab,ns,locals),
+                None => Err(Error::Unreachable),
+            },
+            IFuncStr{func, args:xis} => eval_str_func(*func, xis, slab, ns, locals),
+            IFuncMin(li,ric) | IFuncMax(li,ric) => {
+                let is_max = matches!(self, IFuncMax(..));
+                let left = min_max(get_instr!(slab.cs,li)._eval_val(slab,ns,locals)?, is_max)?;
+                let right = min_max(eval_val_ic(ric, slab, ns, locals)?, is_max)?;
+                if left.is_nan() || right.is_nan() { return Ok(Val::Num(std::f64::NAN)) }
+                Ok(Val::Num(if is_max { left.max(right) } else { left.min(right) }))
+            }
+
+            IVar(name) => match ns.lookup_val(name, Vec::new(), &mut String::new()) {
+                Some(v) => Ok(v),
+                None => Err(Error::Undefined(name.to_string())),
+            },
+            IFunc{name, args:ics} => {
+                let mut args = Vec::with_capacity(ics.len());
+                for ic in ics {
+                    args.push( eval_ic_ref!(ic, slab, ns, locals) );
+                }
+                match ns.lookup_val(name, args, &mut String::new()) {
+                    Some(v) => Ok(v),
+                    None => Err(Error::Undefined(name.to_string())),
+                }
+            }
+            IIndex{name, idx} => {
+                let i = eval_ic_ref!(idx, slab, ns, locals);
+                let keybuf = &mut String::new();
+                match ns.lookup_val(name, vec![i], keybuf) {
+                    Some(v) => Ok(v),
+                    None => Ok(Val::Num(index_var(ns, name, i, keybuf)?)),
+                }
+            }
+
+            IAdd(li,ric) => binop(EAdd, &IC::I(*li), ric, ns, locals),
+            IMul(li,ric) => binop(EMul, &IC::I(*li), ric, ns, locals),
+            IMod{dividend, divisor} => binop(EMod, dividend, divisor, ns, locals),
+            IExp{base, power} => binop(EExp, base, power, ns, locals),
+            ILT(lic,ric) => binop(ELT, lic, ric, ns, locals),
+            ILTE(lic,ric) => binop(ELTE, lic, ric, ns, locals),
+            IEQ(lic,ric) => binop(EEQ, lic, ric, ns, locals),
+            INE(lic,ric) => binop(ENE, lic, ric, ns, locals),
+            IGTE(lic,ric) => binop(EGTE, lic, ric, ns, locals),
+            IGT(lic,ric) => binop(EGT, lic, ric, ns, locals),
+            IAND(li,ric) | IOR(li,ric) => {
+                let op = if let IAND(..) = self { EAND } else { EOR };
+                match get_instr!(slab.cs,li)._eval_val(slab,ns,locals)? {
+                    // Numbers short-circuit, just like eval():
+                    Val::Num(l) => {
+                        if f64_eq!(l,0.0) == (op==EAND) { Ok(Val::Num(l)) }
+                        else { eval_val_ic(ric, slab, ns, locals) }
+                    }
+                    left => op.binaryop_eval_val(Some(&left), Some(&eval_val_ic(ric, slab, ns, locals)?)),
+                }
+            }
+
+            ITernary{cond, then, otherwise} | IFuncIf{cond, then, otherwise} => {
+                let c = eval_instr_ref!(get_instr!(slab.cs,cond), slab, ns, locals);
+                if f64_eq!(c,0.0) { eval_val_ic(otherwise, slab, ns, locals) }
+                else { eval_val_ic(then, slab, ns, locals) }
+            }
+
+            INeg(i) => elementwise(get_instr!(slab.cs,i)._eval_val(slab,ns,locals)?, |x| -x),
+            IInv(i) => elementwise(get_instr!(slab.cs,i)._eval_val(slab,ns,locals)?, |x| 1.0/x),
+            INot(i) => elementwise(get_instr!(slab.cs,i)._eval_val(slab,ns,locals)?, |x| bool_to_f64!(f64_eq!(x,0.0))),
+
+            _ => Ok(Val::Num(self._eval(slab,ns,locals)?)),
+        }
+    }
+}
+
+fn eval_val_ic(ic:&IC, slab:&Slab, ns:&mut impl EvalNamespace, locals:&mut Locals) -> Result<Val,Error> {
+    match ic {
+        IC::C(c) => Ok(Val::Num(*c)),
+        IC::I(i) => get_instr!(slab.cs,i)._eval_val(slab,ns,locals),
+    }
 }
 
@@ -17,6 +17,10 @@
 //!   Each layer is a separate 'scope'.  Higher layers take precedence
 //!   over lower layers.  Very useful for creating scoped higher-level-languages.
 //!   Type alias: [LayeredStringToF64Namespace](#layeredstringtof64namespace)
+//! * [`StringToValNamespace`](#stringtovalnamespace) -- Like
+//!   `StringToF64Namespace`, but variables can also be strings or arrays.
+//! * [`PrintSink`](#printsink) -- Wraps another Namespace and sends the output
+//!   of `print()` somewhere other than stderr, or nowhere at all.
 //!
 //! # Examples
 //!
@@ -169,6 +173,56 @@
 //! }
 //! ```
 //!
+//! ## StringToValNamespace
+//! ```
+//! use fasteval::{Evaler, Val};
+//!
+//! fn main() -> Result<(), fasteval::Error> {
+//!     let mut ns = fasteval::StringToValNamespace::new();
+//!     ns.insert("status".to_string(), Val::from("OPEN"));
+//!     ns.insert("count".to_string(), Val::Num(3.0));
+//!
+//!     let val = fasteval::ez_eval(r#"status == "OPEN" && count > 2"#, &mut ns)?;
+//!     assert_eq!(val, 1.0);
+//!
+//!     // Use eval_val() to get a string result:
+//!     let mut slab = fasteval::Slab::new();
+//!     let expr = fasteval::Parser::new().parse(r#"lower(status) + "/" + count"#, &mut slab.ps)?.from(&slab.ps);
+//!     assert_eq!(expr.eval_val(&slab, &mut ns)?, Val::from("open/3"));
+//!
+//!     // Arrays work the same way:
+//!     ns.insert("xs".to_string(), Val::from(vec![1.0, 2.0, 4.0]));
+//!     assert_eq!(fasteval::ez_eval("sum(xs * count) + xs[2]", &mut ns)?, 25.0);
+//!
+//!     Ok(())
+//! }
+//! ```
+//!
+//! ## PrintSink
+//! ```
+//! fn main() -> Result<(), fasteval::Error> {
+//!     let mut ns = fasteval::StringToF64Namespace::new();
+//!     ns.insert("x".to_string(), 2.0);
+//!
+//!     // Capture the output in a String:
+//!     let mut out = String::new();
+//!     let val = fasteval::ez_eval(r#"print("x is", x) + 1"#, &mut fasteval::PrintSink::to_buffer(&mut ns, &mut out))?;
+//!     assert_eq!(val, 3.0);
+//!     assert_eq!(out, "x is 2\n");
+//!
+//!     // Silently ignore print() in untrusted expressions:
+//!     let val = fasteval::ez_eval(r#"print("%.1f", x)"#, &mut fasteval::PrintSink::disabled(&mut ns))?;
+//!     assert_eq!(val, 2.0);
+//!
+//!     // Any `std::io::Write` works too, like `std::io::stdout()` or a file:
+//!     let mut bytes = Vec::<u8>::new();
+//!     fasteval::ez_eval(r#"print("%d", x)"#, &mut fasteval::PrintSink::to_writer(&mut ns, &mut bytes))?;
+//!     assert_eq!(bytes, b"2");
+//!
+//!     Ok(())
+//! }
+//! ```
+//!
 //! ## Custom Namespace Types
 //!
 //! If the pre-defined Namespace types aren't perfect for your application, you
@@ -188,8 +242,10 @@
 
 
 use crate::error::Error;
+use crate::evaler::Val;
 
 use std::collections::BTreeMap;
+use std::io;
 
 //---- Types:
 
@@ -199,6 +255,21 @@ pub trait EvalNamespace {
     ///
     /// May return cached values.
     fn lookup(&mut self, name:&str, args:Vec<f64>, keybuf:&mut String) -> Option<f64>;
+
+    /// Perform a variable/function lookup that might return a string or an array.
+    ///
+    /// This is used when evaluating expressions that contain strings, and
+    /// wherever an array can be used.
+    /// The default wraps `lookup()`, so only numbers are found.
+    fn lookup_val(&mut self, name:&str, args:Vec<f64>, keybuf:&mut String) -> Option<Val> {
+        self.lookup(name, args, keybuf).map(Val::Num)
+    }
+
+    /// Receives the output of the `print()` expression function.
+    ///
+    /// The default writes to stderr.  Override this (or use a
+    /// [`PrintSink`](struct.PrintSink.html)) to send it somewhere else.
+    fn print(&mut self, text:&str) { eprint!("{}", text); }
 }
 
 /// Cache operations for `EvalNamespace`s.
@@ -244,6 +315,16 @@ pub struct CachedCallbackNamespace<'a> {
     cb   :Box<dyn FnMut(&str, Vec<f64>)->Option<f64> + 'a>,  // I think a reference would be more efficient than a Box, but then I would need to use a funky 'let cb=|n|{}; Namespace::new(&cb)' syntax.  The Box results in a super convenient pass-the-cb-by-value API interface.
 }
 
+/// `PrintSink` wraps another Namespace, and sends the output of `print()` to
+/// a callback instead of stderr.  Lookups are passed through unchanged.
+///
+/// [See module-level documentation for example.](index.html#printsink)
+///
+pub struct PrintSink<'a,NS> where NS:EvalNamespace {
+    ns  :&'a mut NS,
+    sink:Box<dyn FnMut(&str) + 'a>,
+}
+
 //// I am commenting these out until I need them in real-life.
 //// (I don't want to add things to the public API until necessary.)
 // pub struct CachedLayeredNamespace<'a> {
@@ -284,6 +365,26 @@ impl EvalNamespace for StringToF64Namespace {
     }
 }
 
+/// Type alias for `BTreeMap<String,Val>`
+///
+/// String and array values are only visible where strings or arrays can be used.
+/// Numeric lookups only see the numbers.
+pub type StringToValNamespace = BTreeMap<String,Val>;
+impl EvalNamespace for StringToValNamespace {
+    #[inline]
+    fn lookup(&mut self, name:&str, args:Vec<f64>, keybuf:&mut String) -> Option<f64> {
+        match self.lookup_val(name, args, keybuf) {
+            Some(Val::Num(f)) => Some(f),
+            _ => None,
+        }
+    }
+    #[inline]
+    fn lookup_val(&mut self, name:&str, args:Vec<f64>, keybuf:&mut String) -> Option<Val> {
+        let key = key_from_nameargs(keybuf, name, &args);
+        self.get(key).cloned()
+    }
+}
+
 /// Type alias for `BTreeMap<&'static str,f64>`
 pub type StrToF64Namespace = BTreeMap<&'static str,f64>;
 impl EvalNamespace for StrToF64Namespace {
@@ -396,6 +497,48 @@ impl<'a> CachedCallbackNamespace<'a> {
     }
 }
 
+impl<NS> EvalNamespace for PrintSink<'_,NS> where NS:EvalNamespace {
+    #[inline]
+    fn lookup(&mut self, name:&str, args:Vec<f64>, keybuf:&mut String) -> Option<f64> {
+        self.ns.lookup(name, args, keybuf)
+    }
+    #[inline]
+    fn lookup_val(&mut self, name:&str, args:Vec<f64>, keybuf:&mut String) -> Option<Val> {
+        self.ns.lookup_val(name, args, keybuf)
+    }
+    /// Passes `text` to the sink.
+    #[inline]
+    fn print(&mut self, text:&str) { (self.sink)(text) }
+}
+impl<NS> Cached for PrintSink<'_,NS> where NS:EvalNamespace+Cached {
+    fn cache_create(&mut self, name:String, val:f64) -> Result<(),Error> { self.ns.cache_create(name, val) }
+    fn cache_set(&mut self, name:String, val:f64) { self.ns.cache_set(name, val) }
+    fn cache_clear(&mut self) { self.ns.cache_clear() }
+}
+impl<'a,NS> PrintSink<'a,NS> where NS:EvalNamespace {
+    /// Sends each piece of `print()` output to the `sink` callback.
+    #[inline]
+    pub fn new<F>(ns:&'a mut NS, sink:F) -> Self where F:FnMut(&str) + 'a {
+        PrintSink{ ns, sink:Box::new(sink) }
+    }
+    /// Appends `print()` output to `buf`.
+    #[inline]
+    pub fn to_buffer(ns:&'a mut NS, buf:&'a mut String) -> Self {
+        Self::new(ns, move |text| buf.push_str(text))
+    }
+    /// Writes `print()` output to `w`.  Write errors are ignored, since
+    /// `print()` is only a probe and shouldn't change the result.
+    #[inline]
+    pub fn to_writer<W>(ns:&'a mut NS, mut w:W) -> Self where W:io::Write + 'a {
+        Self::new(ns, move |text| { let _ = w.write_all(text.as_bytes()); })
+    }
+    /// Discards all `print()` output.  Useful for untrusted expressions.
+    #[inline]
+    pub fn disabled(ns:&'a mut NS) -> Self {
+        Self::new(ns, |_| ())
+    }
+}
+
 //// I am not ready to make this part of the public API yet.
 // impl EvalNamespace for CachedLayeredNamespace<'_> {
 //     fn lookup(&mut self, name:&str, args:Vec<f64>, keybuf:&mut String) -> Option<f64> {
@@ -9,8 +9,8 @@
 //! * `fasteval` is a good base for building higher-level languages.
 //! * Supports many built-in functions and constants.
 //! * Supports all the standard algebraic unary and binary operators (+ - * / ^ %),
-//!   as well as comparisons (< <= == != >= >) and logical operators (&& ||) with
-//!   short-circuit support.
+//!   as well as comparisons (< <= == != >= >), logical operators (&& ||) with
+//!   short-circuit support, and the ternary conditional operator (? :).
 //! * Easy integration into many different types of applications, including scoped evaluation.
 //! * Very fast performance.
 //!
@@ -24,13 +24,19 @@
 //!
 //! ```text
 //!   * print(...strings and values...) -- Prints to stderr.  Very useful to 'probe' an expression.
+//!                                        Use a `PrintSink` namespace to capture or disable the output.
 //!                                        Evaluates to the last value.
 //!                                        Example: `print("x is", x, "and y is", y)`
 //!                                        Example: `x + print("y:", y) + z == x+y+z`
+//!                                        If the first string contains '%', it's a printf-style
+//!                                        format for the remaining args, and no newline is added.
+//!                                        See the [`printf`](printf/index.html) module.
+//!                                        Example: `print("x=%.3f y=%5d\n", x, y)`
 //!
 //!   * log(base=10, val) -- Logarithm with optional 'base' as first argument.
 //!                          If not provided, 'base' defaults to '10'.
 //!                          Example: `log(100) + log(e(), 100)`
+//!   * ln(val)     * log2(val)     * log10(val)     * exp(val)
 //!
 //!   * e()  -- Euler's number (2.718281828459045)
 //!   * pi() -- π (3.141592653589793)
@@ -41,41 +47,125 @@
 //!   * round(modulus=1, val) -- Round with optional 'modulus' as first argument.
 //!                              Example: `round(1.23456) == 1  &&  round(0.001, 1.23456) == 1.235`
 //!
+//!   * trunc(val)
+//!   * frac(val)   -- The fractional part, with the sign of 'val':  `frac(-2.75) == -0.75`
+//!
 //!   * abs(val)
 //!   * sign(val)
 //!
+//!   * sqrt(val)     * cbrt(val)     * pow(base, exponent)
+//!   * hypot(x, y)   -- sqrt(x^2 + y^2), without overflow.
+//!   * fmod(x, y)    -- The remainder of x/y, with the sign of 'x'.  (The same as 'x % y'.)
+//!   * clamp(val, min, max)
+//!
+//!   * erf(val)      -- The error function.
+//!   * gamma(val)    -- The gamma function:  `gamma(n) == (n-1)!`
+//!   * lgamma(val)   -- ln(abs(gamma(val))), which doesn't overflow.
+//!
 //!   * min(val, ...) -- Example: `min(1, -2, 3, -4) == -4`
 //!   * max(val, ...) -- Example: `max(1, -2, 3, -4) == 3`
 //!
+//!   * sum(val, ...)       * mean(val, ...)       * median(val, ...)
+//!   * stddev(val, ...)    * variance(val, ...)   -- Sample statistics, with n-1 in the denominator.
+//!   * count(val, ...)     -- The number of arguments.
+//!   * percentile(p, val, ...) -- 'p' is from 0 to 100.  Interpolates linearly between values.
+//!                                Example: `median(3, 1, 2, 10) == 2.5  &&  percentile(25, 1, 2, 3, 4, 5) == 2`
+//!     Any NaN argument makes the result NaN.  The 'nan' variants (nansum, nanmean, nanmedian, nanstddev,
+//!     nanvariance, nancount, nanpercentile) skip NaN arguments instead.
+//!     min(), max() and the statistics also accept arrays, which count as one argument per element.
+//!
+//!   * str(val)     -- Converts a number to a string.
+//!   * len(s)       -- The number of characters in 's', or the number of elements in an array.
+//!   * upper(s)     * lower(s)
+//!   * contains(s, sub)     * startswith(s, prefix)     * endswith(s, suffix)
+//!                     Example: `startswith(upper(name), "PUMP") && len(name) < 10`
+//!
+//!   * if(cond, then, else) -- Only the selected argument is evaluated.
+//!                             Example: `if(x > 0, log(x), 0)`
+//!
+//!   * sum(var, start, end, expr)  -- Evaluates 'expr' for var = start, start+1, ... <= end.
+//!   * prod(var, start, end, expr)    'var' is only visible within 'expr'.
+//!                                    Example: `sum(i, 1, 10, i^2) == 385  &&  prod(k, 1, 5, k) == 120`
+//!                                    Limited to `Parser.iter_limit` iterations.
+//!     This is the form of sum() and prod() with exactly 4 args, the first of which is a bare
+//!     name that isn't a local variable.
+//!
 //!   * sin(radians)    * asin(val)
 //!   * cos(radians)    * acos(val)
 //!   * tan(radians)    * atan(val)
 //!   * sinh(val)       * asinh(val)
 //!   * cosh(val)       * acosh(val)
 //!   * tanh(val)       * atanh(val)
+//!   * atan2(y, x)
+//!   * deg(radians) -- Converts to degrees.
+//!   * rad(degrees) -- Converts to radians.
 //! ```
 //!
+//! Angles are in radians, unless `Parser.degrees` is set.
+//!
+//! Builtin names are matched before custom functions.  `Parser.builtins` can
+//! change that:  individual builtins can be disabled (so your namespace handles
+//! them instead), given extra names like `log10`, or set to ask your namespace first.
+//!
+//! `sum`, `mean`, `median`, `stddev`, `variance`, `count`, `percentile` and their
+//! `nan` variants used to be passed to the namespace, so they ask your namespace
+//! first by default, and only use the builtin if it doesn't define them.  Add
+//! `BuiltinRule::BuiltinFirst` rules to always use the builtins, which also lets
+//! calls with constant arguments be evaluated during compilation.
+//!
+//! Rust functions can also be registered with `Parser.native_funcs`.  These are
+//! called directly instead of through the namespace, and calls to pure functions
+//! with constant arguments are evaluated during compilation.
+//!
 //! ## Operators
 //!
-//! The `and` and `or` operators are enabled by default, but if your
-//! application wants to use those words for something else, they can be
-//! disabled by turning off the `alpha-keywords` feature (`cargo build --no-default-features`).
+//! The `and`, `or` and `not` operators and the `true` (`1`) and `false` (`0`)
+//! constants are enabled by default, but if your application wants to use
+//! those words for something else, they can be disabled by turning off the
+//! `alpha-keywords` feature (`cargo build --no-default-features`).  Like `!`,
+//! `not` applies to the value right after it:  `not x and y` means `(!x) && y`.
 //!
 //! ```text
 //! Listed in order of precedence:
 //!
 //!     (Highest Precedence) ^               Exponentiation
 //!                          %               Modulo
+//!                          //              Integer (floor) division *
 //!                          /               Division
 //!                          *               Multiplication
 //!                          -               Subtraction
 //!                          +               Addition
+//!                          << >>           Bit shifts (both have equal precedence) *
 //!                          == != < <= >= > Comparisons (all have equal precedence)
+//!                          &               Bitwise AND *
+//!                          xor             Bitwise XOR * (also needs the `alpha-keywords` feature)
+//!                          |               Bitwise OR *
 //!                          && and          Logical AND with short-circuit
-//!     (Lowest Precedence)  || or           Logical OR with short-circuit
-//!
+//!                          || or           Logical OR with short-circuit
+//!     (Lowest Precedence)  ? :             Ternary conditional (right-associative).  Only
+//!                                          the taken branch is evaluated.
+//!                                          Example: `x < 0 ? -x : x`
+//!
+//!     * Only available with the `bitwise` feature (`cargo build --features bitwise`),
+//!       which also adds the unary `~` (bitwise NOT) operator.  These operators
+//!       truncate their operands to `i64`.  Results that aren't defined for
+//!       integers (like `1 // 0`, or shifting by less than 0 or more than 63
+//!       bits) are `NaN`.
 //! ```
 //!
+//! Calculator-style implicit multiplication (`2x`, `3(x+1)`, `(a)(b)`) can be
+//! enabled with the [`Parser.implicit_mul`](parser/struct.Parser.html#structfield.implicit_mul) option.
+//!
+//! By default, unary minus binds tighter than `^`, so `-2^2` is `4`.  Set the
+//! [`Parser.neg_below_exp`](parser/struct.Parser.html#structfield.neg_below_exp)
+//! option to get the conventional mathematical precedence instead, where `-2^2` is `-4`.
+//!
+//! Comparisons are normally processed left-to-right, so `1 < x < 3` means
+//! `(1 < x) < 3`, which is always true.  The
+//! [`Parser.chain_cmp`](parser/struct.Parser.html#structfield.chain_cmp) option
+//! makes chained comparisons behave like they do in Python:  `1 < x < 3` means
+//! `1 < x && x < 3`, and `x` is only evaluated once.
+//!
 //! ## Numeric Literals
 //!
 //! ```text
@@ -87,6 +177,12 @@
 //!
 //!     Exponents: 1e3, 1E3, 1e-3, 1E-3, 1.2345e100
 //!
+//!     Hexadecimal: 0x1F, 0X1f
+//!     Octal:       0o17, 0O17
+//!     Binary:      0b1010, 0B1010
+//!
+//!     Digit Separators: 1_000_000, 0.000_001, 0xFFFF_FFFF, 0b1010_1010
+//!
 //!     Suffix:
 //!             1.23p        = 0.00000000000123
 //!             1.23n        = 0.00000000123
@@ -98,6 +194,112 @@
 //!             1.23T        = 1230000000000
 //! ```
 //!
+//! ## Strings
+//!
+//! String literals like `"OPEN"` can be used anywhere a value is allowed.
+//! Strings can be compared with `== != < <= > >=` (mixing a string with a
+//! number is never equal, and can't be ordered), and `+` concatenates them:
+//! `"id-" + 7 == "id-7"`.
+//!
+//! Namespaces can provide string variables with
+//! [`EvalNamespace::lookup_val()`](evalns/trait.EvalNamespace.html#method.lookup_val),
+//! for example with a [`StringToValNamespace`](evalns/type.StringToValNamespace.html).
+//! Use [`eval_val()`](evaler/trait.Evaler.html#method.eval_val) to get a string
+//! result;  `eval()` returns a `TypeMismatch` error instead.
+//!
+//! Expressions without string literals or string functions use the normal
+//! numeric-only code, so they are just as fast as before.  Because of that,
+//! `eval()` of `a == b` only looks up numbers.  Use `eval_val()`, or write
+//! `str(a) == b`, to compare two string variables.  Local variables can
+//! only hold numbers.
+//!
+//! ## Arrays
+//!
+//! Array literals are written with square brackets and commas:  `[1, 2, x]`.
+//! `[x]` is still just grouping, so write `[x,]` for an array with one element.
+//! Namespaces can provide arrays with `Val::Arr`, in the same way as strings.
+//!
+//! `xs[i]` gets an element of an array variable.  Indexes start at 0 and are
+//! truncated;  an index that is out of range gives NaN.  (If the namespace
+//! defines a function named `xs`, that is called instead.)  `len(xs)` is the
+//! number of elements.  Only names can be indexed, because `xs[i]` is parsed like
+//! a function call with square brackets.  Square brackets after any other value
+//! are an error, like `[1, 2, 3][1]` or `(xs * 2)[0]`, or a multiplication with
+//! `Parser.implicit_mul`.
+//!
+//! Arithmetic, comparisons, `!` and `&&`/`||` work element-wise:  `xs * 2`,
+//! `xs + ys` and `xs > 0` are all arrays.  Both arrays must have the same length,
+//! and a number is used for every element.  The reductions -- `sum(xs)`,
+//! `max(xs)`, `mean(xs)`, and the other statistics -- turn an array back into a number,
+//! so they can be used with `eval()` too.
+//! Arrays can only hold numbers, and nested array literals are flattened.
+//!
+//! Like strings, arrays need [`eval_val()`](evaler/trait.Evaler.html#method.eval_val)
+//! to be returned;  `eval()` is for expressions that produce a number, like
+//! `sum(xs * ws) / sum(ws)`.  Scalar expressions still use the numeric-only
+//! code, which never allocates for arrays.
+//!
+//! ## Variable Names
+//!
+//! Variable and function names start with a letter or `_`, followed by letters,
+//! digits, or `_`.  Non-ASCII letters and digits are allowed too, so `θ`, `Δt`
+//! and `x₁` are all valid names.  Here, "letters" and "digits" mean Rust's
+//! `char::is_alphabetic()` and `char::is_numeric()`, which is simpler than Unicode's
+//! identifier rules:  combining marks are not accepted (write `é` precomposed), and
+//! number-like characters such as `²` count as digits, so `x²` is a name, not `x^2`.
+//!
+//! Dotted paths like `pump.inlet.pressure` can be enabled with `Parser.dotted_names`.
+//! The whole path is passed to your namespace as a single name.
+//!
+//! Any other name can be written between backticks, like `` `Gross Margin (%)` ``.
+//! A literal backtick is written as two backticks.  Quoted names work anywhere
+//! a plain name does, including function calls and local bindings.
+//! `Parser::quote_name()` does the reverse, so names returned by `var_names()`
+//! can be inserted back into an expression.
+//!
+//! ## Comments
+//!
+//! Long expressions can be annotated with comments, which are allowed
+//! anywhere that whitespace is:
+//!
+//! ```text
+//!     price * qty       # Subtotal
+//!       * (1 + tax)     /* Tax is a fraction, like 0.08 */
+//! ```
+//!
+//! A `/*` without a closing `*/` is an error.
+//!
+//! ## Local Variables
+//!
+//! An expression can begin with local variable bindings, separated by `;`,
+//! so that long formulas don't need to repeat subexpressions:
+//!
+//! ```text
+//!     r = sqrt(x^2 + y^2);  atan(y/x) * r
+//!
+//!     let a = 2;  let b = a^2;  a + b     (The 'let' keyword is optional.)
+//! ```
+//!
+//! Each binding is visible for the rest of the expression, and it shadows any
+//! namespace variable with the same name.  Local variables are not reported
+//! by `var_names()`, and compiled expressions access them by slot rather
+//! than by name.  Their values are kept by each evaluation rather than the
+//! `Slab`, so one `Slab` can still be shared between threads, and a namespace
+//! callback can evaluate other expressions without disturbing them.
+//!
+//! Helper functions can be defined the same way:
+//!
+//! ```text
+//!     f(x) = x^2 + 1;  f(3) + f(4)
+//!
+//!     fact(n) = n <= 1 ? 1 : n * fact(n-1);  fact(5)
+//! ```
+//!
+//! A function's parameters are only visible within its definition.  Functions
+//! take priority over builtins and namespace functions with the same name, and
+//! the number of arguments is checked during parsing.  Recursion is limited by
+//! [`Parser.call_depth_limit`](parser/struct.Parser.html#structfield.call_depth_limit).
+//!
 //! # Examples
 //!
 //! ## Easy evaluation
@@ -121,7 +323,7 @@
 //!     //    |            |      |    |   built-in constants: e(), pi()
 //!     //    |            |      |    'log' can take an optional first 'base' argument, defaults to 10
 //!     //    |            |      numeric literal with suffix: p, n, µ, m, K, M, G, T
-//!     //    |            many built-in functions: print, int, ceil, floor, abs, sign, log, round, min, max, sin, asin, ...
+//!     //    |            many built-in functions: print, int, ceil, floor, abs, sign, log, round, min, max, if, sin, asin, ...
 //!     //    standard binary operators
 //!
 //!     assert_eq!(val, 1.23);
@@ -168,7 +370,7 @@
 //!             "y" => Some(4.0),
 //!
 //!             // Custom function:
-//!             "sum" => Some(args.into_iter().sum()),
+//!             "add" => Some(args.into_iter().sum()),
 //!
 //!             // Custom array-like objects:
 //!             // The `args.get...` code is the same as:
@@ -181,7 +383,7 @@
 //!         }
 //!     };
 //!
-//!     let val = fasteval::ez_eval("sum(x^2, y^2)^0.5 + data[0]",    &mut cb)?;
+//!     let val = fasteval::ez_eval("add(x^2, y^2)^0.5 + data[0]",    &mut cb)?;
 //!     //                           |   |                   |
 //!     //                           |   |                   square-brackets act like parenthesis
 //!     //                           |   variables are like custom functions with zero args
@@ -195,7 +397,7 @@
 //!     //       Therefore, variables can receive arguments too,
 //!     //       which will probably be ignored.
 //!     //       Therefore, these two expressions evaluate to the same thing:
-//!     //           eval("x + y")  ==  eval("x(1,2,3) + y(x, y, sum(x,y))")
+//!     //           eval("x + y")  ==  eval("x(1,2,3) + y(x, y, add(x,y))")
 //!     //                                      ^^^^^      ^^^^^^^^^^^^^^
 //!     //                                      All this stuff is ignored.
 //!     //
@@ -210,9 +412,11 @@
 //!     //           x         -- Uses the custom 'x' variable.
 //!     //           x()       -- Uses the custom 'x' variable because there is no 'x' builtin.
 //!     //           x(1,2,3)  -- Uses the custom 'x' variable.  The args are ignored.
-//!     //           sum       -- Uses the custom 'sum' function with no arguments.
-//!     //           sum()     -- Uses the custom 'sum' function with no arguments.
-//!     //           sum(1,2)  -- Uses the custom 'sum' function with two arguments.
+//!     //           add       -- Uses the custom 'add' function with no arguments.
+//!     //           add()     -- Uses the custom 'add' function with no arguments.
+//!     //           add(1,2)  -- Uses the custom 'add' function with two arguments.
+//!     //           sum(1,2)  -- Uses the custom 'sum' function if the namespace defines
+//!     //                        one, or the builtin 'sum' function otherwise.
 //!
 //!     Ok(())
 //! }
@@ -586,7 +790,6 @@
 //! # Future Work
 //! Here are some features that I might add in the future:
 //!
-//! * Dynamic `sprintf` string formatting for the `print()` built-in expression function.
 //! * FFI so this library can be used from other languages.
 //! * Ability to copy the contents of a `Slab` into a perfectly-sized container
 //!   (`PackedSlab`) to reduce wasted memory.
@@ -618,15 +821,16 @@ pub mod compiler;
 pub mod evaler;
 pub mod evalns;
 pub mod ez;
+pub mod printf;
 
 pub use self::error::Error;
-pub use self::parser::{Parser, Expression, ExpressionI, Value, ValueI};
+pub use self::parser::{Parser, BuiltinRule, NativeFunc, Expression, ExpressionI, Value, ValueI};
 pub use self::compiler::{Compiler, Instruction::{self, IConst}, InstructionI};
 #[cfg(feature="unsafe-vars")]
 pub use self::compiler::Instruction::IUnsafeVar;
-pub use self::evaler::Evaler;
+pub use self::evaler::{Evaler, Val, Locals};
 pub use self::slab::Slab;
-pub use self::evalns::{EvalNamespace, Cached, EmptyNamespace, StringToF64Namespace, StrToF64Namespace, StringToCallbackNamespace, StrToCallbackNamespace, LayeredStringToF64Namespace, CachedCallbackNamespace};
+pub use self::evalns::{EvalNamespace, Cached, EmptyNamespace, StringToF64Namespace, StrToF64Namespace, StringToCallbackNamespace, StrToCallbackNamespace, LayeredStringToF64Namespace, StringToValNamespace, CachedCallbackNamespace, PrintSink};
 pub use self::ez::ez_eval;
 
 
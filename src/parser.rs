@@ -2,17 +2,23 @@
 //!
 //! # fasteval Algebra Grammar
 //! ```text
-//! Expression: Value (BinaryOp Value)*
+//! Sequence: ( (let)? VarName((VarName,)*)? = Expression ; )* Expression     (Only at the top level.)
+//!
+//! Expression: Value (BinaryOp Value)* ( ? Expression : Expression )?
 //!
 //! Value: Constant || UnaryOp || PrintFunc || StdFunc
 //!
-//! Constant: [+-]?[0-9]*(\.[0-9]+)?( ([eE][+-]?[0-9]+) || [pnuµmkKMGT] )?  || [+-]?(NaN || inf)
+//! Constant: [+-]?[0-9]*(\.[0-9]+)?( ([eE][+-]?[0-9]+) || [pnuµmkKMGT] )?  || [+-]?(NaN || inf)  || [+-]?0[xX][0-9a-fA-F]+  || [+-]?0[oO][0-7]+  || [+-]?0[bB][01]+
+//!           (Digits may be separated with '_', like 1_000_000 or 0xFFFF_FFFF.)
 //!
-//! UnaryOp: +Value || -Value || (Expression) || [Expression] || !Value
+//! UnaryOp: +Value || -Value || (Expression) || [Expression] || !Value || ~Value
 //!
 //! BinaryOp: + || - || * || / || % || ^ || < || <= || == || != || >= || > || (or || '||') || (and || '&&')
+//!           || & || '|' || xor || << || >> || //     (The last 6 require the 'bitwise' feature.  'xor' also requires 'alpha-keywords'.)
 //!
-//! VarName: [a-zA-Z_][a-zA-Z_0-9]*
+//! VarName: [a-zA-Z_][a-zA-Z_0-9]*     (Non-ASCII letters and digits are also allowed, like 'θ' or 'Δt'.)
+//!          (VarName.)*VarName          (Dotted paths require Parser.dotted_names.)
+//!          `.*`                        (Quoted names can contain any characters.  Write '``' for a literal '`'.)
 //!
 //! StdFunc: VarName((Expression,)*)?  ||  VarName[(Expression,)*]?
 //!
@@ -21,14 +27,18 @@
 //! ExpressionOrString: Expression || String
 //!
 //! String: ".*"
+//!
+//! Comments: /* ... */ || # ... (to the end of the line)     (Allowed wherever spaces are.)
 //! ```
 
 
 use crate::error::Error;
 use crate::slab::ParseSlab;
+use crate::printf;
 
 use std::str::{from_utf8, from_utf8_unchecked};
 use std::ptr;
+use std::fmt;
 
 
 
@@ -50,24 +60,53 @@ pub struct ValueI(pub usize);
 /// An `Expression` is the top node of a parsed AST.
 ///
 /// It can be `compile()`d or `eval()`d.
-#[derive(Debug, PartialEq)]
+#[derive(PartialEq)]
 pub struct Expression {
     pub(crate) first: Value,
     pub(crate) pairs: Vec<ExprPair>,  // cap=8
+    pub(crate) flags: u8,  // A combination of the EXPR_* bits below.
+    pub(crate) index: Option<ExpressionI>,  // Set by ParseSlab::push_expr(), so that compile() can refer back to this Expression.
+}
+
+// The bits of Expression.flags:
+pub(crate) const EXPR_CHAIN_CMP : u8 = 1;  // Set from Parser.chain_cmp.
+pub(crate) const EXPR_STRINGS   : u8 = 2;  // Set by ParseSlab::push_expr() when an operand might be a string.  Otherwise, eval() can stay in the f64-only fast path.
+pub(crate) const EXPR_ARRAYS    : u8 = 4;  // Set by ParseSlab::push_expr() when an operand might be an array literal, or an operation on one.
+
+impl Expression {
+    #[inline]
+    pub(crate) fn chain_cmp(&self) -> bool { self.flags & EXPR_CHAIN_CMP != 0 }
+    #[inline]
+    pub(crate) fn strings(&self) -> bool { self.flags & EXPR_STRINGS != 0 }
+    #[inline]
+    pub(crate) fn arrays(&self) -> bool { self.flags & EXPR_ARRAYS != 0 }
+    // Strings and arrays can only be handled by eval_val():
+    #[inline]
+    pub(crate) fn needs_val(&self) -> bool { self.flags & (EXPR_STRINGS|EXPR_ARRAYS) != 0 }
 }
 
 #[derive(Debug, PartialEq)]
 pub(crate) struct ExprPair(pub BinaryOp, pub Value);
 
-/// A `Value` can be a Constant, a UnaryOp, a StdFunc, or a PrintFunc.
+/// A `Value` can be a Constant, a String, an Array, a UnaryOp, a StdFunc, a PrintFunc, a Ternary, or a Let binding.
 #[derive(Debug, PartialEq)]
 pub enum Value {
     EConstant(f64),
+    /// A string literal, like `"OPEN"`.  See [`Val`](../evaler/enum.Val.html).
+    EString(String),
+    /// An array literal, like `[1, 2, x]`.
+    EArray(Vec<ExpressionI>),
     EUnaryOp(UnaryOp),
     EStdFunc(StdFunc),
     EPrintFunc(PrintFunc),
+    /// `cond ? then : otherwise` -- Only the taken branch gets evaluated.
+    ETernary{cond:ExpressionI, then:ExpressionI, otherwise:ExpressionI},
+    /// `name = value; body` -- Stores `value` in the local `slot`, then evaluates `body`.
+    ELet{slot:usize, value:ExpressionI, body:ExpressionI},
+    /// `name(params) = definition; body` -- The function `func` is stored in the `ParseSlab`.  Evaluates `body`.
+    ELetFunc{func:usize, body:ExpressionI},
 }
-use Value::{EConstant, EUnaryOp, EStdFunc, EPrintFunc};
+use Value::{EConstant, EString, EArray, EUnaryOp, EStdFunc, EPrintFunc, ETernary, ELet, ELetFunc};
 
 /// Unary Operators
 #[derive(Debug, PartialEq)]
@@ -76,30 +115,51 @@ pub enum UnaryOp {
     ENeg(ValueI),
     ENot(ValueI),
     EParentheses(ExpressionI),
+    #[cfg(feature="bitwise")]
+    EBitNot(ValueI),
 }
 use UnaryOp::{EPos, ENeg, ENot, EParentheses};
+#[cfg(feature="bitwise")]
+use UnaryOp::EBitNot;
 
 /// Binary Operators
 #[derive(Debug, PartialEq, PartialOrd, Copy, Clone)]
 pub enum BinaryOp {
     // Sorted in order of precedence (low-priority to high-priority):
     // Keep this order in-sync with evaler.rs.  (Search for 'rtol' and 'ltor'.)
+    // The bitwise operators operate on values truncated to i64.
     EOR    =  1,  // Lowest Priority
     EAND   =  2,
-    ENE    =  3,
-    EEQ    =  4,
-    EGTE   =  5,
-    ELTE   =  6,
-    EGT    =  7,
-    ELT    =  8,
-    EAdd   =  9,
-    ESub   = 10,
-    EMul   = 11,
-    EDiv   = 12,
-    EMod   = 13,
-    EExp   = 14,  // Highest Priority
+    #[cfg(feature="bitwise")]
+    EBitOr =  3,
+    #[cfg(feature="bitwise")]
+    EBitXor=  4,
+    #[cfg(feature="bitwise")]
+    EBitAnd=  5,
+    ENE    =  6,
+    EEQ    =  7,
+    EGTE   =  8,
+    ELTE   =  9,
+    EGT    = 10,
+    ELT    = 11,
+    #[cfg(feature="bitwise")]
+    EShr   = 12,
+    #[cfg(feature="bitwise")]
+    EShl   = 13,
+    EAdd   = 14,
+    ESub   = 15,
+    EMul   = 16,
+    EDiv   = 17,
+    #[cfg(feature="bitwise")]
+    EIntDiv= 18,
+    EMod   = 19,
+    EExp   = 20,  // Highest Priority
 }
 use BinaryOp::{EAdd, ESub, EMul, EDiv, EMod, EExp, ELT, ELTE, EEQ, ENE, EGTE, EGT, EOR, EAND};
+#[cfg(feature="bitwise")]
+use BinaryOp::{EBitOr, EBitAnd, EShr, EShl, EIntDiv};
+#[cfg(all(feature="bitwise", feature="alpha-keywords"))]
+use BinaryOp::EBitXor;
 
 /// A Function Call with Standard Syntax.
 #[derive(Debug, PartialEq)]
@@ -107,7 +167,12 @@ pub enum StdFunc {
     EVar(String),
     #[cfg(feature="unsafe-vars")]
     EUnsafeVar{name:String, ptr:*const f64},
+    ELocal(usize),  // A variable bound with `name = value;`, stored in a ParseSlab slot.
     EFunc{name:String, args:Vec<ExpressionI>},  // cap=4
+    EIndex{name:String, idx:ExpressionI},  // `name[idx]`.  Indexes an array variable if the namespace has no `name` function.
+    EUserFunc{func:usize, args:Vec<ExpressionI>},  // A function defined with `name(params) = definition;`.
+    ENamespaceFirst{name:String, args:Vec<ExpressionI>, slot:usize, builtin:ExpressionI},  // Only evaluates `builtin` if the namespace doesn't define `name`.  `builtin` reads the arg values from the local slots starting at `slot`.
+    ENativeFunc{func:NativeFunc, args:Vec<ExpressionI>},  // A function from `Parser.native_funcs`.
 
     EFuncInt(ExpressionI),
     EFuncCeil(ExpressionI),
@@ -118,6 +183,11 @@ pub enum StdFunc {
     EFuncRound{modulus:Option<ExpressionI>, expr:ExpressionI},
     EFuncMin{first:ExpressionI, rest:Vec<ExpressionI>},  // cap=4
     EFuncMax{first:ExpressionI, rest:Vec<ExpressionI>},  // cap=4
+    EFuncStat{func:StatFunc, skip_nan:bool, ns_first:Option<String>, first:ExpressionI, rest:Vec<ExpressionI>},  // For `percentile`, `first` is the percentile.  `ns_first` is the name to ask the namespace for first.
+    EFuncStr{func:StrFunc, args:Vec<ExpressionI>},
+    EFuncIf{cond:ExpressionI, then:ExpressionI, otherwise:ExpressionI},
+    EFuncSum{ slot:usize, start:ExpressionI, end:ExpressionI, expr:ExpressionI},  // The index variable is stored in a local slot.
+    EFuncProd{slot:usize, start:ExpressionI, end:ExpressionI, expr:ExpressionI},
 
     EFuncE,
     EFuncPi,
@@ -125,6 +195,9 @@ pub enum StdFunc {
     EFuncSin(ExpressionI),
     EFuncCos(ExpressionI),
     EFuncTan(ExpressionI),
+    EFuncSinD(ExpressionI),  // sin(), cos() and tan() of an angle in degrees, for `Parser.degrees`.
+    EFuncCosD(ExpressionI),
+    EFuncTanD(ExpressionI),
     EFuncASin(ExpressionI),
     EFuncACos(ExpressionI),
     EFuncATan(ExpressionI),
@@ -134,11 +207,80 @@ pub enum StdFunc {
     EFuncASinH(ExpressionI),
     EFuncACosH(ExpressionI),
     EFuncATanH(ExpressionI),
+
+    EFuncSqrt(ExpressionI),
+    EFuncCbrt(ExpressionI),
+    EFuncExp(ExpressionI),
+    EFuncLn(ExpressionI),
+    EFuncLog2(ExpressionI),
+    EFuncLog10(ExpressionI),
+    EFuncTrunc(ExpressionI),
+    EFuncFrac(ExpressionI),
+    EFuncDeg(ExpressionI),
+    EFuncRad(ExpressionI),
+    EFuncErf(ExpressionI),
+    EFuncGamma(ExpressionI),
+    EFuncLGamma(ExpressionI),
+    EFuncATan2(ExpressionI, ExpressionI),
+    EFuncHypot(ExpressionI, ExpressionI),
+    EFuncPow(ExpressionI, ExpressionI),
+    EFuncFMod(ExpressionI, ExpressionI),
+    EFuncClamp{x:ExpressionI, min:ExpressionI, max:ExpressionI},
 }
-use StdFunc::{EVar, EFunc, EFuncInt, EFuncCeil, EFuncFloor, EFuncAbs, EFuncSign, EFuncLog, EFuncRound, EFuncMin, EFuncMax, EFuncE, EFuncPi, EFuncSin, EFuncCos, EFuncTan, EFuncASin, EFuncACos, EFuncATan, EFuncSinH, EFuncCosH, EFuncTanH, EFuncASinH, EFuncACosH, EFuncATanH};
+use StdFunc::{EVar, ELocal, EFunc, EIndex, EUserFunc, ENamespaceFirst, ENativeFunc, EFuncInt, EFuncCeil, EFuncFloor, EFuncAbs, EFuncSign, EFuncLog, EFuncRound, EFuncMin, EFuncMax, EFuncStat, EFuncStr, EFuncIf, EFuncSum, EFuncProd, EFuncE, EFuncPi, EFuncSin, EFuncCos, EFuncTan, EFuncSinD, EFuncCosD, EFuncTanD, EFuncASin, EFuncACos, EFuncATan, EFuncSinH, EFuncCosH, EFuncTanH, EFuncASinH, EFuncACosH, EFuncATanH, EFuncSqrt, EFuncCbrt, EFuncExp, EFuncLn, EFuncLog2, EFuncLog10, EFuncTrunc, EFuncFrac, EFuncDeg, EFuncRad, EFuncErf, EFuncGamma, EFuncLGamma, EFuncATan2, EFuncHypot, EFuncPow, EFuncFMod, EFuncClamp};
 #[cfg(feature="unsafe-vars")]
 use StdFunc::EUnsafeVar;
 
+/// The aggregate computed by a `StdFunc::EFuncStat` call, like `mean(1, 2, 3)`.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum StatFunc {
+    Sum,
+    Mean,
+    Median,
+    StdDev,  // Sample standard deviation.
+    Variance,  // Sample variance.
+    Count,
+    Percentile,
+}
+
+/// A string function, computed by a `StdFunc::EFuncStr` call, like `upper(name)`.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum StrFunc {
+    Str,  // Converts a number to a string.
+    Len,
+    Upper,
+    Lower,
+    Contains,
+    StartsWith,
+    EndsWith,
+}
+impl StrFunc {
+    /// The name that this function is called with.
+    pub fn name(self) -> &'static str {
+        match self {
+            StrFunc::Str => "str",
+            StrFunc::Len => "len",
+            StrFunc::Upper => "upper",
+            StrFunc::Lower => "lower",
+            StrFunc::Contains => "contains",
+            StrFunc::StartsWith => "startswith",
+            StrFunc::EndsWith => "endswith",
+        }
+    }
+    /// Whether this function returns a string (rather than a number).
+    pub fn returns_str(self) -> bool {
+        matches!(self, StrFunc::Str | StrFunc::Upper | StrFunc::Lower)
+    }
+}
+
+/// A function defined within the expression text, like `f(x) = x^2 + 1;`.
+#[derive(Debug, PartialEq)]
+pub(crate) struct UserFunc {
+    pub(crate) name  :String,
+    pub(crate) params:Vec<usize>,  // The local slots that receive the arguments.
+    pub(crate) body  :ExpressionI,
+}
+
 /// Represents a `print()` function call in the `fasteval` expression AST.
 #[derive(Debug, PartialEq)]
 pub struct PrintFunc(pub Vec<ExpressionOrString>);  // cap=8
@@ -174,6 +316,12 @@ enum Token<T> {
 }
 use Token::{Pass, Bite};
 
+// The left side of a `name = ...;` binding:
+enum Binding {
+    Var(String),
+    Func(String, Vec<String>),
+}
+
 macro_rules! peek {
     ($bs:ident) =>  {
         $bs.first().copied()
@@ -234,6 +382,20 @@ macro_rules! skip_n {
     };
 }
 
+// Parse errors are tagged with their location using the number of bytes that
+// remain in the input (not the real offsets), because that's all that the
+// `read_*` functions can see.  `parse_noclear()` converts them to real offsets.
+//
+// The 2-arg form spans the char at the front of `$bs`.
+macro_rules! err_at {
+    ($err:expr, $bs:ident) => {
+        err_at!($err, $bs, char_len($bs))
+    };
+    ($err:expr, $bs:ident, $len:expr) => {
+        Error::Spanned{err:Box::new($err), start:$bs.len(), end:$bs.len()-$len}
+    };
+}
+
 macro_rules! is_space {
     ($b:ident) => {
         if $b>b' ' { false }
@@ -245,7 +407,14 @@ macro_rules! is_space {
 macro_rules! spaces {
     ($bs:ident) => {
         while let Some(b) = peek!($bs) {
-            if !is_space!(b) { break }
+            if !is_space!(b) {
+                // Comments count as whitespace.  Only look for them after a '#' or '/', so comment-free expressions stay fast:
+                if b==b'#' || b==b'/' {
+                    let n = comment_len($bs);
+                    if n>0 { skip_n!($bs,n); continue }
+                }
+                break
+            }
             skip!($bs);  // We normally don't have long strings of whitespace, so it is more efficient to put this single-skip inside this loop rather than a skip_n afterwards.
         }
     };
@@ -254,16 +423,155 @@ macro_rules! spaces {
 
 pub const DEFAULT_EXPR_LEN_LIMIT  : usize = 4096;
 pub const DEFAULT_EXPR_DEPTH_LIMIT: usize = 32;
+pub const DEFAULT_CALL_DEPTH_LIMIT: usize = 64;
+pub const DEFAULT_ITER_LIMIT      : usize = 100_000;
 
 pub struct Parser {
     pub expr_len_limit  :usize,
     pub expr_depth_limit:usize,
+
+    /// The maximum nesting of calls to functions defined within the expression
+    /// (like `f(x) = ...;`) during evaluation.  Deeper recursion produces an
+    /// [`Error::RecursionLimit`](../error/enum.Error.html#variant.RecursionLimit).
+    ///
+    /// `parse()` copies this limit into the `ParseSlab`, so changing it only takes
+    /// effect when the next expression is parsed into that Slab.
+    pub call_depth_limit:usize,
+
+    /// The maximum number of iterations of a single series, like `sum(i, 1, n, i^2)`,
+    /// during evaluation.  Larger ranges produce an
+    /// [`Error::IterationLimit`](../error/enum.Error.html#variant.IterationLimit).
+    /// Like `call_depth_limit`, this is copied into the `ParseSlab` by `parse()`.
+    pub iter_limit:usize,
+
+    /// Treat juxtaposition as multiplication, like a calculator: `2x`, `3(x+1)`, `(a)(b)`.
+    /// Disabled by default.
+    ///
+    /// The right-hand side must be a variable, a function call, or a parenthesized group
+    /// (so `x 2` is still an error), and `x(y)` is always a function call.  Implicit
+    /// multiplication has the same precedence as `*`, so `1/2x` means `(1/2)*x`.
+    ///
+    /// Numeric suffixes only apply when they stand alone: `2k` is `2000` but `2kg` is `2*kg`,
+    /// and `e`/`E` is only an exponent when it is followed by digits: `2e3` is `2000` but `2e` is `2*e`.
+    pub implicit_mul:bool,
+
+    /// Make `^` bind tighter than unary minus, so `-2^2` is `-4` and `2^-1^2` is `0.5`.
+    /// Disabled by default, in which case unary minus binds to the value that
+    /// follows it and `-2^2` is `4`.
+    pub neg_below_exp:bool,
+
+    /// Treat chains of comparisons like Python does: `a < x <= b` means `a < x && x <= b`,
+    /// with `x` evaluated only once.  Disabled by default, in which case comparisons
+    /// are processed left-to-right: `a < x <= b` means `(a < x) <= b`.
+    pub chain_cmp:bool,
+
+    /// Measure angles in degrees instead of radians:  `sin()`, `cos()` and `tan()`
+    /// take degrees, and `asin()`, `acos()`, `atan()` and `atan2()` return degrees.
+    /// Angles that are multiples of 90 give exact results, so `cos(90)` is `0`.
+    /// Disabled by default.  The hyperbolic functions are not affected.
+    pub degrees:bool,
+
+    /// Allow dotted paths like `pump.inlet.pressure` as variable and function names.
+    /// Disabled by default.  The whole path is passed to the namespace and
+    /// reported by `var_names()`.
+    pub dotted_names:bool,
+
+    /// Changes to how builtin function names are resolved:  builtins can be
+    /// disabled, given other names, or overridden by the namespace.  Rules are
+    /// applied in order, so later rules win.  Empty by default.
+    ///
+    /// ```
+    /// use fasteval::{Parser, BuiltinRule};
+    /// let parser = Parser{builtins:vec![BuiltinRule::Disable("print".to_string()),
+    ///                                   BuiltinRule::Alias{alias:"log10".to_string(), builtin:"log".to_string()},
+    ///                                   BuiltinRule::NamespaceFirst("round".to_string())],
+    ///                     ..Parser::new()};
+    /// ```
+    pub builtins:Vec<BuiltinRule>,
+
+    /// Rust functions that can be called from expressions.  They take priority
+    /// over the builtins, and calls are dispatched directly through the function
+    /// pointer rather than through the namespace.  Empty by default.
+    ///
+    /// ```
+    /// use fasteval::{Parser, NativeFunc};
+    /// fn myscale(args:&[f64]) -> f64 { args[0] * args[1] }
+    /// let parser = Parser{native_funcs:vec![NativeFunc{name:"myscale", arity:2, pure:true, func:myscale}],
+    ///                     ..Parser::new()};
+    /// ```
+    pub native_funcs:Vec<NativeFunc>,
+}
+
+/// A rule that changes how `Parser` resolves the name of a builtin function.
+/// See [`Parser.builtins`](struct.Parser.html#structfield.builtins).
+#[derive(Debug, Clone, PartialEq)]
+pub enum BuiltinRule {
+    /// Stop treating `name` as a builtin.  Calls to it go to the namespace,
+    /// like any other custom function.
+    Disable(String),
+    /// Make `alias` call the builtin named `builtin`.  The original name
+    /// still works, unless it is also disabled.
+    Alias{alias:String, builtin:String},
+    /// Evaluate calls to the builtin `name` with the namespace first, and only
+    /// use the builtin if the namespace doesn't define `name`.  The arguments
+    /// are evaluated once, and both get the same values.  This doesn't
+    /// apply to `print()`, or to the series form of `sum()` and `prod()`,
+    /// which don't take ordinary arguments.
+    ///
+    /// The aggregate functions (`sum`, `mean`, `median`, `stddev`, `variance`,
+    /// `count`, `percentile` and their `nan` variants) are namespace-first by
+    /// default, because namespaces could define them before they were builtins.
+    /// They skip the namespace when an argument is an array.
+    NamespaceFirst(String),
+    /// Undo `NamespaceFirst` for `name`, so that the builtin is always used.
+    /// Calls to the aggregate functions with constant arguments can then be
+    /// evaluated during compilation.
+    BuiltinFirst(String),
+}
+
+// The builtins that were added after namespaces could already define functions with the same names:
+const NAMESPACE_FIRST_BY_DEFAULT : [&str; 14] = ["sum", "mean", "median", "stddev", "variance", "count", "percentile",
+                                                 "nansum", "nanmean", "nanmedian", "nanstddev", "nanvariance", "nancount", "nanpercentile"];
+
+/// A Rust function that can be called from expressions.
+/// See [`Parser.native_funcs`](struct.Parser.html#structfield.native_funcs).
+#[derive(Clone, Copy)]
+pub struct NativeFunc {
+    pub name :&'static str,
+    /// The number of arguments.  Calls with a different number are rejected by the parser.
+    pub arity:usize,
+    /// A pure function always returns the same result for the same arguments, and
+    /// has no side effects.  Pure functions with constant arguments are evaluated
+    /// by the compiler, so they cost nothing at evaluation time.
+    pub pure :bool,
+    /// Receives exactly `arity` arguments.
+    pub func :fn(&[f64]) -> f64,
+}
+// Implemented manually because function pointers don't always implement these traits:
+impl fmt::Debug for NativeFunc {
+    fn fmt(&self, f:&mut fmt::Formatter) -> fmt::Result {
+        write!(f, "NativeFunc({:?})", self.name)
+    }
+}
+impl PartialEq for NativeFunc {
+    fn eq(&self, other:&Self) -> bool {
+        self.name==other.name && self.arity==other.arity && self.pure==other.pure && self.func as usize==other.func as usize
+    }
 }
 
 impl Parser {
     #[inline]
     pub const fn new() -> Self { Self{expr_len_limit:DEFAULT_EXPR_LEN_LIMIT,
-                                      expr_depth_limit:DEFAULT_EXPR_DEPTH_LIMIT} }
+                                      expr_depth_limit:DEFAULT_EXPR_DEPTH_LIMIT,
+                                      call_depth_limit:DEFAULT_CALL_DEPTH_LIMIT,
+                                      iter_limit:DEFAULT_ITER_LIMIT,
+                                      implicit_mul:false,
+                                      neg_below_exp:false,
+                                      chain_cmp:false,
+                                      degrees:false,
+                                      dotted_names:false,
+                                      builtins:Vec::new(),
+                                      native_funcs:Vec::new()} }
 
     fn is_varname_byte(b:u8, i:usize) -> bool {
         (b'A'<=b && b<=b'Z') || (b'a'<=b && b<=b'z') || b==b'_' || (i>0 && ( b'0'<=b && b<=b'9' ))
@@ -274,6 +582,36 @@ impl Parser {
             None => false,
         }
     }
+    // Returns the byte length of the VarName character at position i, or 0 if there isn't one.
+    // Non-ASCII characters are accepted if they are alphabetic (or numeric, after the first character).
+    // Combining marks are not accepted, and numeric includes characters like '²' and '₁'.
+    fn varname_char_len(bs:&[u8], i:usize, first:bool) -> usize {
+        match peek_n!(bs,i) {
+            None => 0,
+            Some(b) if b<0x80 && Self::is_varname_byte(b, if first {0} else {1}) => 1,
+            Some(b) if b<0x80 => 0,
+            Some(b) => {
+                let len = if b>=0xf0 {4} else if b>=0xe0 {3} else {2};
+                match bs.get(i..i+len).and_then(|s| from_utf8(s).ok()).and_then(|s| s.chars().next()) {
+                    Some(c) if c.is_alphabetic() || (!first && c.is_numeric()) => len,
+                    _ => 0,
+                }
+            }
+        }
+    }
+    // Checks for a whole-word keyword at the start of 'bs', so that 'true' matches but 'trueness' doesn't:
+    #[cfg(feature="alpha-keywords")]
+    fn is_keyword(bs:&[u8], kw:&[u8]) -> bool {
+        bs.starts_with(kw) && Self::varname_char_len(bs,kw.len(),false)==0
+    }
+    // Checks for [+-]?[0-9] at position i, which is the only thing that can follow an 'e' exponent marker:
+    fn is_exponent(bs:&[u8], mut i:usize) -> bool {
+        if peek_is!(bs,i,b'+') || peek_is!(bs,i,b'-') { i+=1; }
+        match peek_n!(bs,i) {
+            Some(b) => b.is_ascii_digit(),
+            None => false,
+        }
+    }
 
     /// Use this function to parse an expression String.  The `Slab` will be cleared first.
     #[inline]
@@ -288,11 +626,113 @@ impl Parser {
     ///
     /// This function cannot return Result<&Expression> because it would
     /// prolong the mut ref.  / That's why we return an ExpressionI instead.
+    ///
+    /// All errors are returned as [`Error::Spanned`](../error/enum.Error.html#variant.Spanned),
+    /// which includes the location of the problem within `expr_str`.
     #[inline]
     pub fn parse_noclear(&self, expr_str:&str, slab:&mut ParseSlab) -> Result<ExpressionI,Error> {
-        if expr_str.len()>self.expr_len_limit { return Err(Error::TooLong); }  // Restrict length for safety
+        let len = expr_str.len();
+        if len>self.expr_len_limit { return Err(Error::Spanned{err:Box::new(Error::TooLong), start:0, end:len}); }  // Restrict length for safety
         let mut bs = expr_str.as_bytes();
-        self.read_expression(slab, &mut bs, 0, true)
+        // Locals and functions from previously-parsed expressions are not in scope:
+        slab.scope.clear();
+        slab.func_base = slab.funcs.len();
+        slab.call_depth_limit = self.call_depth_limit;
+        slab.iter_limit = self.iter_limit;
+        self.read_sequence(slab, &mut bs).map_err(|err| {
+            match err {
+                Error::Spanned{err, start, end} => Error::Spanned{err, start:len-start, end:len-end},
+                // Errors without a location occurred wherever the parser stopped:
+                err => Error::Spanned{err:Box::new(err), start:len-bs.len(), end:len-bs.len()},
+            }
+        })
+    }
+
+    // Reads the top-level `name = value; ... body` sequence.  Each binding is in scope for the rest of the sequence.
+    fn read_sequence(&self, slab:&mut ParseSlab, bs:&mut &[u8]) -> Result<ExpressionI,Error> {
+        let mut bindings = Vec::<Value>::new();  // ELet or ELetFunc, with a placeholder body.
+        while let Some(binding) = Self::read_binding(bs)? {
+            match binding {
+                Binding::Var(name) => {
+                    let value = self.read_expression(slab,bs,1,false)?;  // The new name is not in scope yet, so `x = x + 1;` refers to the outer `x`.
+                    bindings.push(ELet{slot:slab.push_local(name)?, value, body:ExpressionI(0)});
+                }
+                Binding::Func(name, params) => {
+                    let scope_len = slab.scope.len();
+                    let mut slots = Vec::<usize>::with_capacity(params.len());
+                    for param in params { slots.push(slab.push_local(param)?); }
+                    let func = slab.push_func(name, slots)?;  // Registered before the definition is parsed, so it can call itself.
+                    let body = self.read_expression(slab,bs,1,false)?;
+                    slab.scope.truncate(scope_len);  // The parameters are only visible within the definition.
+                    slab.define_func(func, body);
+                    bindings.push(ELetFunc{func, body:ExpressionI(0)});
+                }
+            }
+            spaces!(bs);
+            let semi = *bs;
+            if read!(bs,"binding")? != b';' { return Err(err_at!(Error::Expected(";".to_string()), semi)); }
+        }
+
+        let mut body = self.read_expression(slab,bs,0,true)?;
+        for binding in bindings.into_iter().rev() {
+            let first = match binding {
+                ELet{slot, value, ..} => ELet{slot, value, body},
+                ELetFunc{func, ..} => ELetFunc{func, body},
+                _ => return Err(Error::Unreachable),
+            };
+            body = slab.push_expr(Expression{first, pairs:Vec::new(), flags:0, index:None})?;
+        }
+        Ok(body)
+    }
+
+    // Reads `name =`, `name(params) =`, or either of those preceded by `let`.
+    // Nothing is consumed if there is no binding.
+    fn read_binding(bs:&mut &[u8]) -> Result<Option<Binding>,Error> {
+        let save = *bs;
+        spaces!(bs);
+        let quoted = peek_is!(bs,0,b'`');
+        let mut name = match Self::read_varname(bs)? {
+            Pass => return Ok(None),
+            Bite(name) => name,
+        };
+        if name=="let" && !quoted {
+            if let Bite(n) = Self::read_varname(bs)? { name=n; }
+        }
+        spaces!(bs);
+        let mut params = None;
+        if peek_is!(bs,0,b'(') {
+            skip!(bs);
+            let mut ps = Vec::<String>::new();
+            loop {
+                spaces!(bs);
+                if peek_is!(bs,0,b')') { skip!(bs); break; }
+                if !ps.is_empty() {
+                    if !peek_is!(bs,0,b',') { *bs=save; return Ok(None); }
+                    skip!(bs);
+                }
+                match Self::read_varname(bs)? {
+                    Pass => { *bs=save; return Ok(None); }
+                    Bite(p) => ps.push(p),
+                }
+            }
+            spaces!(bs);
+            params = Some(ps);
+        }
+        if peek_is!(bs,0,b'=') && !peek_is!(bs,1,b'=') {
+            skip!(bs);
+            match params {
+                None => Ok(Some(Binding::Var(name))),
+                Some(ps) => Ok(Some(Binding::Func(name,ps))),
+            }
+        } else {
+            *bs = save;
+            Ok(None)
+        }
+    }
+
+    // The Expression.flags that come from Parser options.  (ParseSlab::push_expr() adds the others.)
+    fn expr_flags(&self) -> u8 {
+        if self.chain_cmp { EXPR_CHAIN_CMP } else { 0 }
     }
 
     fn read_expression(&self, slab:&mut ParseSlab, bs:&mut &[u8], depth:usize, expect_eof:bool) -> Result<ExpressionI,Error> {
@@ -301,51 +741,91 @@ impl Parser {
         let first = self.read_value(slab,bs,depth)?;
         let mut pairs = Vec::<ExprPair>::with_capacity(8);
         loop {
-            match self.read_binaryop(bs)? {
-                Pass => break,
-                Bite(bop) => {
-                    let val = self.read_value(slab,bs,depth)?;
-                    pairs.push(ExprPair(bop,val));
+            let bop = match self.read_binaryop(bs)? {
+                Bite(bop) => bop,
+                Pass => {
+                    // read_binaryop() already skipped the spaces:
+                    if self.implicit_mul && ( peek_is!(bs,0,b'(') || peek_is!(bs,0,b'[') || peek_is!(bs,0,b'`') || Self::varname_char_len(bs,0,true)>0 ) { EMul }
+                    else { break }
                 }
-            }
+            };
+            let val = self.read_value(slab,bs,depth)?;
+            pairs.push(ExprPair(bop,val));
         }
         spaces!(bs);
+        if peek_is!(bs,0,b'?') {
+            // The ternary has the lowest precedence, so everything we have read so far is the condition:
+            skip!(bs);
+            let cond = slab.push_expr(Expression{first, pairs, flags:self.expr_flags(), index:None})?;
+            let then = self.read_expression(slab,bs,depth+1,false)?;
+            spaces!(bs);
+            let colon = *bs;
+            if read!(bs,"ternary")? != b':' { return Err(err_at!(Error::Expected(":".to_string()), colon)); }
+            let otherwise = self.read_expression(slab,bs,depth+1,expect_eof)?;  // Right-associative: a ? b : c ? d : e
+            return slab.push_expr(Expression{first:ETernary{cond, then, otherwise}, pairs:Vec::new(), flags:0, index:None});
+        }
         if expect_eof && !bs.is_empty() {
             let bs_str = match from_utf8(bs) {
                 Ok(s) => s,
                 Err(..) => "Utf8Error while handling UnparsedTokensRemaining error",
             };
-            return Err(Error::UnparsedTokensRemaining(bs_str.to_string()));
+            return Err(err_at!(Error::UnparsedTokensRemaining(bs_str.to_string()), bs, bs.len()));
         }
-        Ok(slab.push_expr(Expression{first, pairs})?)
+        Ok(slab.push_expr(Expression{first, pairs, flags:self.expr_flags(), index:None})?)
     }
 
     fn read_value(&self, slab:&mut ParseSlab, bs:&mut &[u8], depth:usize) -> Result<Value,Error> {
         if depth>self.expr_depth_limit { return Err(Error::TooDeep) }
 
-        match Self::read_const(slab,bs)? {
-            Pass => {}
-            Bite(c) => return Ok(EConstant(c)),
+        spaces!(bs);
+        // When `^` binds tighter than unary minus, a leading '-' can't be part of a numeric literal:
+        if !(self.neg_below_exp && peek_is!(bs,0,b'-')) {
+            match self.read_const(slab,bs)? {
+                Pass => {}
+                Bite(c) => return Ok(EConstant(c)),
+            }
         }
+        if peek_is!(bs,0,b'[') { return self.read_brackets(slab,bs,depth); }
         match self.read_unaryop(slab,bs,depth)? {
             Pass => {}
             Bite(u) => return Ok(EUnaryOp(u)),
         }
+        if peek_is!(bs,0,b'"') {
+            if let Bite(s) = Self::read_string(bs)? { return Ok(EString(s)); }
+        }
         match self.read_callable(slab,bs,depth)? {
             Pass => {}
             Bite(c) => return Ok(c),
         }
 
-        // Improve the precision of this error case:
+        // Improve the precision of these error cases:
         if bs.is_empty() { return Err(Error::EofWhileParsing("value".to_string())); }
+        if bs.starts_with(b"/*") { return Err(Error::EofWhileParsing("comment".to_string())); }  // spaces!() would have skipped a terminated comment.
 
-        Err(Error::InvalidValue)
+        Err(err_at!(Error::InvalidValue, bs))
     }
 
-    fn read_const(slab:&mut ParseSlab, bs:&mut &[u8]) -> Result<Token<f64>,Error> {
+    fn read_const(&self, slab:&mut ParseSlab, bs:&mut &[u8]) -> Result<Token<f64>,Error> {
         spaces!(bs);
 
-        let mut toklen=0;  let mut sign_ok=true;  let mut specials_ok=true;  let mut suffix_ok=true;  let mut saw_val=false;
+        #[cfg(feature="alpha-keywords")]
+        {
+            if Self::is_keyword(bs,b"true") { skip_n!(bs,4); return Ok(Bite(1.0)); }
+            if Self::is_keyword(bs,b"false") { skip_n!(bs,5); return Ok(Bite(0.0)); }
+        }
+
+        let signlen = if peek_is!(bs,0,b'-') || peek_is!(bs,0,b'+') { 1 } else { 0 };
+        if peek_is!(bs,signlen,b'0') {
+            let radix = match peek_n!(bs,signlen+1) {
+                Some(b'x') | Some(b'X') => 16,
+                Some(b'o') | Some(b'O') => 8,
+                Some(b'b') | Some(b'B') => 2,
+                _ => 0,
+            };
+            if radix!=0 { return Ok(Bite(Self::read_radix_const(bs,signlen,radix)?)); }
+        }
+
+        let mut toklen=0;  let mut sign_ok=true;  let mut specials_ok=true;  let mut suffix_ok=true;  let mut saw_val=false;  let mut saw_sep=false;
         loop {
             match peek_n!(bs, toklen) {
                 None => break,
@@ -354,10 +834,14 @@ impl Parser {
                         saw_val = true;
                         sign_ok=false; specials_ok=false;
                         toklen = toklen+1;
+                    } else if b==b'_' && toklen>0 && peek_n!(bs,toklen-1).map_or(false, |p| p.is_ascii_digit() || p==b'_') {
+                        // Digit separator.  It must also be followed by a digit, which is checked below.
+                        saw_sep = true;
+                        toklen+=1;
                     } else if sign_ok && (b==b'-' || b==b'+') {
                         sign_ok = false;
                         toklen = toklen+1;
-                    } else if saw_val && (b==b'e' || b==b'E') {
+                    } else if saw_val && (b==b'e' || b==b'E') && (!self.implicit_mul || Self::is_exponent(bs,toklen+1)) {
                         suffix_ok = false;
                         sign_ok = true;
                         toklen = toklen+1;
@@ -378,7 +862,14 @@ impl Parser {
 
         if !saw_val { return Ok(Pass); }
 
-        let mut tok = unsafe { from_utf8_unchecked(&bs[..toklen]) };
+        let raw = unsafe { from_utf8_unchecked(&bs[..toklen]) };
+        let mut use_buf = false;
+        if saw_sep {
+            if !Self::separators_ok(raw.as_bytes(),10) { return Err(err_at!(Error::ParseF64(raw.to_string()), bs, toklen)); }
+            slab.char_buf.clear();
+            slab.char_buf.extend(raw.chars().filter(|&c| c!='_'));
+            use_buf = true;
+        }
         if suffix_ok {
             match peek_n!(bs,toklen) {
                 None => (),
@@ -395,25 +886,68 @@ impl Parser {
                         b'p' => (-12,1),
                         _ => (0,0),
                     };
-                    if exp!=0 {
-                        slab.char_buf.clear();
-                        slab.char_buf.push_str(tok);
+                    // With implicit multiplication, '2kg' means '2*kg', not '2000*g':
+                    let standalone = !self.implicit_mul || Self::varname_char_len(bs,toklen+suffixlen,false)==0;
+                    if exp!=0 && standalone {
+                        if !use_buf {
+                            slab.char_buf.clear();
+                            slab.char_buf.push_str(raw);
+                        }
                         slab.char_buf.push('e');
                         slab.char_buf.push_str(&exp.to_string());
-                        tok = &slab.char_buf;
+                        use_buf = true;
 
                         toklen = toklen+suffixlen;
                     }
                 }
             }
         }
+        let tok = if use_buf { slab.char_buf.as_str() } else { raw };
 
-        let val = tok.parse::<f64>().map_err(|_| { Error::ParseF64(tok.to_string()) })?;
+        let val = tok.parse::<f64>().map_err(|_| err_at!(Error::ParseF64(tok.to_string()), bs, toklen))?;
         skip_n!(bs,toklen);
 
         Ok(Bite(val))
     }
 
+    // Reads a hex/octal/binary integer.  'signlen' is the length of the optional sign before the '0x'/'0o'/'0b' prefix.
+    fn read_radix_const(bs:&mut &[u8], signlen:usize, radix:u32) -> Result<f64,Error> {
+        let start = signlen+2;
+        let mut toklen = start;
+        // Consume everything that looks like part of the literal, so that malformed literals are reported as a whole:
+        while Self::is_varname_byte_opt(peek_n!(bs,toklen),1) || peek_is!(bs,toklen,b'.') { toklen+=1; }
+        let tok = unsafe { from_utf8_unchecked(&bs[..toklen]) };
+
+        let digits = &bs[start..toklen];
+        if digits.is_empty() || !Self::separators_ok(digits,radix) { return Err(err_at!(Error::ParseF64(tok.to_string()), bs, toklen)); }
+        let mut val = 0f64;
+        for &b in digits {
+            if b==b'_' { continue; }
+            match (b as char).to_digit(radix) {
+                Some(d) => val = val*f64::from(radix) + f64::from(d),  // Floats don't overflow.
+                None => return Err(err_at!(Error::ParseF64(tok.to_string()), bs, toklen)),
+            }
+        }
+        if peek_is!(bs,0,b'-') { val = -val; }
+
+        skip_n!(bs,toklen);
+        Ok(val)
+    }
+
+    // A '_' digit separator must be between digits.  (Repeated separators are allowed.)
+    fn separators_ok(tok:&[u8], radix:u32) -> bool {
+        if tok.first()==Some(&b'_') { return false; }
+        for (i,&b) in tok.iter().enumerate() {
+            if b==b'_' {
+                match tok.get(i+1) {
+                    Some(&n) if n==b'_' || (n as char).is_digit(radix) => (),
+                    _ => return false,
+                }
+            }
+        }
+        true
+    }
+
     // // This implementation is beautiful and correct, but it is slow due to the fact that I am first parsing everything,
     // // and then I'm calling parse::<f64> which repeats the entire process.
     // // I wish I could just call dec2flt::convert() ( https://doc.rust-lang.org/src/core/num/dec2flt/mod.rs.html#247 )
@@ -501,6 +1035,38 @@ impl Parser {
     //     Ok(Bite(val))
     // }
 
+    // Square brackets are either parentheses, like `[x+1]`, or an array with
+    // commas, like `[]`, `[x,]` or `[1, 2, x]`.
+    fn read_brackets(&self, slab:&mut ParseSlab, bs:&mut &[u8], depth:usize) -> Result<Value,Error> {
+        skip!(bs);
+        spaces!(bs);
+        if peek_is!(bs,0,b']') {
+            skip!(bs);
+            return Ok(EArray(Vec::new()));
+        }
+        let mut xis = Vec::<ExpressionI>::new();
+        loop {
+            xis.push(self.read_expression(slab,bs,depth+1,false)?);
+            spaces!(bs);
+            let close = *bs;
+            match read!(bs,"square brackets")? {
+                b']' => break,
+                b',' => {
+                    spaces!(bs);
+                    if peek_is!(bs,0,b']') {
+                        skip!(bs);
+                        return Ok(EArray(xis));
+                    }
+                }
+                _ => return Err(err_at!(Error::Expected("]".to_string()), close)),
+            }
+        }
+        if xis.len()==1 {
+            if let Some(xi) = xis.pop() { return Ok(EUnaryOp(EParentheses(xi))); }
+        }
+        Ok(EArray(xis))
+    }
+
     fn read_unaryop(&self, slab:&mut ParseSlab, bs:&mut &[u8], depth:usize) -> Result<Token<UnaryOp>,Error> {
         spaces!(bs);
         match peek!(bs) {
@@ -513,21 +1079,16 @@ impl Parser {
                 }
                 b'-' => {
                     skip!(bs);
-                    let v = self.read_value(slab,bs,depth+1)?;
+                    let mut v = self.read_value(slab,bs,depth+1)?;
+                    if self.neg_below_exp { v = self.read_exp_chain(slab,bs,depth+1,v)?; }
                     Ok(Bite(ENeg(slab.push_val(v)?)))
                 }
                 b'(' => {
                     skip!(bs);
                     let xi = self.read_expression(slab,bs,depth+1,false)?;
                     spaces!(bs);
-                    if read!(bs,"parentheses")? != b')' { return Err(Error::Expected(")".to_string())); }
-                    Ok(Bite(EParentheses(xi)))
-                }
-                b'[' => {
-                    skip!(bs);
-                    let xi = self.read_expression(slab,bs,depth+1,false)?;
-                    spaces!(bs);
-                    if read!(bs,"square brackets")? != b']' { return Err(Error::Expected("]".to_string())); }
+                    let close = *bs;
+                    if read!(bs,"parentheses")? != b')' { return Err(err_at!(Error::Expected(")".to_string()), close)); }
                     Ok(Bite(EParentheses(xi)))
                 }
                 b'!' => {
@@ -535,11 +1096,41 @@ impl Parser {
                     let v = self.read_value(slab,bs,depth+1)?;
                     Ok(Bite(ENot(slab.push_val(v)?)))
                 }
+                #[cfg(feature="alpha-keywords")]
+                b'n' if Self::is_keyword(bs,b"not") => {
+                    skip_n!(bs,3);
+                    let v = self.read_value(slab,bs,depth+1)?;
+                    Ok(Bite(ENot(slab.push_val(v)?)))
+                }
+                #[cfg(feature="bitwise")]
+                b'~' => {
+                    skip!(bs);
+                    let v = self.read_value(slab,bs,depth+1)?;
+                    Ok(Bite(EBitNot(slab.push_val(v)?)))
+                }
                 _ => Ok(Pass),
             }
         }
     }
 
+    // Used when `^` binds tighter than unary minus: extends `first` with any following `^` operations,
+    // so that `-2^3^2` negates the whole `2^3^2`.
+    fn read_exp_chain(&self, slab:&mut ParseSlab, bs:&mut &[u8], depth:usize, first:Value) -> Result<Value,Error> {
+        let mut pairs = Vec::<ExprPair>::new();
+        loop {
+            let save = *bs;
+            match self.read_binaryop(bs)? {
+                Bite(EExp) => {}
+                _ => { *bs=save; break }
+            }
+            let val = self.read_value(slab,bs,depth)?;
+            pairs.push(ExprPair(EExp,val));
+        }
+        if pairs.is_empty() { return Ok(first); }
+        let xi = slab.push_expr(Expression{first, pairs, flags:0, index:None})?;
+        Ok(EUnaryOp(EParentheses(xi)))
+    }
+
     fn read_binaryop(&self, bs:&mut &[u8]) -> Result<Token<BinaryOp>,Error> {
         spaces!(bs);
         match peek!(bs) {
@@ -548,9 +1139,19 @@ impl Parser {
                 b'+' => { skip!(bs); Ok(Bite(EAdd)) }
                 b'-' => { skip!(bs); Ok(Bite(ESub)) }
                 b'*' => { skip!(bs); Ok(Bite(EMul)) }
+                #[cfg(feature="bitwise")]
+                b'/' if peek_is!(bs,1,b'/') => { skip_n!(bs,2);
+                                                Ok(Bite(EIntDiv)) }
+                b'/' if peek_is!(bs,1,b'*') => Err(Error::EofWhileParsing("comment".to_string())),  // spaces!() would have skipped a terminated comment.
                 b'/' => { skip!(bs); Ok(Bite(EDiv)) }
                 b'%' => { skip!(bs); Ok(Bite(EMod)) }
                 b'^' => { skip!(bs); Ok(Bite(EExp)) }
+                #[cfg(feature="bitwise")]
+                b'<' if peek_is!(bs,1,b'<') => { skip_n!(bs,2);
+                                                Ok(Bite(EShl)) }
+                #[cfg(feature="bitwise")]
+                b'>' if peek_is!(bs,1,b'>') => { skip_n!(bs,2);
+                                                Ok(Bite(EShr)) }
                 b'<' => { skip!(bs);
                           if peek_is!(bs,0,b'=') { skip!(bs); Ok(Bite(ELTE)) }
                           else { Ok(Bite(ELT)) } }
@@ -571,6 +1172,13 @@ impl Parser {
                                                                       Ok(Bite(EAND)) }
                 b'&' if peek_is!(bs,1,b'&') => { skip_n!(bs,2);
                                                 Ok(Bite(EAND)) }
+                #[cfg(feature="bitwise")]
+                b'&' => { skip!(bs); Ok(Bite(EBitAnd)) }
+                #[cfg(feature="bitwise")]
+                b'|' => { skip!(bs); Ok(Bite(EBitOr)) }
+                #[cfg(all(feature="bitwise", feature="alpha-keywords"))]
+                b'x' if peek_is!(bs,1,b'o') && peek_is!(bs,2,b'r') => { skip_n!(bs,3);
+                                                                      Ok(Bite(EBitXor)) }
                 _ => Ok(Pass),
             }
         }
@@ -579,10 +1187,23 @@ impl Parser {
     fn read_callable(&self, slab:&mut ParseSlab, bs:&mut &[u8], depth:usize) -> Result<Token<Value>,Error> {
         match Self::read_varname(bs)? {
             Pass => Ok(Pass),
-            Bite(varname) => {
+            Bite(mut varname) => {
+                if self.dotted_names {
+                    // Append '.Name' segments.  There must be no spaces around the dots:
+                    while peek_is!(bs,0,b'.') && (peek_is!(bs,1,b'`') || Self::varname_char_len(bs,1,true)>0) {
+                        skip!(bs);
+                        if let Bite(segment) = Self::read_varname(bs)? {
+                            varname.push('.');
+                            varname.push_str(&segment);
+                        }
+                    }
+                }
+
+                let name_start = bs.len()+varname.len();
                 match Self::read_open_parenthesis(bs)? {
                     Pass => {
-                        // VarNames without Parenthesis are always treated as custom 0-arg functions.
+                        // VarNames without Parenthesis are always treated as custom 0-arg functions, unless they were bound with `name = value;`.
+                        if let Some(slot) = slab.find_local(&varname) { return Ok(Bite(EStdFunc(ELocal(slot)))); }
 
                         #[cfg(feature="unsafe-vars")]
                         match slab.unsafe_vars.get(&varname) {
@@ -595,9 +1216,15 @@ impl Parser {
                     }
                     Bite(open_parenth) => {
                         // VarNames with Parenthesis are first matched against builtins, then custom.
-                        match varname.as_ref() {
-                            "print" => Ok(Bite(EPrintFunc(self.read_printfunc(slab,bs,depth,open_parenth)?))),
-                            _ => Ok(Bite(EStdFunc(self.read_func(varname,slab,bs,depth,open_parenth)?))),
+                        match self.builtin_name(&varname) {
+                            Some("print") => Ok(Bite(EPrintFunc(self.read_printfunc(name_start,slab,bs,depth,open_parenth)?))),
+                            Some("sum") | Some("prod") if slab.find_func(&varname).is_none() => {
+                                match self.read_seriesfunc(&varname,slab,bs,depth,open_parenth)? {
+                                    Some(f) => Ok(Bite(EStdFunc(f))),
+                                    None => Ok(Bite(EStdFunc(self.read_func(varname,name_start,slab,bs,depth,open_parenth)?))),
+                                }
+                            }
+                            _ => Ok(Bite(EStdFunc(self.read_func(varname,name_start,slab,bs,depth,open_parenth)?))),
                         }
                     }
                 }
@@ -607,9 +1234,14 @@ impl Parser {
 
     fn read_varname(bs:&mut &[u8]) -> Result<Token<String>,Error> {
         spaces!(bs);
+        if peek_is!(bs,0,b'`') { return Ok(Bite(Self::read_quoted_varname(bs)?)); }
 
         let mut toklen = 0;
-        while Self::is_varname_byte_opt(peek_n!(bs,toklen),toklen) { toklen=toklen+1; }
+        loop {
+            let charlen = Self::varname_char_len(bs,toklen,toklen==0);
+            if charlen==0 { break; }
+            toklen+=charlen;
+        }
 
         if toklen==0 { return Ok(Pass); }
 
@@ -618,6 +1250,45 @@ impl Parser {
         Ok(Bite(out))
     }
 
+    // Reads a `quoted name`, for names that contain spaces or operators, like `Gross Margin (%)`.
+    // A literal backtick is written as two backticks.
+    fn read_quoted_varname(bs:&mut &[u8]) -> Result<String,Error> {
+        let start = *bs;
+        skip!(bs);
+        let mut raw = Vec::<u8>::new();
+        loop {
+            match read!(bs) {
+                Err(Error::EOF) => return Err(Error::EofWhileParsing("quoted name".to_string())),
+                Err(_) => return Err(Error::Unreachable),
+                Ok(b'`') => {
+                    if !peek_is!(bs,0,b'`') { break; }
+                    skip!(bs);
+                    raw.push(b'`');
+                }
+                Ok(b) => raw.push(b),
+            }
+        }
+        if raw.is_empty() { return Err(err_at!(Error::Expected("a name between the backticks".to_string()), start, 2)); }
+        String::from_utf8(raw).map_err(|_| err_at!(Error::Utf8ErrorWhileParsing("quoted name".to_string()), start, start.len()-bs.len()))
+    }
+
+    /// Returns `name` in a form that can be used within an expression:  plain
+    /// names are returned unchanged, and anything else is quoted with backticks.
+    /// This is the inverse of the quoting done by the parser, so the names
+    /// reported by `var_names()` can always be inserted back into an expression.
+    pub fn quote_name(name:&str) -> String {
+        let bs = name.as_bytes();
+        let mut i = 0;
+        while i<bs.len() {
+            let charlen = Self::varname_char_len(bs,i,i==0);
+            if charlen==0 { break; }
+            i+=charlen;
+        }
+        let keyword = matches!(name, "let" | "and" | "or" | "not" | "xor" | "NaN" | "inf" | "true" | "false");
+        if i>0 && i==bs.len() && !keyword { return name.to_string(); }
+        format!("`{}`", name.replace('`', "``"))
+    }
+
     fn read_open_parenthesis(bs:&mut &[u8]) -> Result<Token<u8>,Error> {
         spaces!(bs);
 
@@ -630,7 +1301,7 @@ impl Parser {
         }
     }
 
-    fn read_func(&self, fname:String, slab:&mut ParseSlab, bs:&mut &[u8], depth:usize, open_parenth:u8) -> Result<StdFunc,Error> {
+    fn read_func(&self, fname:String, name_start:usize, slab:&mut ParseSlab, bs:&mut &[u8], depth:usize, open_parenth:u8) -> Result<StdFunc,Error> {
         let close_parenth = match open_parenth {
             b'(' => b')',
             b'[' => b']',
@@ -649,18 +1320,54 @@ impl Parser {
                 None => return Err(Error::EofWhileParsing(fname)),
             }
             if !args.is_empty() {
+                let sep = *bs;
                 match read!(bs) {
                     Ok(b',') | Ok(b';') => {
                         // I accept ',' or ';' because the TV API disallows the ',' char in symbols... so I'm using ';' as a compromise.
                     }
-                    _ => return Err(Error::Expected("',' or ';'".to_string())),
+                    _ => return Err(err_at!(Error::Expected("',' or ';'".to_string()), sep)),
                 }
             }
             args.push(self.read_expression(slab,bs,depth+1,false)?);
         }
 
-        let fname_str = fname.as_str();
-        match fname_str {
+        // Arg errors point at the whole function call:
+        let call_span = |err| Error::Spanned{err:Box::new(err), start:name_start, end:bs.len()};
+
+        // Functions defined within the expression take priority over the builtins:
+        if let Some(func) = slab.find_func(&fname) {
+            let want = slab.funcs.get(func).map_or(0, |uf| uf.params.len());
+            if args.len()!=want {
+                return Err(call_span(Error::WrongArgs(format!("{}: expected {} arg{}", fname, want, if want==1 {""} else {"s"}))));
+            }
+            return Ok(EUserFunc{func, args});
+        }
+
+        // ...then the registered native functions:
+        if let Some(func) = self.native_funcs.iter().find(|nf| nf.name==fname) {
+            if args.len()!=func.arity {
+                return Err(call_span(Error::WrongArgs(format!("{}: expected {} arg{}", fname, func.arity, if func.arity==1 {""} else {"s"}))));
+            }
+            return Ok(ENativeFunc{func:*func, args});
+        }
+
+        let fname_str = self.builtin_name(&fname).unwrap_or("");
+        let is_stat = NAMESPACE_FIRST_BY_DEFAULT.contains(&fname_str);
+
+        // A copy of the call, in case the namespace gets to answer it first.  The builtin then reads
+        // the already-evaluated args from unnamed local slots, so that they are only evaluated once.
+        // (The aggregates ask the namespace themselves, because their args can be arrays.)
+        let ns_first = if self.namespace_first(&fname) && !is_stat {
+            let (call_args, scope_len, slot) = (args.clone(), slab.scope.len(), slab.locals.len());
+            for xi in args.iter_mut() {
+                let local = slab.push_local(String::new())?;
+                *xi = slab.push_expr(Expression{first:EStdFunc(ELocal(local)), pairs:Vec::new(), flags:0, index:None})?;
+            }
+            slab.scope.truncate(scope_len);
+            Some((fname.clone(), call_args, slot))
+        } else { None };
+
+        let out = match fname_str {
             "int" => {
                 if args.len()==1 { Ok(EFuncInt(match args.pop() {
                                                    Some(xi) => xi,
@@ -746,6 +1453,58 @@ impl Parser {
                     }
                 } else { Err(Error::WrongArgs("max: expected one or more args".to_string())) }
             }
+            "str" | "len" | "upper" | "lower" | "contains" | "startswith" | "endswith" => {
+                let (func, nargs) = match fname_str {
+                    "str" => (StrFunc::Str, 1),
+                    "len" => (StrFunc::Len, 1),
+                    "upper" => (StrFunc::Upper, 1),
+                    "lower" => (StrFunc::Lower, 1),
+                    "contains" => (StrFunc::Contains, 2),
+                    "startswith" => (StrFunc::StartsWith, 2),
+                    _ => (StrFunc::EndsWith, 2),
+                };
+                if args.len()==nargs { Ok(EFuncStr{func, args}) }
+                else if nargs==1 { Err(Error::WrongArgs(format!("{}: expected one arg", fname_str))) }
+                else { Err(Error::WrongArgs(format!("{}: expected two args", fname_str))) }
+            }
+            "sum" | "mean" | "median" | "stddev" | "variance" | "count" | "percentile" |
+            "nansum" | "nanmean" | "nanmedian" | "nanstddev" | "nanvariance" | "nancount" | "nanpercentile" => {
+                let skip_nan = fname_str.starts_with("nan");
+                let func = match fname_str.trim_start_matches("nan") {
+                    "sum" => StatFunc::Sum,
+                    "mean" => StatFunc::Mean,
+                    "median" => StatFunc::Median,
+                    "stddev" => StatFunc::StdDev,
+                    "variance" => StatFunc::Variance,
+                    "count" => StatFunc::Count,
+                    _ => StatFunc::Percentile,
+                };
+                if func==StatFunc::Percentile && args.len()<2 {
+                    Err(Error::WrongArgs(format!("{}: expected a percentile and one or more args", fname_str)))
+                } else if !args.is_empty() {
+                    match remove_no_panic(&mut args, 0) {
+                        Some(first) => Ok(EFuncStat{func, skip_nan, ns_first:if self.namespace_first(&fname) { Some(fname.clone()) } else { None }, first, rest:args}),
+                        None => Err(Error::Unreachable),
+                    }
+                } else { Err(Error::WrongArgs(format!("{}: expected one or more args", fname_str))) }
+            }
+            "if" => {
+                if args.len()==3 {
+                    let otherwise = match args.pop() {
+                                        Some(xi) => xi,
+                                        None => return Err(Error::Unreachable),
+                                    };
+                    let then = match args.pop() {
+                                   Some(xi) => xi,
+                                   None => return Err(Error::Unreachable),
+                               };
+                    Ok(EFuncIf{cond:match args.pop() {
+                                        Some(xi) => xi,
+                                        None => return Err(Error::Unreachable),
+                                    },
+                               then, otherwise})
+                } else { Err(Error::WrongArgs("if: expected if(cond, then, else)".to_string())) }
+            }
 
             "e" => {
                 if args.is_empty() { Ok(EFuncE)
@@ -840,21 +1599,249 @@ impl Parser {
                                                  }))
                 } else { Err(Error::WrongArgs("atanh: expected one arg".to_string())) }
             }
+            "sqrt" => {
+                if args.len()==1 { Ok(EFuncSqrt(match args.pop() {
+                                                       Some(xi) => xi,
+                                                       None => return Err(Error::Unreachable),
+                                                     }))
+                } else { Err(Error::WrongArgs("sqrt: expected one arg".to_string())) }
+            }
+            "cbrt" => {
+                if args.len()==1 { Ok(EFuncCbrt(match args.pop() {
+                                                       Some(xi) => xi,
+                                                       None => return Err(Error::Unreachable),
+                                                     }))
+                } else { Err(Error::WrongArgs("cbrt: expected one arg".to_string())) }
+            }
+            "exp" => {
+                if args.len()==1 { Ok(EFuncExp(match args.pop() {
+                                                      Some(xi) => xi,
+                                                      None => return Err(Error::Unreachable),
+                                                    }))
+                } else { Err(Error::WrongArgs("exp: expected one arg".to_string())) }
+            }
+            "ln" => {
+                if args.len()==1 { Ok(EFuncLn(match args.pop() {
+                                                     Some(xi) => xi,
+                                                     None => return Err(Error::Unreachable),
+                                                   }))
+                } else { Err(Error::WrongArgs("ln: expected one arg".to_string())) }
+            }
+            "log2" => {
+                if args.len()==1 { Ok(EFuncLog2(match args.pop() {
+                                                       Some(xi) => xi,
+                                                       None => return Err(Error::Unreachable),
+                                                     }))
+                } else { Err(Error::WrongArgs("log2: expected one arg".to_string())) }
+            }
+            "log10" => {
+                if args.len()==1 { Ok(EFuncLog10(match args.pop() {
+                                                        Some(xi) => xi,
+                                                        None => return Err(Error::Unreachable),
+                                                      }))
+                } else { Err(Error::WrongArgs("log10: expected one arg".to_string())) }
+            }
+            "trunc" => {
+                if args.len()==1 { Ok(EFuncTrunc(match args.pop() {
+                                                        Some(xi) => xi,
+                                                        None => return Err(Error::Unreachable),
+                                                      }))
+                } else { Err(Error::WrongArgs("trunc: expected one arg".to_string())) }
+            }
+            "frac" => {
+                if args.len()==1 { Ok(EFuncFrac(match args.pop() {
+                                                       Some(xi) => xi,
+                                                       None => return Err(Error::Unreachable),
+                                                     }))
+                } else { Err(Error::WrongArgs("frac: expected one arg".to_string())) }
+            }
+            "deg" => {
+                if args.len()==1 { Ok(EFuncDeg(match args.pop() {
+                                                      Some(xi) => xi,
+                                                      None => return Err(Error::Unreachable),
+                                                    }))
+                } else { Err(Error::WrongArgs("deg: expected one arg".to_string())) }
+            }
+            "rad" => {
+                if args.len()==1 { Ok(EFuncRad(match args.pop() {
+                                                      Some(xi) => xi,
+                                                      None => return Err(Error::Unreachable),
+                                                    }))
+                } else { Err(Error::WrongArgs("rad: expected one arg".to_string())) }
+            }
+            "erf" => {
+                if args.len()==1 { Ok(EFuncErf(match args.pop() {
+                                                      Some(xi) => xi,
+                                                      None => return Err(Error::Unreachable),
+                                                    }))
+                } else { Err(Error::WrongArgs("erf: expected one arg".to_string())) }
+            }
+            "gamma" => {
+                if args.len()==1 { Ok(EFuncGamma(match args.pop() {
+                                                        Some(xi) => xi,
+                                                        None => return Err(Error::Unreachable),
+                                                      }))
+                } else { Err(Error::WrongArgs("gamma: expected one arg".to_string())) }
+            }
+            "lgamma" => {
+                if args.len()==1 { Ok(EFuncLGamma(match args.pop() {
+                                                         Some(xi) => xi,
+                                                         None => return Err(Error::Unreachable),
+                                                       }))
+                } else { Err(Error::WrongArgs("lgamma: expected one arg".to_string())) }
+            }
+            "atan2" => {
+                if let [a, b] = args[..] { Ok(EFuncATan2(a, b)) }
+                else { Err(Error::WrongArgs("atan2: expected atan2(y, x)".to_string())) }
+            }
+            "hypot" => {
+                if let [a, b] = args[..] { Ok(EFuncHypot(a, b)) }
+                else { Err(Error::WrongArgs("hypot: expected hypot(x, y)".to_string())) }
+            }
+            "pow" => {
+                if let [a, b] = args[..] { Ok(EFuncPow(a, b)) }
+                else { Err(Error::WrongArgs("pow: expected pow(x, y)".to_string())) }
+            }
+            "fmod" => {
+                if let [a, b] = args[..] { Ok(EFuncFMod(a, b)) }
+                else { Err(Error::WrongArgs("fmod: expected fmod(x, y)".to_string())) }
+            }
+            "clamp" => {
+                if let [x, min, max] = args[..] { Ok(EFuncClamp{x, min, max}) }
+                else { Err(Error::WrongArgs("clamp: expected clamp(x, min, max)".to_string())) }
+            }
 
             _ => {
                 #[cfg(feature="unsafe-vars")]
-                match slab.unsafe_vars.get(fname_str) {
-                    None => Ok(EFunc{name:fname, args}),
+                match slab.unsafe_vars.get(&fname) {
+                    None => Ok(Self::custom_func(fname, args, open_parenth)),
                     Some(&ptr) => Ok(EUnsafeVar{name:fname, ptr}),
                 }
 
                 #[cfg(not(feature="unsafe-vars"))]
-                Ok(EFunc{name:fname, args})
+                Ok(Self::custom_func(fname, args, open_parenth))
+            }
+        };
+        let out = if self.degrees { out.and_then(|f| Self::use_degrees(slab,f)) } else { out };
+        match (out, ns_first) {
+            (Ok(EFunc{..}), Some((name, args, _))) | (Ok(EIndex{..}), Some((name, args, _))) => Ok(Self::custom_func(name, args, open_parenth)),
+            (Ok(f @ EFunc{..}), None) | (Ok(f @ EIndex{..}), None) => Ok(f),
+            (Ok(builtin), Some((name, args, slot))) => {
+                let builtin = slab.push_expr(Expression{first:EStdFunc(builtin), pairs:Vec::new(), flags:0, index:None})?;
+                Ok(ENamespaceFirst{name, args, slot, builtin})
+            }
+            (out, _) => out.map_err(call_span),
+        }
+    }
+
+    // A call to a function that isn't a builtin.  `name[i]` might also index an array variable.
+    fn custom_func(name:String, mut args:Vec<ExpressionI>, open_parenth:u8) -> StdFunc {
+        if open_parenth==b'[' && args.len()==1 {
+            if let Some(idx) = args.pop() { return EIndex{name, idx}; }
+        }
+        EFunc{name, args}
+    }
+
+    // Makes trig functions measure angles in degrees.  sin(), cos() and tan() get variants
+    // that are exact at multiples of 90 degrees, and the inverse functions are wrapped with deg().
+    // The compiler folds these like any other function.
+    fn use_degrees(slab:&mut ParseSlab, f:StdFunc) -> Result<StdFunc,Error> {
+        match f {
+            EFuncSin(xi) => Ok(EFuncSinD(xi)),
+            EFuncCos(xi) => Ok(EFuncCosD(xi)),
+            EFuncTan(xi) => Ok(EFuncTanD(xi)),
+            EFuncASin(_) | EFuncACos(_) | EFuncATan(_) | EFuncATan2(..) => {
+                Ok(EFuncDeg(slab.push_expr(Expression{first:EStdFunc(f), pairs:Vec::new(), flags:0, index:None})?))
+            }
+            _ => Ok(f),
+        }
+    }
+
+    // Returns the name of the builtin function that `name` refers to, after applying
+    // the `builtins` rules, or None if `name` should be treated as a custom function.
+    fn builtin_name<'a>(&'a self, name:&'a str) -> Option<&'a str> {
+        let mut out = Some(name);
+        for rule in &self.builtins {
+            match rule {
+                BuiltinRule::Disable(n) if n==name => out = None,
+                BuiltinRule::Alias{alias, builtin} if alias==name => out = Some(builtin.as_str()),
+                _ => (),
+            }
+        }
+        out
+    }
+    fn namespace_first(&self, name:&str) -> bool {
+        let mut out = NAMESPACE_FIRST_BY_DEFAULT.contains(&name);
+        for rule in &self.builtins {
+            match rule {
+                BuiltinRule::NamespaceFirst(n) if n==name => out = true,
+                BuiltinRule::BuiltinFirst(n) if n==name => out = false,
+                _ => (),
+            }
+        }
+        out
+    }
+
+    // sum(var, start, end, expr) and prod(var, start, end, expr).  `var` is only visible within `expr`.
+    // Calls that don't have this form (a bare VarName that isn't a local, followed by exactly
+    // three more args) are rolled back and return None, so they can be parsed as ordinary calls.
+    fn read_seriesfunc<'b>(&self, fname:&str, slab:&mut ParseSlab, bs:&mut &'b [u8], depth:usize, open_parenth:u8) -> Result<Option<StdFunc>,Error> {
+        let close_parenth = match open_parenth {
+            b'(' => b')',
+            b'[' => b']',
+            _ => return Err(Error::Expected("'(' or '['".to_string())),
+        };
+        let (bs_start, exprs_len, vals_len, locals_len, scope_len) = (*bs, slab.exprs.len(), slab.vals.len(), slab.locals.len(), slab.scope.len());
+        let rollback = |slab:&mut ParseSlab, bs:&mut &'b [u8]| {
+            *bs = bs_start;
+            slab.exprs.truncate(exprs_len);
+            slab.vals.truncate(vals_len);
+            slab.locals.truncate(locals_len);
+            slab.scope.truncate(scope_len);
+            Ok(None)
+        };
+
+        let var = match Self::read_varname(bs)? {
+            Bite(var) if slab.find_local(&var).is_none() => var,
+            _ => return rollback(slab,bs),
+        };
+        let mut args = Vec::<ExpressionI>::with_capacity(3);
+        let mut slot = 0;
+        while args.len()<3 {
+            spaces!(bs);
+            let sep = *bs;
+            match peek!(bs) {
+                Some(b',') | Some(b';') => { skip!(bs); }
+                Some(b) if b==close_parenth => return rollback(slab,bs),
+                Some(_) if args.is_empty() => return rollback(slab,bs),
+                Some(_) => return Err(err_at!(Error::Expected("',' or ';'".to_string()), sep)),
+                None => return Err(Error::EofWhileParsing(fname.to_string())),
+            }
+            if args.len()==2 {
+                slot = slab.push_local(var.clone())?;
+                args.push(self.read_expression(slab,bs,depth+1,false)?);
+                slab.scope.truncate(scope_len);  // The index variable is only visible within `expr`.
+            } else {
+                args.push(self.read_expression(slab,bs,depth+1,false)?);
             }
         }
+        spaces!(bs);
+        match peek!(bs) {
+            Some(b) if b==close_parenth => { skip!(bs); }
+            Some(b',') | Some(b';') => return rollback(slab,bs),
+            Some(_) => return Err(err_at!(Error::Expected((close_parenth as char).to_string()), bs)),
+            None => return Err(Error::EofWhileParsing(fname.to_string())),
+        }
+
+        let (start, end, expr) = match args.as_slice() {
+            [start, end, expr] => (*start, *end, *expr),
+            _ => return Err(Error::Unreachable),
+        };
+        if self.builtin_name(fname)==Some("sum") { Ok(Some(EFuncSum{slot, start, end, expr})) }
+        else { Ok(Some(EFuncProd{slot, start, end, expr})) }
     }
 
-    fn read_printfunc(&self, slab:&mut ParseSlab, bs:&mut &[u8], depth:usize, open_parenth:u8) -> Result<PrintFunc,Error> {
+    fn read_printfunc(&self, name_start:usize, slab:&mut ParseSlab, bs:&mut &[u8], depth:usize, open_parenth:u8) -> Result<PrintFunc,Error> {
         let close_parenth = match open_parenth {
             b'(' => b')',
             b'[' => b']',
@@ -873,21 +1860,39 @@ impl Parser {
                 None => { return Err(Error::EofWhileParsing("print".to_string())); }
             }
             if !args.is_empty() {
+                let sep = *bs;
                 match read!(bs) {
                     Ok(b',') | Ok(b';') => {}
-                    _ => { return Err(Error::Expected("',' or ';'".to_string())); }
+                    _ => { return Err(err_at!(Error::Expected("',' or ';'".to_string()), sep)); }
                 }
             }
             args.push(self.read_expressionorstring(slab,bs,depth+1)?);
         }
 
+        // Check printf formats now, so mistakes are found even if print() is never evaluated:
+        if let Some(EStr(fmtstr)) = args.first() {
+            if fmtstr.contains('%') {
+                // The type of an expression that might be a string isn't known until eval:
+                let is_str : Vec<Option<bool>> = args[1..].iter().map(|a| match a {
+                    EExpr(xi) => if slab.get_expr(*xi).needs_val() { None } else { Some(false) },
+                    EStr(_) => Some(true),
+                }).collect();
+                printf::check(fmtstr, &is_str).map_err(|err| Error::Spanned{err:Box::new(err), start:name_start, end:bs.len()})?;
+            }
+        }
+
         Ok(PrintFunc(args))
     }
 
     fn read_expressionorstring(&self, slab:&mut ParseSlab, bs:&mut &[u8], depth:usize) -> Result<ExpressionOrString,Error> {
-        match Self::read_string(bs)? {
-            Pass => {}
-            Bite(s) => return Ok(EStr(s)),
+        let save = *bs;
+        if let Bite(s) = Self::read_string(bs)? {
+            // A string by itself is an EStr, but a string within an expression (like `"a" + b`) is not:
+            spaces!(bs);
+            match peek!(bs) {
+                Some(b',') | Some(b';') | Some(b')') | Some(b']') | None => return Ok(EStr(s)),
+                _ => *bs=save,
+            }
         }
         Ok(EExpr(self.read_expression(slab,bs,depth+1,false)?))
     }
@@ -909,7 +1914,7 @@ impl Parser {
             Some(_) => true,
         } { toklen=toklen+1; }
 
-        let out = from_utf8(&bs[..toklen]).map_err(|_| Error::Utf8ErrorWhileParsing("string".to_string()))?;
+        let out = from_utf8(&bs[..toklen]).map_err(|_| err_at!(Error::Utf8ErrorWhileParsing("string".to_string()), bs, toklen))?;
         skip_n!(bs, toklen);
         match read!(bs) {
             Err(Error::EOF) => Err(Error::EofWhileParsing("string".to_string())),
@@ -924,12 +1929,38 @@ impl Default for Parser {
     fn default() -> Self { Self::new() }
 }
 impl Default for Expression {
-    fn default() -> Self { Expression{first:Default::default(), pairs:Vec::new()} }
+    fn default() -> Self { Expression{first:Default::default(), pairs:Vec::new(), flags:0, index:None} }
+}
+// Only mention the flags when some are set, to keep the common output compact:
+impl fmt::Debug for Expression {
+    fn fmt(&self, f:&mut fmt::Formatter) -> fmt::Result {
+        let mut d = f.debug_struct("Expression");
+        d.field("first", &self.first).field("pairs", &self.pairs);
+        if self.flags!=0 { d.field("flags", &self.flags); }
+        d.finish()
+    }
 }
 impl Default for Value {
     fn default() -> Self { EConstant(std::f64::NAN) }
 }
 
+// Returns the length of the comment at the start of bs, or 0 if there isn't one.
+// An unterminated '/*' isn't skipped, so that read_value() and read_binaryop() can report it.
+fn comment_len(bs:&[u8]) -> usize {
+    match bs {
+        [b'#', ..] => bs.iter().position(|&b| b==b'\n').unwrap_or(bs.len()),
+        [b'/', b'*', rest @ ..] => rest.windows(2).position(|w| w==b"*/").map_or(0, |i| i+4),
+        _ => 0,
+    }
+}
+
+// The length of the UTF8 char at the front of `bs`, so that error spans never split a char:
+fn char_len(bs:&[u8]) -> usize {
+    let mut n = 1;
+    while peek_n!(bs,n).map_or(false, |b| b&0xc0==0x80) { n+=1; }
+    n.min(bs.len())
+}
+
 // A version of Vec::remove that doesn't panic:
 // (Mostly copy-pasted from https://doc.rust-lang.org/src/alloc/vec.rs.html#991-1010 .)
 pub(crate) fn remove_no_panic<T>(vself:&mut Vec<T>, index:usize) -> Option<T> {
@@ -0,0 +1,233 @@
+//! A small, pure-Rust `sprintf()`, used by the `print()` expression function.
+//!
+//! Supported conversions:  `%d %i %f %F %e %E %g %G %x %X %o %s %%`
+//!
+//! Each conversion can have the `-` (left-justify), `+` (always show the sign),
+//! ` ` (space for positive numbers), and `0` (pad with zeros) flags, followed
+//! by a field width and a `.precision`, like `%-8.3f`.  C length modifiers
+//! (`l`, `h`) are accepted and ignored.
+//!
+//! Numbers are `f64`s, so the integer conversions (`%d %x %o`) truncate
+//! towards zero.  NaN and infinity print as `NaN` and `inf`, just like the
+//! rest of `print()`.
+//!
+//! # Examples
+//!
+//! ```
+//! use fasteval::printf::{sprintf, Arg};
+//!
+//! assert_eq!(sprintf("%d items at %.2f = %8.3e", &[Arg::Num(3.0), Arg::Num(1.5), Arg::Num(4.5)]).unwrap(),
+//!            "3 items at 1.50 = 4.500e+00");
+//! assert_eq!(sprintf("%-5s|%05x|%%", &[Arg::Str("ab".to_string()), Arg::Num(255.0)]).unwrap(),
+//!            "ab   |000ff|%");
+//! ```
+
+use crate::error::Error;
+
+/// An argument for a conversion:  `%s` takes a `Str`, and the others take a `Num`.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Arg {
+    Num(f64),
+    Str(String),
+}
+
+// Widths and precisions beyond this are almost certainly mistakes, and would allocate huge strings.
+const MAX_WIDTH : usize = 1000;
+
+#[derive(Debug, Default)]
+struct Spec {
+    minus:bool,
+    plus :bool,
+    space:bool,
+    zero :bool,
+    width:usize,
+    prec :Option<usize>,
+    conv :char,
+}
+
+/// Formats `args` according to `fmt`.
+///
+/// Returns `Error::WrongArgs` if the format is invalid, or if `args` doesn't
+/// match its conversions.
+pub fn sprintf(fmt:&str, args:&[Arg]) -> Result<String,Error> {
+    let specs = parse(fmt)?;
+    let n = check_count(&specs, args.len())?;
+
+    let mut out = String::with_capacity(fmt.len()+8*n);
+    let mut args = args.iter();
+    for (lit, spec) in specs {
+        out.push_str(lit);
+        if let Some(spec) = spec {
+            let arg = args.next().ok_or(Error::Unreachable)?;
+            check_kind(spec.conv, matches!(arg, Arg::Str(_)))?;
+            match arg {
+                Arg::Str(s) => {
+                    let s = match spec.prec {
+                        Some(p) => s.chars().take(p).collect(),
+                        None => s.clone(),
+                    };
+                    out.push_str(&pad(&spec, "", &s, false));
+                }
+                Arg::Num(f) => out.push_str(&format_num(&spec, *f)?),
+            }
+        }
+    }
+    Ok(out)
+}
+
+// Checks a format without formatting anything.  `is_str` has one item per
+// argument, and `None` means that the argument's type won't be known until eval.
+pub(crate) fn check(fmt:&str, is_str:&[Option<bool>]) -> Result<(),Error> {
+    let specs = parse(fmt)?;
+    check_count(&specs, is_str.len())?;
+    for (spec, is_str) in specs.iter().filter_map(|(_,spec)| spec.as_ref()).zip(is_str) {
+        if let Some(is_str) = is_str { check_kind(spec.conv, *is_str)?; }
+    }
+    Ok(())
+}
+
+fn check_count(specs:&[(&str,Option<Spec>)], nargs:usize) -> Result<usize,Error> {
+    let n = specs.iter().filter(|(_,spec)| spec.is_some()).count();
+    if n!=nargs {
+        return Err(Error::WrongArgs(format!("print: the format expects {} values, but got {}", n, nargs)));
+    }
+    Ok(n)
+}
+
+fn check_kind(conv:char, is_str:bool) -> Result<(),Error> {
+    match (conv, is_str) {
+        ('s', false) => Err(Error::WrongArgs("print: %s expects a string".to_string())),
+        (c, true) if c!='s' => Err(Error::WrongArgs(format!("print: %{} expects a number, not a string", c))),
+        _ => Ok(()),
+    }
+}
+
+// Splits the format into pieces of literal text, each followed by an optional conversion.
+// '%%' becomes a literal '%' at the end of a piece.
+fn parse(fmt:&str) -> Result<Vec<(&str,Option<Spec>)>,Error> {
+    let mut out = Vec::new();
+    let mut rest = fmt;
+    while let Some(i) = rest.find('%') {
+        let lit = &rest[..i];
+        let mut chars = rest[i+1..].char_indices().peekable();
+        let mut spec = Spec::default();
+        while let Some(&(_,c)) = chars.peek() {
+            match c {
+                '-' => spec.minus = true,
+                '+' => spec.plus = true,
+                ' ' => spec.space = true,
+                '0' => spec.zero = true,
+                _ => break,
+            }
+            chars.next();
+        }
+        spec.width = read_num(&mut chars)?.unwrap_or(0);
+        if let Some(&(_,'.')) = chars.peek() {
+            chars.next();
+            spec.prec = Some(read_num(&mut chars)?.unwrap_or(0));
+        }
+        while let Some(&(_,'l')) | Some(&(_,'h')) = chars.peek() { chars.next(); }
+        let (j, conv) = match chars.next() {
+            Some(x) => x,
+            None => return Err(Error::WrongArgs("print: incomplete format conversion at the end of the string".to_string())),
+        };
+        let end = i+1+j+conv.len_utf8();
+        match conv {
+            '%' => out.push((&rest[..=i], None)),
+            'd' | 'i' | 'f' | 'F' | 'e' | 'E' | 'g' | 'G' | 'x' | 'X' | 'o' | 's' => {
+                spec.conv = conv;
+                out.push((lit, Some(spec)));
+            }
+            _ => return Err(Error::WrongArgs(format!("print: unknown format conversion '{}'", &rest[i..end]))),
+        }
+        rest = &rest[end..];
+    }
+    out.push((rest, None));
+    Ok(out)
+}
+
+fn read_num(chars:&mut std::iter::Peekable<std::str::CharIndices>) -> Result<Option<usize>,Error> {
+    let mut out = None;
+    while let Some(&(_,c)) = chars.peek() {
+        let d = match c.to_digit(10) {
+            Some(d) => d as usize,
+            None => break,
+        };
+        let n = out.unwrap_or(0)*10 + d;
+        if n>MAX_WIDTH { return Err(Error::WrongArgs(format!("print: format width or precision is larger than {}", MAX_WIDTH))); }
+        out = Some(n);
+        chars.next();
+    }
+    Ok(out)
+}
+
+fn format_num(spec:&Spec, f:f64) -> Result<String,Error> {
+    if f.is_nan() { return Ok(pad(spec, "", "NaN", false)); }
+
+    let is_int = matches!(spec.conv, 'd' | 'i' | 'x' | 'X' | 'o');
+    let f = if is_int { f.trunc() } else { f };
+    let neg = if is_int { f<0.0 } else { f.is_sign_negative() };
+    let sign = if neg { "-" } else if spec.plus { "+" } else if spec.space { " " } else { "" };
+    let a = f.abs();
+    if a.is_infinite() { return Ok(pad(spec, sign, "inf", false)); }
+
+    let body = match spec.conv {
+        'd' | 'i' => format!("{:.0}", a),
+        'x' | 'X' | 'o' => {
+            if a>=18446744073709551616.0 { return Err(Error::WrongArgs(format!("print: {} is too large for %{}", f, spec.conv))); }
+            let n = a as u64;
+            match spec.conv {
+                'x' => format!("{:x}", n),
+                'X' => format!("{:X}", n),
+                _ => format!("{:o}", n),
+            }
+        }
+        'f' | 'F' => format!("{:.*}", spec.prec.unwrap_or(6), a),
+        'e' | 'E' => exponential(a, spec.prec.unwrap_or(6), spec.conv=='E'),
+        _ => general(a, spec.prec.unwrap_or(6), spec.conv=='G'),
+    };
+    if is_int {
+        // For integers, the precision is the minimum number of digits, and it disables the '0' flag:
+        if let Some(p) = spec.prec {
+            let zeros = "0".repeat(p.saturating_sub(body.len()));
+            return Ok(pad(spec, sign, &(zeros+&body), false));
+        }
+    }
+    Ok(pad(spec, sign, &body, true))
+}
+
+// Like C's '%e':  "1.500000e+02".  Rust's own format is "1.5e2".
+fn exponential(a:f64, prec:usize, upper:bool) -> String {
+    let s = format!("{:.*e}", prec, a);
+    let (mantissa, exp) = s.split_at(s.find('e').unwrap_or(s.len()));
+    let exp = exp.get(1..).and_then(|e| e.parse::<i32>().ok()).unwrap_or(0);
+    format!("{}{}{}{:02}", mantissa, if upper { 'E' } else { 'e' }, if exp<0 { '-' } else { '+' }, exp.abs())
+}
+
+// Like C's '%g':  Uses '%e' for very large or small exponents, otherwise '%f',
+// with 'prec' significant digits and trailing zeros removed.
+fn general(a:f64, prec:usize, upper:bool) -> String {
+    let p = prec.max(1);
+    let s = format!("{:.*e}", p-1, a);
+    let exp = s.find('e').and_then(|i| s[i+1..].parse::<i64>().ok()).unwrap_or(0);
+    if exp < -4 || exp >= p as i64 {
+        let s = exponential(a, p-1, upper);
+        let i = s.find(if upper { 'E' } else { 'e' }).unwrap_or(s.len());
+        format!("{}{}", trim_zeros(&s[..i]), &s[i..])
+    } else {
+        trim_zeros(&format!("{:.*}", (p as i64-1-exp) as usize, a)).to_string()
+    }
+}
+
+fn trim_zeros(s:&str) -> &str {
+    if s.contains('.') { s.trim_end_matches('0').trim_end_matches('.') }
+    else { s }
+}
+
+fn pad(spec:&Spec, sign:&str, body:&str, zero_ok:bool) -> String {
+    let len = sign.chars().count() + body.chars().count();
+    let fill = spec.width.saturating_sub(len);
+    if spec.minus { format!("{}{}{}", sign, body, " ".repeat(fill)) }
+    else if spec.zero && zero_ok { format!("{}{}{}", sign, "0".repeat(fill), body) }
+    else { format!("{}{}{}", " ".repeat(fill), sign, body) }
+}
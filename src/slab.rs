@@ -53,7 +53,7 @@
 
 use crate::error::Error;
 use crate::parser::{ExpressionI, ValueI,
-                    Expression,  Value};
+                    Expression,  Value, UserFunc, UnaryOp, BinaryOp, StdFunc, EXPR_STRINGS, EXPR_ARRAYS};
 use crate::compiler::{Instruction::{self, IConst}, InstructionI};
 
 use std::fmt;
@@ -216,6 +216,12 @@ pub struct ParseSlab {
     pub(crate) def_expr   :Expression,
     pub(crate) def_val    :Value,
     pub(crate) char_buf   :String,
+    pub(crate) locals     :Vec<String>,      // The names of `name = value;` bindings and function parameters, indexed by slot.
+    pub(crate) scope      :Vec<usize>,       // The slots that are visible at the current parse position.
+    pub(crate) funcs      :Vec<UserFunc>,    // Functions defined with `name(params) = definition;`.
+    pub(crate) func_base  :usize,            // Functions below this belong to previously-parsed expressions.
+    pub(crate) call_depth_limit:usize,
+    pub(crate) iter_limit :usize,            // The maximum number of sum()/prod() iterations.
     #[cfg(feature="unsafe-vars")]
     pub(crate) unsafe_vars:BTreeMap<String, *const f64>,
 }
@@ -224,6 +230,9 @@ pub struct ParseSlab {
 pub struct CompileSlab {
     pub(crate) instrs   :Vec<Instruction>,
     pub(crate) def_instr:Instruction,
+    pub(crate) funcs    :Vec<Option<InstructionI>>,  // The compiled definitions of ParseSlab.funcs.
+    pub(crate) local_consts:Vec<Option<f64>>,  // Locals with known values while folding sum() and prod().
+    pub(crate) fold_budget:Option<usize>,      // Iterations left for the outermost series fold, or None when not folding.
 }
 
 impl ParseSlab {
@@ -254,20 +263,58 @@ impl ParseSlab {
         }
     }
 
-    /// Appends an `Expression` to `ParseSlab.exprs`.
+    /// Appends an `Expression` to `ParseSlab.exprs`, and sets its strings
+    /// and arrays flags if any of its operands might be a string or an array.
     ///
     /// # Errors
     ///
     /// If `ParseSlab.exprs` is already full, a `SlabOverflow` error is returned.
     ///
     #[inline]
-    pub(crate) fn push_expr(&mut self, expr:Expression) -> Result<ExpressionI,Error> {
+    pub(crate) fn push_expr(&mut self, mut expr:Expression) -> Result<ExpressionI,Error> {
         let i = self.exprs.len();
         if i>=self.exprs.capacity() { return Err(Error::SlabOverflow); }
+        if self.may_be_str(&expr.first) || expr.pairs.iter().any(|pair| self.may_be_str(&pair.1)) { expr.flags |= EXPR_STRINGS; }
+        if self.may_be_arr(&expr.first) || expr.pairs.iter().any(|pair| self.may_be_arr(&pair.1)) { expr.flags |= EXPR_ARRAYS; }
+        expr.index = Some(ExpressionI(i));
         self.exprs.push(expr);
         Ok(ExpressionI(i))
     }
 
+    // Sub-expressions are always pushed before the Values that refer to them,
+    // so their flags are already set.
+    fn may_be_str(&self, val:&Value) -> bool {
+        match val {
+            Value::EString(_) => true,
+            Value::EStdFunc(StdFunc::EFuncStr{func, ..}) => func.returns_str(),
+            Value::EUnaryOp(UnaryOp::EParentheses(xi)) => self.result_may_be_str(*xi),
+            // These don't produce strings, but their operand needs the slow path to report a TypeMismatch:
+            Value::EUnaryOp(UnaryOp::EPos(vi)) | Value::EUnaryOp(UnaryOp::ENeg(vi)) | Value::EUnaryOp(UnaryOp::ENot(vi)) => self.may_be_str(self.get_val(*vi)),
+            Value::ETernary{then, otherwise, ..} => self.result_may_be_str(*then) || self.result_may_be_str(*otherwise),
+            Value::ELet{body, ..} | Value::ELetFunc{body, ..} => self.result_may_be_str(*body),
+            _ => false,
+        }
+    }
+
+    // Only '+' can produce a string.  Comparisons of strings produce numbers.
+    fn result_may_be_str(&self, xi:ExpressionI) -> bool {
+        let expr = self.get_expr(xi);
+        expr.strings() && expr.pairs.iter().all(|pair| pair.0==BinaryOp::EAdd)
+    }
+
+    // Any operation on an array produces an array.  Namespace variables can
+    // also be arrays, but that isn't known until eval.
+    fn may_be_arr(&self, val:&Value) -> bool {
+        match val {
+            Value::EArray(_) => true,
+            Value::EUnaryOp(UnaryOp::EParentheses(xi)) => self.get_expr(*xi).arrays(),
+            Value::EUnaryOp(UnaryOp::EPos(vi)) | Value::EUnaryOp(UnaryOp::ENeg(vi)) | Value::EUnaryOp(UnaryOp::ENot(vi)) => self.may_be_arr(self.get_val(*vi)),
+            Value::ETernary{then, otherwise, ..} => self.get_expr(*then).arrays() || self.get_expr(*otherwise).arrays(),
+            Value::ELet{body, ..} | Value::ELetFunc{body, ..} => self.get_expr(*body).arrays(),
+            _ => false,
+        }
+    }
+
     /// Appends a `Value` to `ParseSlab.vals`.
     ///
     /// # Errors
@@ -282,11 +329,58 @@ impl ParseSlab {
         Ok(ValueI(i))
     }
 
-    /// Clears all data from `ParseSlab.exprs` and `ParseSlab.vals`.
+    /// Allocates a slot for a local variable binding.
+    ///
+    /// # Errors
+    ///
+    /// If `ParseSlab.locals` is already full, a `SlabOverflow` error is returned.
+    ///
+    pub(crate) fn push_local(&mut self, name:String) -> Result<usize,Error> {
+        let i = self.locals.len();
+        if i>=self.locals.capacity() { return Err(Error::SlabOverflow); }
+        self.locals.push(name);
+        self.scope.push(i);
+        Ok(i)
+    }
+
+    /// Finds the most recent in-scope binding of `name`.
+    pub(crate) fn find_local(&self, name:&str) -> Option<usize> {
+        self.scope.iter().rev().find(|&&slot| self.locals.get(slot).map(String::as_str)==Some(name)).copied()
+    }
+
+    /// Registers a function and its parameter slots.  The definition is added later with `define_func()`.
+    ///
+    /// # Errors
+    ///
+    /// If `ParseSlab.funcs` is already full, a `SlabOverflow` error is returned.
+    ///
+    pub(crate) fn push_func(&mut self, name:String, params:Vec<usize>) -> Result<usize,Error> {
+        let i = self.funcs.len();
+        if i>=self.funcs.capacity() { return Err(Error::SlabOverflow); }
+        self.funcs.push(UserFunc{name, params, body:ExpressionI(usize::MAX)});
+        Ok(i)
+    }
+
+    /// Sets the definition of a function that was registered with `push_func()`.
+    pub(crate) fn define_func(&mut self, func:usize, body:ExpressionI) {
+        if let Some(uf) = self.funcs.get_mut(func) { uf.body = body; }
+    }
+
+    /// Finds the most recent in-scope function named `name`.
+    pub(crate) fn find_func(&self, name:&str) -> Option<usize> {
+        let base = self.func_base;
+        self.funcs.get(base..)?.iter().rposition(|uf| uf.name==name).map(|i| base+i)
+    }
+
+    /// Clears all data from `ParseSlab.exprs`, `ParseSlab.vals`, the local variable names, and the functions.
     #[inline]
     pub fn clear(&mut self) {
         self.exprs.clear();
         self.vals.clear();
+        self.locals.clear();
+        self.scope.clear();
+        self.funcs.clear();
+        self.func_base = 0;
     }
 
     /// [See the `add_unsafe_var()` documentation above.](#unsafe-variable-registration-with-add_unsafe_var)
@@ -334,10 +428,38 @@ impl CompileSlab {
         }
     }
 
-    /// Clears all data from `CompileSlab.instrs`.
+    pub(crate) fn get_local_const(&self, slot:usize) -> Option<f64> {
+        match self.local_consts.get(slot) {
+            Some(c) => *c,
+            None => None,
+        }
+    }
+    pub(crate) fn set_local_const(&mut self, slot:usize, c:Option<f64>) {
+        if self.local_consts.len()<=slot { self.local_consts.resize(slot+1, None); }
+        if let Some(lc) = self.local_consts.get_mut(slot) { *lc = c; }
+    }
+
+    /// Drops the `Instruction`s from index `len` onwards, and forgets any compiled
+    /// functions that were among them, so they will be compiled again when needed.
+    pub(crate) fn truncate_instrs(&mut self, len:usize) {
+        self.instrs.truncate(len);
+        for f in self.funcs.iter_mut() {
+            if matches!(f, Some(i) if i.0>=len) { *f = None; }
+        }
+    }
+
+    /// Replaces an `Instruction` that was pushed as a placeholder.
+    pub(crate) fn set_instr(&mut self, i:InstructionI, instr:Instruction) {
+        if let Some(instr_ref) = self.instrs.get_mut(i.0) { *instr_ref = instr; }
+    }
+
+    /// Clears all data from `CompileSlab.instrs` and the compiled functions.
     #[inline]
     pub fn clear(&mut self) {
         self.instrs.clear();
+        self.funcs.clear();
+        self.local_consts.clear();
+        self.fold_budget = None;
     }
 }
 
@@ -356,12 +478,21 @@ impl Slab {
                 def_expr   :Default::default(),
                 def_val    :Default::default(),
                 char_buf   :String::with_capacity(64),
+                locals     :Vec::with_capacity(cap),
+                scope      :Vec::with_capacity(cap),
+                funcs      :Vec::with_capacity(cap),
+                func_base  :0,
+                call_depth_limit:crate::parser::DEFAULT_CALL_DEPTH_LIMIT,
+                iter_limit :crate::parser::DEFAULT_ITER_LIMIT,
                 #[cfg(feature="unsafe-vars")]
                 unsafe_vars:BTreeMap::new(),
             },
             cs:CompileSlab{
                 instrs   :Vec::new(),  // Don't pre-allocate for compilation.
                 def_instr:Default::default(),
+                funcs    :Vec::new(),
+                local_consts:Vec::new(),
+                fold_budget:None,
             },
         }
     }
@@ -369,9 +500,8 @@ impl Slab {
     /// Clears all data from [`Slab.ps`](struct.ParseSlab.html) and [`Slab.cs`](struct.CompileSlab.html).
     #[inline]
     pub fn clear(&mut self) {
-        self.ps.exprs.clear();
-        self.ps.vals.clear();
-        self.cs.instrs.clear();
+        self.ps.clear();
+        self.cs.clear();
     }
 }
 
@@ -406,6 +536,14 @@ impl fmt::Debug for ParseSlab {
         write_indexed_list(f, &self.exprs)?;
         write!(f, ", vals:")?;
         write_indexed_list(f, &self.vals)?;
+        if !self.locals.is_empty() {
+            write!(f, ", locals:")?;
+            write_indexed_list(f, &self.locals)?;
+        }
+        if !self.funcs.is_empty() {
+            write!(f, ", funcs:")?;
+            write_indexed_list(f, &self.funcs)?;
+        }
         write!(f, " }}")?;
         Ok(())
     }
@@ -3,7 +3,7 @@ use fasteval::parser::{PrintFunc, ExpressionOrString::{EExpr, EStr}};
 #[cfg(feature="eval-builtin")]
 use fasteval::parser::{EvalFunc, KWArg};
 use fasteval::compiler::IC;
-use fasteval::compiler::Instruction::{self, IConst, INeg, INot, IInv, IAdd, IMul, IMod, IExp, ILT, ILTE, IEQ, INE, IGTE, IGT, IAND, IOR, IVar, IFunc, IFuncInt, IFuncCeil, IFuncFloor, IFuncAbs, IFuncSign, IFuncLog, IFuncRound, IFuncMin, IFuncMax, IFuncSin, IFuncCos, IFuncTan, IFuncASin, IFuncACos, IFuncATan, IFuncSinH, IFuncCosH, IFuncTanH, IFuncASinH, IFuncACosH, IFuncATanH, IPrintFunc};
+use fasteval::compiler::Instruction::{self, IConst, INeg, INot, IInv, IAdd, IMul, IMod, IExp, ILT, ILTE, IEQ, INE, IGTE, IGT, IAND, IOR, ITernary, IVar, IFunc, IFuncInt, IFuncCeil, IFuncFloor, IFuncAbs, IFuncSign, IFuncLog, IFuncRound, IFuncMin, IFuncMax, IFuncIf, IFuncSin, IFuncCos, IFuncTan, IFuncASin, IFuncACos, IFuncATan, IFuncSinH, IFuncCosH, IFuncTanH, IFuncASinH, IFuncACosH, IFuncATanH, IPrintFunc};
 #[cfg(feature="eval-builtin")]
 use fasteval::compiler::Instruction::IEvalFunc;
 
@@ -22,7 +22,7 @@ fn slab_overflow() {
     assert_eq!(format!("{:?}", slab),
 "Slab{ exprs:{ 0:Expression { first: EUnaryOp(EPos(ValueI(1))), pairs: [] }, 1:Expression { first: EConstant(1.0), pairs: [ExprPair(EAdd, EConstant(2.0)), ExprPair(EAdd, EConstant(-3.0)), ExprPair(EAdd, EUnaryOp(EParentheses(ExpressionI(0))))] } }, vals:{ 0:EConstant(4.0), 1:EUnaryOp(EPos(ValueI(0))) }, instrs:{} }");
 
-    assert_eq!(Parser::new().parse("1 + 2 + -3 + ( ++++4 )", &mut slab.ps), Err(Error::SlabOverflow));
+    assert_eq!(Parser::new().parse("1 + 2 + -3 + ( ++++4 )", &mut slab.ps), Err(Error::Spanned{err:Box::new(Error::SlabOverflow), start:20, end:20}));
 }
 
 #[test]
@@ -225,6 +225,11 @@ fn all_instrs() {
 
     // INot:
     comp_chk("!x", INot(InstructionI(0)), "CompileSlab{ instrs:{ 0:IVar(\"x\") } }", 0.0);
+    comp_chk("not x", INot(InstructionI(0)), "CompileSlab{ instrs:{ 0:IVar(\"x\") } }", 0.0);
+    comp_chk("not not x", IVar("x".to_string()), "CompileSlab{ instrs:{} }", 1.0);
+    comp_chk("not true", IConst(0.0), "CompileSlab{ instrs:{} }", 0.0);
+    comp_chk("not(false) + true", IConst(2.0), "CompileSlab{ instrs:{} }", 2.0);
+    comp_chk("false or x", IVar("x".to_string()), "CompileSlab{ instrs:{} }", 1.0);
 
     // IInv:
     comp_chk("1/x", IInv(InstructionI(0)), "CompileSlab{ instrs:{ 0:IVar(\"x\") } }", 1.0);
@@ -442,7 +447,8 @@ fn all_instrs() {
     comp_chk("min(2.7)", IConst(2.7), "CompileSlab{ instrs:{} }", 2.7);
     comp_chk("min(2.7, 3.7)", IConst(2.7), "CompileSlab{ instrs:{} }", 2.7);
     comp_chk("min(4.7, 3.7, 2.7)", IConst(2.7), "CompileSlab{ instrs:{} }", 2.7);
-    comp_chk("min(y7)", IVar("y7".to_string()), "CompileSlab{ instrs:{} }", 2.7);
+    // A single argument might be an array, so the call is kept:
+    comp_chk_str("min(y7)", "IFuncMin(InstructionI(0), C(inf))", "CompileSlab{ instrs:{ 0:IVar(\"y7\") } }", 2.7);
     comp_chk("min(4.7, y7, 3.7)", IFuncMin(InstructionI(0), IC::C(3.7)), "CompileSlab{ instrs:{ 0:IVar(\"y7\") } }", 2.7);
     comp_chk("min(3.7, y7, 4.7)", IFuncMin(InstructionI(0), IC::C(3.7)), "CompileSlab{ instrs:{ 0:IVar(\"y7\") } }", 2.7);
     comp_chk_str("min(NaN, y7, 4.7)", "IFuncMin(InstructionI(0), C(NaN))", "CompileSlab{ instrs:{ 0:IVar(\"y7\") } }", std::f64::NAN);
@@ -456,7 +462,7 @@ fn all_instrs() {
     comp_chk("max(2.7)", IConst(2.7), "CompileSlab{ instrs:{} }", 2.7);
     comp_chk("max(2.7, 1.7)", IConst(2.7), "CompileSlab{ instrs:{} }", 2.7);
     comp_chk("max(0.7, 1.7, 2.7)", IConst(2.7), "CompileSlab{ instrs:{} }", 2.7);
-    comp_chk("max(y7)", IVar("y7".to_string()), "CompileSlab{ instrs:{} }", 2.7);
+    comp_chk_str("max(y7)", "IFuncMax(InstructionI(0), C(-inf))", "CompileSlab{ instrs:{ 0:IVar(\"y7\") } }", 2.7);
     comp_chk("max(0.7, y7, 1.7)", IFuncMax(InstructionI(0), IC::C(1.7)), "CompileSlab{ instrs:{ 0:IVar(\"y7\") } }", 2.7);
     comp_chk("max(1.7, y7, 0.7)", IFuncMax(InstructionI(0), IC::C(1.7)), "CompileSlab{ instrs:{ 0:IVar(\"y7\") } }", 2.7);
     comp_chk_str("max(NaN, y7, 0.7)", "IFuncMax(InstructionI(0), C(NaN))", "CompileSlab{ instrs:{ 0:IVar(\"y7\") } }", std::f64::NAN);
@@ -466,6 +472,12 @@ fn all_instrs() {
     comp_chk_str("max(-inf, y7, 4.7)", "IFuncMax(InstructionI(0), C(4.7))", "CompileSlab{ instrs:{ 0:IVar(\"y7\") } }", 4.7);
     comp_chk_str("max(-inf, 4.7)", "IConst(4.7)", "CompileSlab{ instrs:{} }", 4.7);
 
+    // IFuncIf
+    comp_chk("if(x, y, 3)", IFuncIf { cond:InstructionI(0), then:IC::I(InstructionI(1)), otherwise:IC::C(3.0) }, "CompileSlab{ instrs:{ 0:IVar(\"x\"), 1:IVar(\"y\") } }", 2.0);
+    comp_chk("if(w, y, 3)", IFuncIf { cond:InstructionI(0), then:IC::I(InstructionI(1)), otherwise:IC::C(3.0) }, "CompileSlab{ instrs:{ 0:IVar(\"w\"), 1:IVar(\"y\") } }", 3.0);
+    comp_chk("if(1, y, z)", IVar("y".to_string()), "CompileSlab{ instrs:{} }", 2.0);
+    comp_chk("if(2 < 1, y, z + 1)", IAdd(InstructionI(0), IC::C(1.0)), "CompileSlab{ instrs:{ 0:IVar(\"z\") } }", 4.0);
+
     // IFuncSin
     comp_chk("sin(0)", IConst(0.0), "CompileSlab{ instrs:{} }", 0.0);
     comp_chk("round(0.000001, sin(pi()))", IConst(0.0), "CompileSlab{ instrs:{} }", 0.0);
@@ -520,10 +532,45 @@ fn all_instrs() {
     comp_chk("atanh(0)", IConst(0.0), "CompileSlab{ instrs:{} }", 0.0);
     comp_chk("atanh(w)", IFuncATanH(InstructionI(0)), "CompileSlab{ instrs:{ 0:IVar(\"w\") } }", 0.0);
 
+    // ITernary:
+    comp_chk("x ? y : 3", ITernary { cond:InstructionI(0), then:IC::I(InstructionI(1)), otherwise:IC::C(3.0) }, "CompileSlab{ instrs:{ 0:IVar(\"x\"), 1:IVar(\"y\") } }", 2.0);
+    comp_chk("w ? y : 3", ITernary { cond:InstructionI(0), then:IC::I(InstructionI(1)), otherwise:IC::C(3.0) }, "CompileSlab{ instrs:{ 0:IVar(\"w\"), 1:IVar(\"y\") } }", 3.0);
+    comp_chk("1 ? y : z", IVar("y".to_string()), "CompileSlab{ instrs:{} }", 2.0);
+    comp_chk("2 < 1 ? y : z", IVar("z".to_string()), "CompileSlab{ instrs:{} }", 3.0);
+    comp_chk("x > 0 ? 1 : x ? 2 : 3", ITernary { cond:InstructionI(2), then:IC::C(1.0), otherwise:IC::I(InstructionI(3)) }, "CompileSlab{ instrs:{ 0:IVar(\"x\"), 1:IVar(\"x\"), 2:IGT(I(InstructionI(0)), C(0.0)), 3:ITernary { cond: InstructionI(1), then: C(2.0), otherwise: C(3.0) } } }", 1.0);
+
     // IPrintFunc
     comp_chk(r#"print("test",1.23)"#, IPrintFunc(PrintFunc(vec![EStr("test".to_string()), EExpr(ExpressionI(0))])), "CompileSlab{ instrs:{} }", 1.23);
 }
 
+#[test]
+#[cfg(feature="bitwise")]
+fn bitwise() {
+    use fasteval::compiler::Instruction::{IBitNot, IBitAnd, IBitOr, IShr, IIntDiv};
+
+    comp_chk("1 << 2 + 1", IConst(8.0), "CompileSlab{ instrs:{} }", 8.0);
+    comp_chk("-7 // 2", IConst(-4.0), "CompileSlab{ instrs:{} }", -4.0);
+    comp_chk("~5", IConst(-6.0), "CompileSlab{ instrs:{} }", -6.0);
+    comp_chk("x && 3 & 5", IAND(InstructionI(0), IC::C(1.0)), "CompileSlab{ instrs:{ 0:IVar(\"x\") } }", 1.0);
+
+    comp_chk("~x", IBitNot(InstructionI(0)), "CompileSlab{ instrs:{ 0:IVar(\"x\") } }", -2.0);
+    comp_chk("z & 6", IBitAnd(IC::I(InstructionI(0)), IC::C(6.0)), "CompileSlab{ instrs:{ 0:IVar(\"z\") } }", 2.0);
+    comp_chk("z | 4 | y", IBitOr(IC::I(InstructionI(1)), IC::I(InstructionI(2))), "CompileSlab{ instrs:{ 0:IVar(\"z\"), 1:IBitOr(I(InstructionI(0)), C(4.0)), 2:IVar(\"y\") } }", 7.0);
+    comp_chk("y << z >> x", IShr(IC::I(InstructionI(2)), IC::I(InstructionI(3))), "CompileSlab{ instrs:{ 0:IVar(\"y\"), 1:IVar(\"z\"), 2:IShl(I(InstructionI(0)), I(InstructionI(1))), 3:IVar(\"x\") } }", 8.0);
+    comp_chk("z // 2", IIntDiv(IC::I(InstructionI(0)), IC::C(2.0)), "CompileSlab{ instrs:{ 0:IVar(\"z\") } }", 1.0);
+
+    comp_chk_str("1 // 0", "IConst(NaN)", "CompileSlab{ instrs:{} }", std::f64::NAN);
+    comp_chk_str("1 << 64", "IConst(NaN)", "CompileSlab{ instrs:{} }", std::f64::NAN);
+    comp_chk_str("x >> -1", "IShr(I(InstructionI(0)), C(-1.0))", "CompileSlab{ instrs:{ 0:IVar(\"x\") } }", std::f64::NAN);
+
+    #[cfg(feature="alpha-keywords")]
+    {
+        use fasteval::compiler::Instruction::IBitXor;
+        comp_chk("0x0F & 0b0110 | 0x100 xor 1", IConst(263.0), "CompileSlab{ instrs:{} }", 263.0);
+        comp_chk("y7 xor 1", IBitXor(IC::I(InstructionI(0)), IC::C(1.0)), "CompileSlab{ instrs:{ 0:IVar(\"y7\") } }", 3.0);
+    }
+}
+
 #[test]
 fn custom_func() {
     comp_chk("x + 1", IAdd(InstructionI(0), IC::C(1.0)), "CompileSlab{ instrs:{ 0:IVar(\"x\") } }", 2.0);
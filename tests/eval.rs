@@ -1,4 +1,4 @@
-use fasteval::{Evaler, Error, Slab, Cached, EmptyNamespace, CachedCallbackNamespace, Parser};
+use fasteval::{Evaler, Val, Compiler, InstructionI, Error, Slab, Cached, EmptyNamespace, CachedCallbackNamespace, StringToValNamespace, Parser, BuiltinRule, NativeFunc};
 use fasteval::bool_to_f64;
 
 use std::mem;
@@ -237,6 +237,1102 @@ fn corners() {
         "Ok(NaN)");
 }
 
+#[test]
+fn ternary() {
+    let mut slab = Slab::new();
+    let mut ns = BTreeMap::<String,f64>::new();
+    ns.insert("x".to_string(), 1.0);
+    ns.insert("w".to_string(), 0.0);
+
+    assert_eq!(Parser::new().parse("x ? 2 : 3", &mut slab.ps).unwrap().from(&slab.ps).eval(&slab, &mut ns), Ok(2.0));
+    assert_eq!(Parser::new().parse("w ? 2 : 3", &mut slab.ps).unwrap().from(&slab.ps).eval(&slab, &mut ns), Ok(3.0));
+
+    // Unlike `(w && 0) || 5`, a zero in the taken branch is returned:
+    assert_eq!(Parser::new().parse("x ? 0 : 5", &mut slab.ps).unwrap().from(&slab.ps).eval(&slab, &mut ns), Ok(0.0));
+
+    // Lowest precedence, right-associative:
+    assert_eq!(Parser::new().parse("x - 1 || w ? 1 + 1 : 2 + 2", &mut slab.ps).unwrap().from(&slab.ps).eval(&slab, &mut ns), Ok(4.0));
+    assert_eq!(Parser::new().parse("w ? 1 : x ? 2 : 3", &mut slab.ps).unwrap().from(&slab.ps).eval(&slab, &mut ns), Ok(2.0));
+    assert_eq!(Parser::new().parse("x ? w ? 1 : 2 : 3", &mut slab.ps).unwrap().from(&slab.ps).eval(&slab, &mut ns), Ok(2.0));
+    assert_eq!(Parser::new().parse("1 + (w ? 1 : 2) * 3", &mut slab.ps).unwrap().from(&slab.ps).eval(&slab, &mut ns), Ok(7.0));
+
+    // Only the taken branch is evaluated:
+    assert_eq!(Parser::new().parse("x ? 2 : undefined", &mut slab.ps).unwrap().from(&slab.ps).eval(&slab, &mut ns), Ok(2.0));
+    assert_eq!(Parser::new().parse("w ? undefined : 3", &mut slab.ps).unwrap().from(&slab.ps).eval(&slab, &mut ns), Ok(3.0));
+    assert_eq!(Parser::new().parse("x ? undefined : 3", &mut slab.ps).unwrap().from(&slab.ps).eval(&slab, &mut ns), Err(Error::Undefined("undefined".to_string())));
+
+    // ...but var_names() reports both branches:
+    let mut expect = BTreeSet::new();
+    expect.insert("a".to_string());  expect.insert("b".to_string());  expect.insert("c".to_string());
+    assert_eq!(Parser::new().parse("a ? b : c", &mut slab.ps).unwrap().from(&slab.ps).var_names(&slab), expect);
+}
+
+#[test]
+fn if_func() {
+    let mut slab = Slab::new();
+    let mut calls = Vec::<String>::new();
+    let mut ns = |name:&str, _args:Vec<f64>| -> Option<f64> {
+        calls.push(name.to_string());
+        match name {
+            "x" => Some(1.0),
+            "w" => Some(0.0),
+            _ => Some(99.0),
+        }
+    };
+
+    assert_eq!(Parser::new().parse("if(x, 2, 3)", &mut slab.ps).unwrap().from(&slab.ps).eval(&slab, &mut ns), Ok(2.0));
+    assert_eq!(Parser::new().parse("if(w, 2, 3)", &mut slab.ps).unwrap().from(&slab.ps).eval(&slab, &mut ns), Ok(3.0));
+    assert_eq!(Parser::new().parse("if(x, 0, 5)", &mut slab.ps).unwrap().from(&slab.ps).eval(&slab, &mut ns), Ok(0.0));
+
+    // Callbacks in the untaken branch are skipped:
+    assert_eq!(Parser::new().parse("if(x, taken(), skipped()) + if(w, skipped(), taken())", &mut slab.ps).unwrap().from(&slab.ps).eval(&slab, &mut ns), Ok(198.0));
+    assert_eq!(calls, vec!["x", "w", "x", "x", "taken", "w", "taken"]);
+
+    assert_eq!(Parser::new().parse("if(1, 2)", &mut slab.ps), Err(Error::Spanned{err:Box::new(Error::WrongArgs("if: expected if(cond, then, else)".to_string())), start:0, end:8}));
+}
+
+#[test]
+fn implicit_mul() {
+    let mut slab = Slab::new();
+    let mut ns = BTreeMap::<String,f64>::new();
+    ns.insert("x".to_string(), 2.0);
+    ns.insert("y".to_string(), 5.0);
+    ns.insert("e".to_string(), 10.0);
+    ns.insert("kg".to_string(), 100.0);
+    let parser = Parser{implicit_mul:true, ..Parser::new()};
+
+    let mut chk = |expr_str:&str, expect:f64| {
+        assert_eq!(parser.parse(expr_str, &mut slab.ps).unwrap().from(&slab.ps).eval(&slab, &mut ns), Ok(expect), "{}", expr_str);
+    };
+    chk("2x", 4.0);
+    chk("2 x", 4.0);
+    chk("3(x+1)", 9.0);
+    chk("(x)(y)", 10.0);
+    chk("[x](y)", 10.0);
+    chk("(x)y", 10.0);
+    chk("x y", 10.0);
+    chk("2x y + 1", 21.0);
+    chk("2x^2", 8.0);
+    chk("1/2x", 1.0);
+    chk("-2x", -4.0);
+    chk("2pi()", 2.0*std::f64::consts::PI);
+    chk("2sin(0)", 0.0);
+
+    // Numeric suffixes and exponents:
+    chk("2k", 2000.0);
+    chk("2k x", 4000.0);
+    chk("2k(x)", 4000.0);
+    chk("2kg", 200.0);
+    chk("2e3", 2000.0);
+    chk("2e-3", 0.002);
+    chk("2e", 20.0);
+    chk("2e x", 40.0);
+
+    // Errors:
+    assert_eq!(parser.parse("x 2", &mut slab.ps), Err(Error::Spanned{err:Box::new(Error::UnparsedTokensRemaining("2".to_string())), start:2, end:3}));
+    assert_eq!(Parser::new().parse("2x", &mut slab.ps), Err(Error::Spanned{err:Box::new(Error::UnparsedTokensRemaining("x".to_string())), start:1, end:2}));
+    assert_eq!(Parser::new().parse("2e", &mut slab.ps), Err(Error::Spanned{err:Box::new(Error::ParseF64("2e".to_string())), start:0, end:2}));
+
+    // A variable followed by parentheses is still a function call (and BTreeMap namespaces don't have functions):
+    assert_eq!(parser.parse("x(y)", &mut slab.ps).unwrap().from(&slab.ps).eval(&slab, &mut ns), Err(Error::Undefined("x".to_string())));
+}
+
+#[test]
+fn neg_below_exp() {
+    let mut slab = Slab::new();
+    let mut ns = BTreeMap::<String,f64>::new();
+    ns.insert("x".to_string(), 3.0);
+    let parser = Parser{neg_below_exp:true, ..Parser::new()};
+
+    let mut chk = |expr_str:&str, default:f64, expect:f64| {
+        assert_eq!(Parser::new().parse(expr_str, &mut slab.ps).unwrap().from(&slab.ps).eval(&slab, &mut ns), Ok(default), "{}", expr_str);
+
+        let expr_i = parser.parse(expr_str, &mut slab.ps).unwrap();
+        assert_eq!(expr_i.from(&slab.ps).eval(&slab, &mut ns), Ok(expect), "{}", expr_str);
+        let instr = expr_i.from(&slab.ps).compile(&slab.ps, &mut slab.cs);
+        assert_eq!(instr.eval(&slab, &mut ns), Ok(expect), "compiled {}", expr_str);
+    };
+    chk("-2^2", 4.0, -4.0);
+    chk("-x^2", 9.0, -9.0);
+    chk("- 2 ^ 2", 4.0, -4.0);
+    chk("-2^3^2", -512.0, -512.0);
+    chk("-2^4^0.5", 4.0, -4.0);
+    chk("2^-1^2", 2.0, 0.5);
+    chk("1 - -2^2", -3.0, 5.0);
+    chk("-2^2 * 3", 12.0, -12.0);
+    chk("-2 * 3", -6.0, -6.0);
+    chk("(-2)^2", 4.0, 4.0);
+    chk("-(2)^2", 4.0, -4.0);
+    chk("--2^2", 4.0, 4.0);
+    chk("-0x10^2", 256.0, -256.0);
+}
+
+#[test]
+fn chain_cmp() {
+    let mut slab = Slab::new();
+    let mut ns = BTreeMap::<String,f64>::new();
+    ns.insert("x".to_string(), 5.0);
+    ns.insert("lo".to_string(), 1.0);
+    ns.insert("hi".to_string(), 10.0);
+    let parser = Parser{chain_cmp:true, ..Parser::new()};
+
+    let mut chk = |expr_str:&str, default:f64, expect:f64| {
+        assert_eq!(Parser::new().parse(expr_str, &mut slab.ps).unwrap().from(&slab.ps).eval(&slab, &mut ns), Ok(default), "{}", expr_str);
+
+        let expr_i = parser.parse(expr_str, &mut slab.ps).unwrap();
+        assert_eq!(expr_i.from(&slab.ps).eval(&slab, &mut ns), Ok(expect), "{}", expr_str);
+        let instr = expr_i.from(&slab.ps).compile(&slab.ps, &mut slab.cs);
+        assert_eq!(instr.eval(&slab, &mut ns), Ok(expect), "compiled {}", expr_str);
+    };
+    chk("lo < x < hi", 1.0, 1.0);
+    chk("hi > x > lo", 0.0, 1.0);
+    chk("lo < x < 3", 1.0, 0.0);
+    chk("lo <= 1 < x", 1.0, 1.0);
+    chk("1 < 2 < 3 < 2", 1.0, 0.0);
+    chk("5 == x == 5", 0.0, 1.0);
+    chk("x < 7 == 1", 1.0, 0.0);
+    chk("lo < x + 1 < 7", 1.0, 1.0);
+    chk("lo < x < hi && x != 4", 1.0, 1.0);
+    chk("0 < x < hi < 20 || 0", 1.0, 1.0);
+    chk("(lo < x) < 0.5", 0.0, 0.0);
+    chk("x < hi", 1.0, 1.0);
+
+    // The middle operand is only evaluated once:
+    let mut calls = Vec::<String>::new();
+    let mut cb = |name:&str, _args:Vec<f64>| -> Option<f64> {
+        calls.push(name.to_string());
+        match name {
+            "x" => Some(5.0),
+            _ => None,
+        }
+    };
+    let expr_i = parser.parse("1 < x() < 10", &mut slab.ps).unwrap();
+    assert_eq!(expr_i.from(&slab.ps).eval(&slab, &mut cb), Ok(1.0));
+    let instr = expr_i.from(&slab.ps).compile(&slab.ps, &mut slab.cs);
+    assert_eq!(instr.eval(&slab, &mut cb), Ok(1.0));
+    assert_eq!(calls, vec!["x", "x"]);
+
+    assert_eq!(parser.parse("1 < 2 <= 2", &mut slab.ps).unwrap().from(&slab.ps).compile(&slab.ps, &mut slab.cs), fasteval::IConst(1.0));
+}
+
+#[test]
+fn let_bindings() {
+    let mut slab = Slab::new();
+    let mut ns = BTreeMap::<String,f64>::new();
+    ns.insert("x".to_string(), 3.0);
+    ns.insert("y".to_string(), 4.0);
+
+    let mut chk = |expr_str:&str, expect:f64| {
+        let expr_i = Parser::new().parse(expr_str, &mut slab.ps).unwrap();
+        assert_eq!(expr_i.from(&slab.ps).eval(&slab, &mut ns), Ok(expect), "{}", expr_str);
+        let instr = expr_i.from(&slab.ps).compile(&slab.ps, &mut slab.cs);
+        assert_eq!(instr.eval(&slab, &mut ns), Ok(expect), "compiled {}", expr_str);
+    };
+    chk("r = (x^2+y^2)^0.5; atan(y/x) * r", (4.0f64/3.0).atan()*5.0);
+    chk("let a = 2; let b = a^2; a + b", 6.0);
+    chk("x = 10; x + y", 14.0);
+    chk("x = x + 1; x", 4.0);
+    chk("a = 1; a = a + 1; a * 10", 20.0);
+    chk("a = x; b = y; a < b ? b : a", 4.0);
+    chk("a=2;b=3;a*b", 6.0);
+    chk("x == 3", 1.0);
+    chk("let = 5; let + 1", 6.0);
+
+    // The number of bindings is only limited by the Slab capacity:
+    let many = (1..=20).map(|i| format!("v{} = {}; ", i, i)).collect::<String>() + "v17 + v20";
+    chk(&many, 37.0);
+
+    // Locals are not external variables:
+    let mut expect = BTreeSet::<String>::new();
+    expect.insert("x".to_string());
+    expect.insert("y".to_string());
+    assert_eq!(Parser::new().parse("r = x + 1; r * y", &mut slab.ps).unwrap().from(&slab.ps).var_names(&slab), expect);
+
+    // Compiled expressions use slots instead of names:
+    let expr_i = Parser::new().parse("a = x; a * a", &mut slab.ps).unwrap();
+    assert_eq!(format!("{:?}", slab.ps), r#"ParseSlab{ exprs:{ 0:Expression { first: EStdFunc(EVar("x")), pairs: [] }, 1:Expression { first: EStdFunc(ELocal(0)), pairs: [ExprPair(EMul, EStdFunc(ELocal(0)))] }, 2:Expression { first: ELet { slot: 0, value: ExpressionI(0), body: ExpressionI(1) }, pairs: [] } }, vals:{}, locals:{ 0:"a" } }"#);
+    slab.cs.clear();
+    let instr = expr_i.from(&slab.ps).compile(&slab.ps, &mut slab.cs);
+    assert_eq!(format!("{:?} {:?}", instr, slab.cs), r#"ILet { slot: 0, value: I(InstructionI(2)), body: I(InstructionI(3)) } CompileSlab{ instrs:{ 0:ILocal(0), 1:ILocal(0), 2:IVar("x"), 3:IMul(InstructionI(0), I(InstructionI(1))) } }"#);
+
+    // Locals are scoped to their own expression:
+    let parser = Parser::new();
+    parser.parse("a = 1; a", &mut slab.ps).unwrap();
+    let expr_i = parser.parse_noclear("a", &mut slab.ps).unwrap();
+    assert_eq!(expr_i.from(&slab.ps).eval(&slab, &mut ns), Err(Error::Undefined("a".to_string())));
+
+    assert_eq!(parser.parse("a = 1", &mut slab.ps), Err(Error::Spanned{err:Box::new(Error::EofWhileParsing("binding".to_string())), start:5, end:5}));
+    assert_eq!(parser.parse("a = 1 2", &mut slab.ps), Err(Error::Spanned{err:Box::new(Error::Expected(";".to_string())), start:6, end:7}));
+    assert_eq!(parser.parse("a = 1;", &mut slab.ps), Err(Error::Spanned{err:Box::new(Error::EofWhileParsing("value".to_string())), start:6, end:6}));
+    assert!(parser.parse("(a = 1; a)", &mut slab.ps).is_err());
+
+    // A namespace callback can evaluate another expression without disturbing the current locals,
+    // even when both expressions are in the same Slab and use the same slots:
+    let inner_i = parser.parse("a = 100; a + 1", &mut slab.ps).unwrap();
+    let expr_i = parser.parse_noclear("a = 1; inner + a", &mut slab.ps).unwrap();
+    slab.cs.clear();
+    let instr = expr_i.from(&slab.ps).compile(&slab.ps, &mut slab.cs);
+    let inner_instr = inner_i.from(&slab.ps).compile(&slab.ps, &mut slab.cs);
+    let slab = &slab;
+    let mut cb = |name:&str, _:Vec<f64>| -> Option<f64> {
+        if name=="inner" { inner_i.from(&slab.ps).eval(slab, &mut EmptyNamespace).ok() } else { None }
+    };
+    assert_eq!(expr_i.from(&slab.ps).eval(slab, &mut cb), Ok(102.0));
+    assert_eq!(instr.eval(slab, &mut cb), Ok(102.0));
+    let mut cb = |name:&str, _:Vec<f64>| -> Option<f64> {
+        if name=="inner" { inner_instr.eval(slab, &mut EmptyNamespace).ok() } else { None }
+    };
+    assert_eq!(instr.eval(slab, &mut cb), Ok(102.0));
+}
+
+#[test]
+#[cfg(not(feature="unsafe-vars"))]  // Unsafe variables are raw pointers, which can't be shared.
+fn shared_slab() {
+    fn assert_sync<T:Sync>(_:&T) {}
+
+    // Locals are stored by the evaluation, not the Slab, so one Slab can be used by many threads at once:
+    let mut slab = Slab::new();
+    let expr_i = Parser::new().parse("fact(n) = n <= 1 ? 1 : n * fact(n - 1); a = x; fact(a) + sum(i, 1, a, i)", &mut slab.ps).unwrap();
+    let instr = expr_i.from(&slab.ps).compile(&slab.ps, &mut slab.cs);
+    assert_sync(&slab);
+
+    let (slab, instr) = (&slab, &instr);
+    std::thread::scope(|s| {
+        for x in 1..=6 {
+            s.spawn(move || {
+                let mut ns = BTreeMap::<String,f64>::new();
+                ns.insert("x".to_string(), x as f64);
+                let expect = (1..=x).product::<i32>() as f64 + (x*(x+1)/2) as f64;
+                for _ in 0..200 {
+                    assert_eq!(expr_i.from(&slab.ps).eval(slab, &mut ns), Ok(expect));
+                    assert_eq!(instr.eval(slab, &mut ns), Ok(expect));
+                }
+            });
+        }
+    });
+}
+
+#[test]
+fn user_funcs() {
+    let mut slab = Slab::new();
+    let mut ns = BTreeMap::<String,f64>::new();
+    ns.insert("x".to_string(), 3.0);
+    ns.insert("y".to_string(), 4.0);
+
+    let mut chk = |parser:&Parser, expr_str:&str, expect:Result<f64,Error>| {
+        let expr_i = parser.parse(expr_str, &mut slab.ps).unwrap();
+        assert_eq!(expr_i.from(&slab.ps).eval(&slab, &mut ns), expect, "{}", expr_str);
+        let instr = expr_i.from(&slab.ps).compile(&slab.ps, &mut slab.cs);
+        assert_eq!(instr.eval(&slab, &mut ns), expect, "compiled {}", expr_str);
+    };
+    let parser = Parser::new();
+    chk(&parser, "f(x) = x^2 + 1; f(3) + f(4)", Ok(27.0));
+    chk(&parser, "hyp(a, b) = (a^2 + b^2)^0.5; hyp(3, 4)", Ok(5.0));
+    chk(&parser, "let two() = 2; two() * 3", Ok(6.0));
+    chk(&parser, "k = 10; f(t) = t * k; f(2)", Ok(20.0));
+    chk(&parser, "f(t) = t + y; f(1)", Ok(5.0));
+    chk(&parser, "f(x) = x * 2; f(5) + x", Ok(13.0));
+    chk(&parser, "sq(x) = x*x; f(x) = sq(x) + sq(x+1); f(2)", Ok(13.0));
+    chk(&parser, "f(x) = x; a = f(1); f(x) = x * 10; a + f(1)", Ok(11.0));
+    chk(&parser, "fact(n) = n <= 1 ? 1 : n * fact(n - 1); fact(5)", Ok(120.0));
+    chk(&parser, "fib(n) = n < 2 ? n : fib(n-1) + fib(n-2); fib(10)", Ok(55.0));
+    chk(&parser, "f(x) = f(x); f(1)", Err(Error::RecursionLimit("f".to_string())));
+
+    let many = (1..=20).map(|i| format!("f{}(x) = x + {}; ", i, i)).collect::<String>() + "f1(1) + f20(1)";
+    chk(&parser, &many, Ok(23.0));
+
+    let parser = Parser{call_depth_limit:5, ..Parser::new()};
+    chk(&parser, "f(n) = n > 0 ? f(n - 1) : 0; f(4)", Ok(0.0));
+    chk(&parser, "f(n) = n > 0 ? f(n - 1) : 0; f(5)", Err(Error::RecursionLimit("f".to_string())));
+
+    // The call depth belongs to each evaluation, so a namespace callback can start a new one at any depth.
+    // A failed evaluation doesn't leave anything behind, either:
+    let mut inner_slab = Slab::new();
+    let inner_i = parser.parse("g(n) = n > 0 ? g(n - 1) : 7; g(4)", &mut inner_slab.ps).unwrap();
+    let mut cb = |name:&str, _:Vec<f64>| -> Option<f64> {
+        if name=="inner" { inner_i.from(&inner_slab.ps).eval(&inner_slab, &mut EmptyNamespace).ok() } else { None }
+    };
+    let expr_i = parser.parse("f(n) = n > 0 ? f(n - 1) : inner; f(4)", &mut slab.ps).unwrap();
+    assert_eq!(expr_i.from(&slab.ps).eval(&slab, &mut cb), Ok(7.0));
+    let instr = expr_i.from(&slab.ps).compile(&slab.ps, &mut slab.cs);
+    assert_eq!(instr.eval(&slab, &mut cb), Ok(7.0));
+    let expr_i = parser.parse("f(n) = n > 0 ? f(n - 1) : inner; f(5)", &mut slab.ps).unwrap();
+    assert_eq!(expr_i.from(&slab.ps).eval(&slab, &mut cb), Err(Error::RecursionLimit("f".to_string())));
+    assert_eq!(inner_i.from(&inner_slab.ps).eval(&inner_slab, &mut EmptyNamespace), Ok(7.0));
+
+    // The definition's variables are reported, but not its parameters:
+    let mut expect = BTreeSet::<String>::new();
+    expect.insert("y".to_string());
+    expect.insert("z".to_string());
+    let expr_i = Parser::new().parse("f(x) = x + y; f(z)", &mut slab.ps).unwrap();
+    assert_eq!(expr_i.from(&slab.ps).var_names(&slab), expect);
+    let instr = expr_i.from(&slab.ps).compile(&slab.ps, &mut slab.cs);
+    assert_eq!(instr.var_names(&slab), expect);
+
+    // Arity is checked during parsing:
+    assert_eq!(Parser::new().parse("f(x) = x; f(1, 2)", &mut slab.ps), Err(Error::Spanned{err:Box::new(Error::WrongArgs("f: expected 1 arg".to_string())), start:10, end:17}));
+    assert_eq!(Parser::new().parse("f(a, b) = a; f(1)", &mut slab.ps), Err(Error::Spanned{err:Box::new(Error::WrongArgs("f: expected 2 args".to_string())), start:13, end:17}));
+
+    // Functions are scoped to their own expression:
+    Parser::new().parse("f(x) = x; f(1)", &mut slab.ps).unwrap();
+    let expr_i = Parser::new().parse_noclear("f(1)", &mut slab.ps).unwrap();
+    assert_eq!(expr_i.from(&slab.ps).eval(&slab, &mut ns), Err(Error::Undefined("f".to_string())));
+}
+
+#[test]
+fn series() {
+    let mut slab = Slab::new();
+    let mut ns = BTreeMap::<String,f64>::new();
+    ns.insert("i".to_string(), 100.0);
+    ns.insert("n".to_string(), 4.0);
+
+    let mut chk = |parser:&Parser, expr_str:&str, expect:Result<f64,Error>| {
+        let expr_i = parser.parse(expr_str, &mut slab.ps).unwrap();
+        assert_eq!(expr_i.from(&slab.ps).eval(&slab, &mut ns), expect, "{}", expr_str);
+        let instr = expr_i.from(&slab.ps).compile(&slab.ps, &mut slab.cs);
+        assert_eq!(instr.eval(&slab, &mut ns), expect, "compiled {}", expr_str);
+    };
+    let parser = Parser::new();
+    chk(&parser, "sum(i, 1, 10, i^2)", Ok(385.0));
+    chk(&parser, "prod(k, 1, 5, k)", Ok(120.0));
+    chk(&parser, "sum(k, 1, n, k)", Ok(10.0));
+    chk(&parser, "prod(k, 1, n, k)", Ok(24.0));
+    chk(&parser, "prod(k, 1, n, k + i)", Ok(101.0*102.0*103.0*104.0));
+    chk(&parser, "sum(k, 1.5, 3.9, k)", Ok(1.5+2.5+3.5));
+    chk(&parser, "sum(k, 5, 1, k)", Ok(0.0));
+    chk(&parser, "prod(k, 5, 1, k)", Ok(1.0));
+    chk(&parser, "sum(i, 1, 3, i) + i", Ok(106.0));
+    chk(&parser, "sum(a, 1, 3, sum(b, 1, a, a*b))", Ok(1.0 + 2.0+4.0 + 3.0+6.0+9.0));
+    chk(&parser, "sum[k; 1; 3; k]", Ok(6.0));
+    chk(&parser, "f(m) = sum(k, 1, m, k); f(3) + f(4)", Ok(16.0));
+    chk(&parser, "sum(k, 1, 1000000, k)", Err(Error::IterationLimit("sum".to_string())));
+
+    let parser = Parser{iter_limit:5, ..Parser::new()};
+    chk(&parser, "sum(k, 1, 5, k)", Ok(15.0));
+    chk(&parser, "prod(k, 1, n+2, k)", Err(Error::IterationLimit("prod".to_string())));
+
+    // Constant series are folded during compilation:
+    let expr_i = Parser::new().parse("sum(k, 1, 10, k^2)", &mut slab.ps).unwrap();
+    slab.cs.clear();
+    assert_eq!(format!("{:?}", expr_i.from(&slab.ps).compile(&slab.ps, &mut slab.cs)), "IConst(385.0)");
+    assert_eq!(format!("{:?}", slab.cs), "CompileSlab{ instrs:{} }");
+
+    // A failed attempt at folding doesn't leave any instructions behind:
+    let expr_i = Parser::new().parse("sum(i, 1, 3, (i+n)*(n+2))", &mut slab.ps).unwrap();
+    slab.cs.clear();
+    let instr = expr_i.from(&slab.ps).compile(&slab.ps, &mut slab.cs);
+    assert_eq!(format!("{:?} {:?}", instr, slab.cs), r#"IFuncSum { slot: 0, start: C(1.0), end: C(3.0), expr: I(InstructionI(5)) } CompileSlab{ instrs:{ 0:ILocal(0), 1:IVar("n"), 2:IVar("n"), 3:IAdd(InstructionI(0), I(InstructionI(1))), 4:IAdd(InstructionI(2), C(2.0)), 5:IMul(InstructionI(3), I(InstructionI(4))) } }"#);
+    assert_eq!(instr.eval(&slab, &mut ns), Ok(6.0*(5.0+6.0+7.0)));
+
+    // ...but large nested series are left for run time, so compiling stays fast:
+    let expr_i = Parser::new().parse("sum(i, 1, 1000, sum(j, 1, 1000, i*j))", &mut slab.ps).unwrap();
+    slab.cs.clear();
+    let instr = expr_i.from(&slab.ps).compile(&slab.ps, &mut slab.cs);
+    assert!(format!("{:?}", instr).starts_with("IFuncSum"));
+    assert_eq!(instr.eval(&slab, &mut ns), Ok(500500.0*500500.0));
+    let expr_i = Parser::new().parse("sum(i, 1, 100000, sum(j, 1, 100000, i*j))", &mut slab.ps).unwrap();
+    let instr = expr_i.from(&slab.ps).compile(&slab.ps, &mut slab.cs);
+    assert!(format!("{:?}", instr).starts_with("IFuncSum"));
+
+    // The index variable is not reported:
+    let expr_i = Parser::new().parse("sum(k, 1, n, k * x)", &mut slab.ps).unwrap();
+    let mut expect = BTreeSet::<String>::new();
+    expect.insert("n".to_string());
+    expect.insert("x".to_string());
+    assert_eq!(expr_i.from(&slab.ps).var_names(&slab), expect);
+    let instr = expr_i.from(&slab.ps).compile(&slab.ps, &mut slab.cs);
+    assert_eq!(instr.var_names(&slab), expect);
+
+    // Other calls to sum() are the variadic aggregate, which asks the namespace first, like
+    // before sum() was a builtin.  (This namespace's sum() is negated, so it can be told apart.)
+    let mut cb = |name:&str, args:Vec<f64>| -> Option<f64> {
+        match name {
+            "sum" => Some(-args.into_iter().sum::<f64>()),
+            "x" => Some(3.0),
+            "a" => Some(1.0),
+            "b" => Some(2.0),
+            "c" => Some(3.0),
+            "d" => Some(4.0),
+            _ => None,
+        }
+    };
+    let mut chk = |parser:&Parser, expr_str:&str, expect:Result<f64,Error>| {
+        let expr_i = parser.parse(expr_str, &mut slab.ps).unwrap();
+        assert_eq!(expr_i.from(&slab.ps).eval(&slab, &mut cb), expect, "{}", expr_str);
+        let instr = expr_i.from(&slab.ps).compile(&slab.ps, &mut slab.cs);
+        assert_eq!(instr.eval(&slab, &mut cb), expect, "compiled {}", expr_str);
+    };
+    let parser = Parser::new();
+    chk(&parser, "sum(x, 1)", Ok(-4.0));
+    chk(&parser, "sum(x^2, 1, 2, 3)", Ok(-15.0));
+    chk(&parser, "sum(1, 2, 3, 4)", Ok(-10.0));
+    chk(&parser, "sum((x), 1, 2, x)", Ok(-9.0));
+    chk(&parser, "sum((a), b, c, d)", Ok(-10.0));
+    chk(&parser, "a = 1; sum(a, b, c, d)", Ok(-10.0));
+
+    // ...unless it has exactly 4 args, and the first is a bare name that isn't a local:
+    chk(&parser, "sum(x, 1, 2, x)", Ok(3.0));
+    chk(&parser, "sum(a, b, c, d)", Ok(2.0*4.0));
+
+    // The builtin aggregate can be used instead:
+    let builtin_sum = Parser{builtins:vec![BuiltinRule::BuiltinFirst("sum".to_string())], ..Parser::new()};
+    chk(&builtin_sum, "sum(x, 1)", Ok(4.0));
+    chk(&builtin_sum, "sum(x, 1, 2)", Ok(6.0));
+    chk(&builtin_sum, "sum((a), b, c, d)", Ok(10.0));
+    chk(&builtin_sum, "sum(a, b, c, d)", Ok(2.0*4.0));
+
+    // Disabling the builtin only leaves the namespace's sum(), and the series form of prod():
+    let no_sum = Parser{builtins:vec![BuiltinRule::Disable("sum".to_string())], ..Parser::new()};
+    chk(&no_sum, "sum(x, 1)", Ok(-4.0));
+    chk(&no_sum, "sum(k, 1, 3, k)", Err(Error::Undefined("k".to_string())));
+    chk(&no_sum, "prod(k, 1, 3, k)", Ok(6.0));
+}
+
+#[test]
+fn unicode_and_dotted_names() {
+    let mut slab = Slab::new();
+    let mut ns = BTreeMap::<String,f64>::new();
+    ns.insert("θ".to_string(), 0.5);
+    ns.insert("Δt".to_string(), 2.0);
+    ns.insert("x₁".to_string(), 3.0);
+    ns.insert("pump".to_string(), 1.0);
+    ns.insert("inlet".to_string(), 10.0);
+    ns.insert("pump.inlet.pressure".to_string(), 4.0);
+    ns.insert("pump.rate".to_string(), 5.0);
+
+    let mut chk = |parser:&Parser, expr_str:&str, expect:Result<f64,Error>| {
+        let expr_i = parser.parse(expr_str, &mut slab.ps).unwrap();
+        assert_eq!(expr_i.from(&slab.ps).eval(&slab, &mut ns), expect, "{}", expr_str);
+        let instr = expr_i.from(&slab.ps).compile(&slab.ps, &mut slab.cs);
+        assert_eq!(instr.eval(&slab, &mut ns), expect, "compiled {}", expr_str);
+    };
+    let parser = Parser::new();
+    chk(&parser, "θ * Δt", Ok(1.0));
+    chk(&parser, "x₁ + 1", Ok(4.0));
+    chk(&parser, "α = 3; α^2", Ok(9.0));
+    chk(&Parser{implicit_mul:true, ..Parser::new()}, "2θ", Ok(1.0));
+
+    // Superscripts and subscripts are numeric, so they continue a name:
+    chk(&parser, "x₁₁", Err(Error::Undefined("x₁₁".to_string())));
+    chk(&parser, "x²", Err(Error::Undefined("x²".to_string())));
+
+    chk(&parser, "caf\u{e9} = 2; caf\u{e9}", Ok(2.0));
+
+    let parser = Parser{dotted_names:true, ..Parser::new()};
+    chk(&parser, "pump.inlet.pressure * 2", Ok(8.0));
+    chk(&parser, "pump.rate + pump", Ok(6.0));
+    chk(&parser, "pump.other", Err(Error::Undefined("pump.other".to_string())));
+
+    let mut expect = BTreeSet::<String>::new();
+    expect.insert("pump.inlet.pressure".to_string());
+    expect.insert("θ".to_string());
+    let expr_i = parser.parse("pump.inlet.pressure + θ", &mut slab.ps).unwrap();
+    assert_eq!(expr_i.from(&slab.ps).var_names(&slab), expect);
+    let instr = expr_i.from(&slab.ps).compile(&slab.ps, &mut slab.cs);
+    assert_eq!(instr.var_names(&slab), expect);
+
+    // Without the option, the dot is a syntax error:
+    assert!(Parser::new().parse("pump.inlet", &mut slab.ps).is_err());
+    assert!(parser.parse("pump. inlet", &mut slab.ps).is_err());
+
+    // A name can't start with a digit-like character, and combining marks aren't accepted,
+    // so 'é' must be written precomposed:
+    assert!(parser.parse("²", &mut slab.ps).is_err());
+    assert_eq!(parser.parse("cafe\u{301}", &mut slab.ps), Err(Error::Spanned{err:Box::new(Error::UnparsedTokensRemaining("\u{301}".to_string())), start:4, end:6}));
+}
+
+#[test]
+fn quoted_names() {
+    let mut slab = Slab::new();
+    let mut ns = BTreeMap::<String,f64>::new();
+    ns.insert("Gross Margin (%)".to_string(), 40.0);
+    ns.insert("Revenue".to_string(), 200.0);
+    ns.insert("it's `odd`".to_string(), 1.0);
+    ns.insert("and".to_string(), 2.0);
+    ns.insert("Net Sales.Q1".to_string(), 3.0);
+
+    let mut chk = |parser:&Parser, expr_str:&str, expect:Result<f64,Error>| {
+        let expr_i = parser.parse(expr_str, &mut slab.ps).unwrap();
+        assert_eq!(expr_i.from(&slab.ps).eval(&slab, &mut ns), expect, "{}", expr_str);
+        let instr = expr_i.from(&slab.ps).compile(&slab.ps, &mut slab.cs);
+        assert_eq!(instr.eval(&slab, &mut ns), expect, "compiled {}", expr_str);
+    };
+    let parser = Parser::new();
+    chk(&parser, "`Gross Margin (%)` / 100 * Revenue", Ok(80.0));
+    chk(&parser, "`Revenue` + `it's ``odd```", Ok(201.0));
+    chk(&parser, "`and` and `and`", Ok(2.0));
+    chk(&parser, "`Net Sales.Q1` * 2", Ok(6.0));
+    chk(&parser, "`Unit Price`(2)", Err(Error::Undefined("Unit Price".to_string())));
+    chk(&parser, "`my total` = Revenue + 1; `my total` * 2", Ok(402.0));
+    chk(&parser, "`let` = 5; `let`", Ok(5.0));
+    chk(&Parser{implicit_mul:true, ..Parser::new()}, "2`Revenue`", Ok(400.0));
+    chk(&Parser{dotted_names:true, ..Parser::new()}, "`Net Sales`.Q1", Ok(3.0));
+
+    assert!(Parser::new().parse("`Net Sales`.Q1", &mut slab.ps).is_err());
+    assert_eq!(Parser::new().parse("`Revenue", &mut slab.ps), Err(Error::Spanned{err:Box::new(Error::EofWhileParsing("quoted name".to_string())), start:8, end:8}));
+    assert_eq!(Parser::new().parse("1 + ``", &mut slab.ps), Err(Error::Spanned{err:Box::new(Error::Expected("a name between the backticks".to_string())), start:4, end:6}));
+
+    // Quoted names count towards the expression length limit:
+    let long = format!("`{}`", "x".repeat(100));
+    assert_eq!(Parser{expr_len_limit:100, ..Parser::new()}.parse(&long, &mut slab.ps), Err(Error::Spanned{err:Box::new(Error::TooLong), start:0, end:102}));
+
+    // Names reported by var_names() can be quoted back into an expression:
+    let expr_i = Parser::new().parse("`Gross Margin (%)` + `it's ``odd``` + `and` + Revenue + θ", &mut slab.ps).unwrap();
+    let names = expr_i.from(&slab.ps).var_names(&slab);
+    let quoted : Vec<String> = names.iter().map(|name| Parser::quote_name(name)).collect();
+    assert_eq!(quoted, vec!["`Gross Margin (%)`", "Revenue", "`and`", "`it's ``odd```", "θ"]);
+    let expr_i = Parser::new().parse(&quoted.join(" + "), &mut slab.ps).unwrap();
+    assert_eq!(expr_i.from(&slab.ps).var_names(&slab), names);
+}
+
+#[test]
+fn comments() {
+    let mut slab = Slab::new();
+    let mut ns = BTreeMap::<String,f64>::new();
+    ns.insert("price".to_string(), 2.5);
+    ns.insert("qty".to_string(), 4.0);
+
+    let mut chk = |expr_str:&str, expect:Result<f64,Error>| {
+        let expr_i = Parser::new().parse(expr_str, &mut slab.ps).unwrap();
+        assert_eq!(expr_i.from(&slab.ps).eval(&slab, &mut ns), expect, "{}", expr_str);
+        let instr = expr_i.from(&slab.ps).compile(&slab.ps, &mut slab.cs);
+        assert_eq!(instr.eval(&slab, &mut ns), expect, "compiled {}", expr_str);
+    };
+    chk("/* total */ price * qty", Ok(10.0));
+    chk("price/*unit*/*/**/qty", Ok(10.0));
+    chk("price / /* not a comment: */ qty", Ok(0.625));
+    chk("min(/* a */ 1, /* b */ 2 /* c */)", Ok(1.0));
+    chk("sub = price * qty; /* then add tax */ sub * 1.5", Ok(15.0));
+    chk("price * qty /* trailing */", Ok(10.0));
+    chk("price * qty  # Subtotal\n  + 1   # Shipping", Ok(11.0));
+    chk("# All of this is a comment: 1 + 2\r\n3", Ok(3.0));
+    chk("price * qty # trailing", Ok(10.0));
+    chk("price /* a # b */ * qty", Ok(10.0));
+    #[cfg(feature="bitwise")]
+    chk("7 // 2 # integer division", Ok(3.0));
+
+    assert_eq!(Parser::new().parse("1 + /* unterminated", &mut slab.ps), Err(Error::Spanned{err:Box::new(Error::EofWhileParsing("comment".to_string())), start:4, end:4}));
+    assert_eq!(Parser::new().parse("1 + 2 /* oops", &mut slab.ps), Err(Error::Spanned{err:Box::new(Error::EofWhileParsing("comment".to_string())), start:6, end:6}));
+    assert_eq!(Parser::new().parse("f(1, /* oops", &mut slab.ps), Err(Error::Spanned{err:Box::new(Error::EofWhileParsing("comment".to_string())), start:5, end:5}));
+    assert_eq!(Parser::new().parse("1 + # 2", &mut slab.ps), Err(Error::Spanned{err:Box::new(Error::EofWhileParsing("value".to_string())), start:7, end:7}));
+    assert_eq!(Parser::new().parse("/* nothing */", &mut slab.ps), Err(Error::Spanned{err:Box::new(Error::EofWhileParsing("value".to_string())), start:13, end:13}));
+}
+
+#[test]
+fn builtin_rules() {
+    let mut slab = Slab::new();
+    let mut cb = |name:&str, args:Vec<f64>| -> Option<f64> {
+        match (name, args.as_slice()) {
+            ("x", []) => Some(100.0),
+            ("log", [n]) => Some(n.ln()),
+            ("round", [n]) if *n<0.0 => Some(-1.0),
+            ("count", _) => Some(42.0),
+            _ => None,
+        }
+    };
+    let mut chk = |parser:&Parser, expr_str:&str, expect:Result<f64,Error>| {
+        let expr_i = parser.parse(expr_str, &mut slab.ps).unwrap();
+        assert_eq!(expr_i.from(&slab.ps).eval(&slab, &mut cb), expect, "{}", expr_str);
+        let instr = expr_i.from(&slab.ps).compile(&slab.ps, &mut slab.cs);
+        assert_eq!(instr.eval(&slab, &mut cb), expect, "compiled {}", expr_str);
+    };
+    let s = |name:&str| name.to_string();
+
+    let parser = Parser{builtins:vec![BuiltinRule::Disable(s("log"))], ..Parser::new()};
+    chk(&parser, "log(x)", Ok(100f64.ln()));
+    chk(&parser, "log(1, 2)", Err(Error::Undefined(s("log"))));
+    chk(&parser, "round(2.5)", Ok(3.0));
+
+    let parser = Parser{builtins:vec![BuiltinRule::Alias{alias:s("log10"), builtin:s("log")},
+                                      BuiltinRule::Alias{alias:s("biggest"), builtin:s("max")},
+                                      BuiltinRule::Alias{alias:s("Σ"), builtin:s("sum")}], ..Parser::new()};
+    chk(&parser, "log10(x)", Ok(2.0));
+    chk(&parser, "log(x)", Ok(2.0));
+    chk(&parser, "biggest(1, 3, 2)", Ok(3.0));
+    chk(&parser, "Σ(i, 1, 4, i)", Ok(10.0));
+
+    // Renaming is an alias plus a disable:
+    let parser = Parser{builtins:vec![BuiltinRule::Alias{alias:s("log10"), builtin:s("log")},
+                                      BuiltinRule::Disable(s("log"))], ..Parser::new()};
+    chk(&parser, "log10(x) + log(1)", Ok(2.0));
+
+    let parser = Parser{builtins:vec![BuiltinRule::NamespaceFirst(s("round")), BuiltinRule::NamespaceFirst(s("log"))], ..Parser::new()};
+    chk(&parser, "round(-2.5)", Ok(-1.0));
+    chk(&parser, "round(2.5)", Ok(3.0));
+    chk(&parser, "log(x)", Ok(100f64.ln()));
+    chk(&parser, "log(2, 8)", Ok(3.0));
+
+    // The aggregates ask the namespace first by default, unless an arg is an array:
+    chk(&Parser::new(), "count(1, 2)", Ok(42.0));
+    chk(&Parser::new(), "count([1, 2, 3])", Ok(3.0));
+    chk(&Parser::new(), "nancount(1, 2)", Ok(2.0));
+    chk(&Parser{builtins:vec![BuiltinRule::BuiltinFirst(s("count"))], ..Parser::new()}, "count(1, 2)", Ok(2.0));
+    chk(&parser, "round(round(-2.5) + 3.4)", Ok(2.0));
+    chk(&parser, "f(n) = n > 0 ? round(n - 1.5) + f(n - 1) : 0; f(3)", Ok(2.0));
+
+    // Disabling print() hides it from users:
+    let hide_print = Parser{builtins:vec![BuiltinRule::Disable(s("print"))], ..Parser::new()};
+    chk(&hide_print, "print(1)", Err(Error::Undefined(s("print"))));
+
+    // The namespace must be asked at runtime, so these aren't folded:
+    let expr_i = parser.parse("round(2.5)", &mut slab.ps).unwrap();
+    slab.cs.clear();
+    let instr = expr_i.from(&slab.ps).compile(&slab.ps, &mut slab.cs);
+    assert_eq!(format!("{:?}", instr), "INamespaceFirst { name: \"round\", args: [C(2.5)], slot: 0, builtin: InstructionI(1) }");
+    let mut expect = BTreeSet::<String>::new();
+    expect.insert(s("round"));
+    assert_eq!(instr.var_names(&slab), expect);
+
+    // The args are only evaluated once, even when the builtin answers:
+    let ticks = std::cell::Cell::new(0);
+    let mut tick_cb = |name:&str, _args:Vec<f64>| -> Option<f64> {
+        match name {
+            "tick" => { ticks.set(ticks.get()+1); Some(-4.0) }
+            _ => None,
+        }
+    };
+    let expr_i = Parser{builtins:vec![BuiltinRule::NamespaceFirst(s("abs"))], ..Parser::new()}.parse("abs(tick)", &mut slab.ps).unwrap();
+    assert_eq!(expr_i.from(&slab.ps).eval(&slab, &mut tick_cb), Ok(4.0));
+    assert_eq!(ticks.get(), 1);
+    let instr = expr_i.from(&slab.ps).compile(&slab.ps, &mut slab.cs);
+    assert_eq!(instr.eval(&slab, &mut tick_cb), Ok(4.0));
+    assert_eq!(ticks.get(), 2);
+    // Its string arguments become plain string values, which namespace functions can't take:
+    let expr_i = hide_print.parse("print(\"hi\")", &mut slab.ps).unwrap();
+    assert_eq!(expr_i.from(&slab.ps).eval(&slab, &mut cb), Err(Error::TypeMismatch(s("expected a number, not a string"))));
+}
+
+fn myscale(args:&[f64]) -> f64 { args[0] * args[1] }
+fn mycount(_:&[f64]) -> f64 {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    static CALLS : AtomicUsize = AtomicUsize::new(0);
+    (CALLS.fetch_add(1, Ordering::SeqCst) + 1) as f64
+}
+#[test]
+fn native_funcs() {
+    let mut slab = Slab::new();
+    let mut ns = BTreeMap::<String,f64>::new();
+    ns.insert("x".to_string(), 5.0);
+
+    let parser = Parser{native_funcs:vec![NativeFunc{name:"myscale", arity:2, pure:true, func:myscale},
+                                          NativeFunc{name:"count", arity:0, pure:false, func:mycount},
+                                          NativeFunc{name:"round", arity:2, pure:true, func:myscale}],
+                        ..Parser::new()};
+    let mut chk = |expr_str:&str, expect:Result<f64,Error>, expect_instr:&str| {
+        let expr_i = parser.parse(expr_str, &mut slab.ps).unwrap();
+        assert_eq!(expr_i.from(&slab.ps).eval(&slab, &mut ns), expect, "{}", expr_str);
+        slab.cs.clear();
+        let instr = expr_i.from(&slab.ps).compile(&slab.ps, &mut slab.cs);
+        assert_eq!(format!("{:?}", instr), expect_instr, "{}", expr_str);
+        assert_eq!(instr.eval(&slab, &mut ns), expect, "compiled {}", expr_str);
+    };
+    chk("myscale(3, 4)", Ok(12.0), "IConst(12.0)");
+    chk("myscale(x, 4) + 1", Ok(21.0), "IAdd(InstructionI(1), C(1.0))");
+    chk("round(2, 3)", Ok(6.0), "IConst(6.0)");
+    chk("f(a) = myscale(a, a); f(3)", Ok(9.0), "ILetFunc { func: 0, body: I(InstructionI(3)) }");
+
+    // Impure functions are never folded:
+    let expr_i = parser.parse("count()", &mut slab.ps).unwrap();
+    slab.cs.clear();
+    let instr = expr_i.from(&slab.ps).compile(&slab.ps, &mut slab.cs);
+    assert_eq!(format!("{:?}", instr), "INativeFunc { func: NativeFunc(\"count\"), args: [] }");
+    let first = instr.eval(&slab, &mut ns).unwrap();
+    assert_eq!(instr.eval(&slab, &mut ns), Ok(first+1.0));
+
+    // Native functions aren't namespace variables:
+    let expr_i = parser.parse("myscale(x, y)", &mut slab.ps).unwrap();
+    let mut expect = BTreeSet::<String>::new();
+    expect.insert("x".to_string());
+    expect.insert("y".to_string());
+    assert_eq!(expr_i.from(&slab.ps).var_names(&slab), expect);
+    let instr = expr_i.from(&slab.ps).compile(&slab.ps, &mut slab.cs);
+    assert_eq!(instr.var_names(&slab), expect);
+
+    assert_eq!(parser.parse("1 + myscale(1)", &mut slab.ps), Err(Error::Spanned{err:Box::new(Error::WrongArgs("myscale: expected 2 args".to_string())), start:4, end:14}));
+}
+
+#[test]
+fn extended_math() {
+    let mut slab = Slab::new();
+    let mut ns = BTreeMap::<String,f64>::new();
+    ns.insert("X".to_string(), 2.0);
+    ns.insert("Y".to_string(), -0.5);
+
+    // Checks the interpreter and the compiler, with variable and constant arguments:
+    let mut chk = |expr_str:&str, expect:f64| {
+        for s in [expr_str.to_string(), expr_str.replace("X", "2").replace("Y", "(-0.5)")].iter() {
+            let expr_i = Parser::new().parse(s, &mut slab.ps).unwrap();
+            let val = expr_i.from(&slab.ps).eval(&slab, &mut ns).unwrap();
+            let instr = expr_i.from(&slab.ps).compile(&slab.ps, &mut slab.cs);
+            let cval = instr.eval(&slab, &mut ns).unwrap();
+            for v in [val, cval].iter() {
+                assert!(*v==expect || (v.is_nan() && expect.is_nan()) || (*v-expect).abs() <= 1e-14*expect.abs(), "{} = {} (expected {})", s, v, expect);
+            }
+        }
+    };
+    chk("sqrt(X)", 2f64.sqrt());
+    chk("cbrt(-27)", -3.0);
+    chk("exp(X)", 2f64.exp());
+    chk("ln(X)", 2f64.ln());
+    chk("log2(8)", 3.0);
+    chk("log10(X)", 2f64.log10());
+    chk("trunc(-2.7)", -2.0);
+    chk("frac(-2.75)", -0.75);
+    chk("deg(pi())", 180.0);
+    chk("rad(180)", std::f64::consts::PI);
+    chk("atan2(Y, X)", (-0.5f64).atan2(2.0));
+    chk("hypot(3, 4)", 5.0);
+    chk("pow(X, 10)", 1024.0);
+    chk("fmod(-7, X)", -1.0);
+    chk("clamp(5, 0, X)", 2.0);
+    chk("clamp(Y, 0, X)", 0.0);
+    chk("clamp(1, X, 0)", std::f64::NAN);
+
+    // Reference values from C's libm.  These are approximations, so they might not match to the last bit:
+    chk("erf(0)", 0.0);
+    chk("erf(Y)", -0.5204998778130465);
+    chk("erf(1)", 0.8427007929497149);
+    chk("erf(X)", 0.9953222650189527);
+    chk("erf(-3)", -0.9999779095030014);
+    chk("erf(5.5)", 0.9999999999999927);
+    chk("erf(10)", 1.0);
+    chk("gamma(5)", 24.0);
+    chk("gamma(0.5)", 1.7724538509055159);
+    chk("gamma(Y)", -3.544907701811032);
+    chk("gamma(10.5)", 1133278.3889487854);
+    chk("gamma(3.3)", 2.6834373819557675);
+    chk("gamma(30.5)", 4.8226969334909095e31);
+    chk("gamma(171.5)", 9.483367566824801e307);
+    chk("gamma(0)", std::f64::INFINITY);
+    chk("gamma(-2)", std::f64::NAN);
+    chk("lgamma(1)", 0.0);
+    chk("lgamma(0.5)", 0.5723649429247004);
+    chk("lgamma(3.3)", 0.9870985778947339);
+    chk("lgamma(-7.5)", -8.404537371451598);
+    chk("lgamma(Y)", 1.265512123484645);
+    chk("lgamma(100)", 359.1342053695754);
+    chk("lgamma(-2)", std::f64::INFINITY);
+
+    // Constant arguments are folded:
+    let expr_i = Parser::new().parse("sqrt(16) + hypot(3, 4) + clamp(7, 0, 5)", &mut slab.ps).unwrap();
+    assert_eq!(format!("{:?}", expr_i.from(&slab.ps).compile(&slab.ps, &mut slab.cs)), "IConst(14.0)");
+
+    let mut expect = BTreeSet::<String>::new();
+    expect.insert("x".to_string());
+    expect.insert("y".to_string());
+    expect.insert("z".to_string());
+    let expr_i = Parser::new().parse("atan2(x, 1) + sqrt(y) + clamp(1, 0, z)", &mut slab.ps).unwrap();
+    assert_eq!(expr_i.from(&slab.ps).var_names(&slab), expect);
+    let instr = expr_i.from(&slab.ps).compile(&slab.ps, &mut slab.cs);
+    assert_eq!(instr.var_names(&slab), expect);
+
+    assert_eq!(Parser::new().parse("sqrt(1, 2)", &mut slab.ps), Err(Error::Spanned{err:Box::new(Error::WrongArgs("sqrt: expected one arg".to_string())), start:0, end:10}));
+    assert_eq!(Parser::new().parse("atan2(1)", &mut slab.ps), Err(Error::Spanned{err:Box::new(Error::WrongArgs("atan2: expected atan2(y, x)".to_string())), start:0, end:8}));
+    assert_eq!(Parser::new().parse("clamp(1, 2)", &mut slab.ps), Err(Error::Spanned{err:Box::new(Error::WrongArgs("clamp: expected clamp(x, min, max)".to_string())), start:0, end:11}));
+}
+
+#[test]
+fn degrees() {
+    let mut slab = Slab::new();
+    let mut ns = BTreeMap::<String,f64>::new();
+    ns.insert("a".to_string(), 30.0);
+    ns.insert("h".to_string(), 0.5);
+
+    let mut chk = |parser:&Parser, expr_str:&str, expect:f64| {
+        let expr_i = parser.parse(expr_str, &mut slab.ps).unwrap();
+        assert_eq!(expr_i.from(&slab.ps).eval(&slab, &mut ns), Ok(expect), "{}", expr_str);
+        let instr = expr_i.from(&slab.ps).compile(&slab.ps, &mut slab.cs);
+        assert_eq!(instr.eval(&slab, &mut ns), Ok(expect), "compiled {}", expr_str);
+    };
+    let parser = Parser{degrees:true, ..Parser::new()};
+    chk(&parser, "sin(a)", 30f64.to_radians().sin());
+    chk(&parser, "cos(60)", 60f64.to_radians().cos());
+    chk(&parser, "tan(45)", 1.0);
+    chk(&parser, "cos(90)", 0.0);
+    chk(&parser, "sin(180)", 0.0);
+    chk(&parser, "sin(-90) + cos(540) + tan(-45)", -3.0);
+    chk(&parser, "sin(a + 720)", 30f64.to_radians().sin());
+    chk(&parser, "tan(90)", std::f64::INFINITY);
+    chk(&parser, "asin(h)", 0.5f64.asin().to_degrees());
+    chk(&parser, "acos(0.5)", 0.5f64.acos().to_degrees());
+    chk(&parser, "atan(1)", 45.0);
+    chk(&parser, "atan2(-1, -1)", -135.0);
+    chk(&parser, "sinh(h)", 0.5f64.sinh());
+    chk(&parser, "deg(pi()) + rad(180)", 180.0+std::f64::consts::PI);
+    chk(&parser, "f(x) = sin(x); f(90)", 1.0);
+
+    // Radians are the default:
+    chk(&Parser::new(), "sin(a)", 30f64.sin());
+    chk(&Parser::new(), "atan(1)", std::f64::consts::FRAC_PI_4);
+
+    // Constant arguments are still folded:
+    let expr_i = parser.parse("sin(90) + atan2(1, 0)", &mut slab.ps).unwrap();
+    slab.cs.clear();
+    assert_eq!(format!("{:?}", expr_i.from(&slab.ps).compile(&slab.ps, &mut slab.cs)), "IConst(91.0)");
+}
+
+#[test]
+fn stats() {
+    let mut slab = Slab::new();
+    let mut ns = BTreeMap::<String,f64>::new();
+    ns.insert("x".to_string(), 100.0);
+
+    let mut chk = |expr_str:&str, expect:f64| {
+        let expr_i = Parser::new().parse(expr_str, &mut slab.ps).unwrap();
+        let instr = expr_i.from(&slab.ps).compile(&slab.ps, &mut slab.cs);
+        for v in [expr_i.from(&slab.ps).eval(&slab, &mut ns).unwrap(), instr.eval(&slab, &mut ns).unwrap()].iter() {
+            assert!(*v==expect || (v.is_nan() && expect.is_nan()), "{} = {} (expected {})", expr_str, v, expect);
+        }
+    };
+    chk("sum(1, 2, 3.5)", 6.5);
+    chk("sum((x), 1, 2, 3)", 106.0);
+    chk("mean(1, 2, 3, x)", 26.5);
+    chk("mean(x)", 100.0);
+    chk("median(3, 1, 2)", 2.0);
+    chk("median(3, 1, 2, x)", 2.5);
+    chk("median(inf, inf)", std::f64::INFINITY);
+    chk("variance(2, 4, 4, 4, 5, 5, 7, 9)", 32.0/7.0);
+    chk("stddev(1, 2, 3, 4, 5)", 2.5f64.sqrt());
+    chk("variance(x, x, x)", 0.0);
+    chk("variance(x)", std::f64::NAN);
+    chk("count(1, x, 3)", 3.0);
+    chk("percentile(25, 1, 2, 3, 4, 5)", 2.0);
+    chk("percentile(10, x, 0)", 10.0);
+    chk("percentile(0, 5, x, 1) + percentile(100, 5, x, 1)", 101.0);
+    chk("percentile(101, 1, 2)", std::f64::NAN);
+    chk("percentile(-1, 1, 2)", std::f64::NAN);
+
+    // NaN propagates, except in the 'nan' variants:
+    chk("mean(1, NaN, x)", std::f64::NAN);
+    chk("median(1, NaN, x)", std::f64::NAN);
+    chk("count(NaN, 1)", 2.0);
+    chk("nansum(1, NaN, x)", 101.0);
+    chk("nanmean(1, NaN, x)", 50.5);
+    chk("nanmedian(NaN, 1, 2, x)", 2.0);
+    chk("nanvariance(NaN, 1, 3)", 2.0);
+    chk("nanstddev(1, 3, NaN)", 2f64.sqrt());
+    chk("nancount(NaN, 1, NaN, x)", 2.0);
+    chk("nanpercentile(50, 1, NaN, 3)", 2.0);
+    chk("nanpercentile(NaN, 1, 2)", std::f64::NAN);
+    chk("nanmean(NaN)", std::f64::NAN);
+
+    // The namespace is asked first, so nothing is folded by default:
+    let expr_i = Parser::new().parse("count(x, 2)", &mut slab.ps).unwrap();
+    slab.cs.clear();
+    assert_eq!(format!("{:?}", expr_i.from(&slab.ps).compile(&slab.ps, &mut slab.cs)), "IFuncStat { func: Count, skip_nan: false, ns_first: Some(\"count\"), args: [I(InstructionI(0)), C(2.0)] }");
+
+    // ...but the builtins can be used directly, and then constant arguments are folded:
+    let parser = Parser{builtins:vec![BuiltinRule::BuiltinFirst("mean".to_string()), BuiltinRule::BuiltinFirst("nanmedian".to_string()),
+                                      BuiltinRule::BuiltinFirst("count".to_string())], ..Parser::new()};
+    let expr_i = parser.parse("mean(1, 2, 6) + nanmedian(NaN, 4, 2)", &mut slab.ps).unwrap();
+    slab.cs.clear();
+    assert_eq!(format!("{:?}", expr_i.from(&slab.ps).compile(&slab.ps, &mut slab.cs)), "IConst(6.0)");
+    let expr_i = parser.parse("count(x, 2)", &mut slab.ps).unwrap();
+    slab.cs.clear();
+    assert_eq!(format!("{:?}", expr_i.from(&slab.ps).compile(&slab.ps, &mut slab.cs)), "IFuncStat { func: Count, skip_nan: false, ns_first: None, args: [I(InstructionI(0)), C(2.0)] }");
+
+    assert_eq!(Parser::new().parse("mean()", &mut slab.ps), Err(Error::Spanned{err:Box::new(Error::WrongArgs("mean: expected one or more args".to_string())), start:0, end:6}));
+    assert_eq!(Parser::new().parse("percentile(50)", &mut slab.ps), Err(Error::Spanned{err:Box::new(Error::WrongArgs("percentile: expected a percentile and one or more args".to_string())), start:0, end:14}));
+}
+
+#[test]
+fn strings() {
+    let mut slab = Slab::new();
+    let mut ns = StringToValNamespace::new();
+    ns.insert("status".to_string(), Val::from("OPEN"));
+    ns.insert("name".to_string(), Val::from("Pump-7"));
+    ns.insert("x".to_string(), Val::Num(2.0));
+
+    let mut chk = |expr_str:&str, expect:Result<Val,Error>| {
+        let expr_i = Parser::new().parse(expr_str, &mut slab.ps).unwrap();
+        let instr = expr_i.from(&slab.ps).compile(&slab.ps, &mut slab.cs);
+        assert_eq!(expr_i.from(&slab.ps).eval_val(&slab, &mut ns), expect, "{}", expr_str);
+        assert_eq!(instr.eval_val(&slab, &mut ns), expect, "{} (compiled)", expr_str);
+
+        // eval() returns the same numbers, but not strings:
+        let num = expect.clone().and_then(|v| v.num());
+        assert_eq!(expr_i.from(&slab.ps).eval(&slab, &mut ns), num, "{}", expr_str);
+        assert_eq!(instr.eval(&slab, &mut ns), num, "{} (compiled)", expr_str);
+    };
+    chk(r#"status == "OPEN""#, Ok(Val::Num(1.0)));
+    chk(r#"status != "OPEN" || x > 1"#, Ok(Val::Num(1.0)));
+    chk(r#""OPEN" == 1"#, Ok(Val::Num(0.0)));
+    chk(r#""abc" < "abd" && "b" > "abc" && "a" <= "a""#, Ok(Val::Num(1.0)));
+    chk(r#"name + "/" + x"#, Ok(Val::from("Pump-7/2")));
+    chk(r#""a" + 1 + 2"#, Ok(Val::from("a12")));
+    chk(r#"1 + 2 + "a""#, Ok(Val::from("3a")));
+    chk(r#"("id-" + x*3) == "id-6""#, Ok(Val::Num(1.0)));
+    chk(r#"x > 1 ? "big" : "small""#, Ok(Val::from("big")));
+    chk(r#""é""#, Ok(Val::from("é")));
+
+    chk(r#"len(name) + len("é")"#, Ok(Val::Num(7.0)));
+    chk("upper(name)", Ok(Val::from("PUMP-7")));
+    chk(r#"lower("ÀB")"#, Ok(Val::from("àb")));
+    chk(r#"contains(name, "-") + startswith(name, "Pu") + endswith(name, "6")"#, Ok(Val::Num(2.0)));
+    chk("str(x/4) + str(name)", Ok(Val::from("0.5Pump-7")));
+    chk("str(status) == status", Ok(Val::Num(1.0)));
+    chk(r#"sqrt(len("abcd"))"#, Ok(Val::Num(2.0)));
+
+    let mismatch = |msg:&str| Err(Error::TypeMismatch(msg.to_string()));
+    chk(r#""a" * 2"#, mismatch("strings only support '+' and comparisons"));
+    chk(r#""a" < 2"#, mismatch("cannot compare a string with a number"));
+    chk(r#"-"a""#, mismatch("expected a number, not a string"));
+    chk(r#"sin("a")"#, mismatch("expected a number, not a string"));
+    chk("len(x)", mismatch("len: expected a string"));
+    chk(r#"contains("a", 1)"#, mismatch("contains: expected a string"));
+
+    let mut parser = Parser::new();
+    parser.chain_cmp = true;
+    let expr_i = parser.parse(r#""a" < name < "c" != "d""#, &mut slab.ps).unwrap();
+    assert_eq!(expr_i.from(&slab.ps).eval(&slab, &mut ns), Ok(0.0));
+
+    // String variables are only visible to expressions that use strings:
+    let expr_i = Parser::new().parse("status", &mut slab.ps).unwrap();
+    assert_eq!(expr_i.from(&slab.ps).eval(&slab, &mut ns), Err(Error::Undefined("status".to_string())));
+    assert_eq!(expr_i.from(&slab.ps).eval_val(&slab, &mut ns), Ok(Val::from("OPEN")));
+
+    // Numeric expressions still compile to normal Instructions:
+    let expr_i = Parser::new().parse(r#"(status == "OPEN") + 1"#, &mut slab.ps).unwrap();
+    slab.cs.clear();
+    assert_eq!(format!("{:?}", expr_i.from(&slab.ps).compile(&slab.ps, &mut slab.cs)), "IAdd(InstructionI(0), C(1.0))");
+    assert_eq!(format!("{:?}", slab.cs.get_instr(InstructionI(0))), "IValExpr(ExpressionI(0))");
+    // ...which refer back to the parsed Expression, so using another slab is an error:
+    let expr_i = Parser::new().parse(r#"status == "OPEN""#, &mut slab.ps).unwrap();
+    let mut other = Slab::new();
+    let instr = expr_i.from(&slab.ps).compile(&other.ps, &mut other.cs);
+    assert_eq!(instr.eval(&other, &mut ns), Err(Error::Unreachable));
+
+    assert_eq!(Parser::new().parse("len()", &mut slab.ps), Err(Error::Spanned{err:Box::new(Error::WrongArgs("len: expected one arg".to_string())), start:0, end:5}));
+    assert_eq!(Parser::new().parse(r#"contains("a")"#, &mut slab.ps), Err(Error::Spanned{err:Box::new(Error::WrongArgs("contains: expected two args".to_string())), start:0, end:13}));
+    assert_eq!(Parser::new().parse(r#""abc"#, &mut slab.ps), Err(Error::Spanned{err:Box::new(Error::EofWhileParsing("string".to_string())), start:4, end:4}));
+}
+
+#[test]
+fn arrays() {
+    let mut slab = Slab::new();
+    let mut ns = StringToValNamespace::new();
+    ns.insert("xs".to_string(), Val::from(vec![1.0, 2.0, 3.0]));
+    ns.insert("ys".to_string(), Val::from(vec![10.0, 20.0, 30.0]));
+    ns.insert("empty".to_string(), Val::from(Vec::new()));
+    ns.insert("name".to_string(), Val::from("xs"));
+    ns.insert("x".to_string(), Val::Num(2.0));
+
+    let mut chk = |expr_str:&str, expect:Result<Val,Error>| {
+        let expr_i = Parser::new().parse(expr_str, &mut slab.ps).unwrap();
+        let instr = expr_i.from(&slab.ps).compile(&slab.ps, &mut slab.cs);
+        assert_eq!(expr_i.from(&slab.ps).eval_val(&slab, &mut ns), expect, "{}", expr_str);
+        assert_eq!(instr.eval_val(&slab, &mut ns), expect, "{} (compiled)", expr_str);
+
+        // Numeric results are also available from eval():
+        if let Ok(Val::Num(f)) = expect {
+            assert_eq!(expr_i.from(&slab.ps).eval(&slab, &mut ns), Ok(f), "{}", expr_str);
+            assert_eq!(instr.eval(&slab, &mut ns), Ok(f), "{} (compiled)", expr_str);
+        }
+    };
+    let arr = |a:&[f64]| Ok(Val::from(a.to_vec()));
+    chk("[1, 2, 3]", arr(&[1.0, 2.0, 3.0]));
+    chk("[x, x*2,]", arr(&[2.0, 4.0]));
+    chk("[]", arr(&[]));
+    chk("[[1, 2], xs]", arr(&[1.0, 2.0, 1.0, 2.0, 3.0]));
+    chk("[3]", Ok(Val::Num(3.0)));
+    chk("[3,]", arr(&[3.0]));
+    chk("xs", arr(&[1.0, 2.0, 3.0]));
+
+    chk("xs[0] + xs[2]", Ok(Val::Num(4.0)));
+    chk("xs[x - 0.5]", Ok(Val::Num(2.0)));
+    chk("xs[3] == xs[3]", Ok(Val::Num(0.0)));
+    chk("xs[-1] == xs[-1]", Ok(Val::Num(0.0)));
+    chk("len(xs) + len(empty) + len([1, 2])", Ok(Val::Num(5.0)));
+
+    chk("xs * 2", arr(&[2.0, 4.0, 6.0]));
+    chk("1 - xs", arr(&[0.0, -1.0, -2.0]));
+    chk("12 / xs", arr(&[12.0, 6.0, 4.0]));
+    chk("xs + ys", arr(&[11.0, 22.0, 33.0]));
+    chk("ys % xs^2", arr(&[0.0, 0.0, 3.0]));
+    chk("-xs + [1, 1, 1]", arr(&[0.0, -1.0, -2.0]));
+    chk("xs >= 2", arr(&[0.0, 1.0, 1.0]));
+    chk("xs == [1, 0, 3]", arr(&[1.0, 0.0, 1.0]));
+    chk("!(xs - 2)", arr(&[0.0, 1.0, 0.0]));
+    chk("xs && [0, 5, 6]", arr(&[0.0, 5.0, 6.0]));
+    chk("x > 1 ? xs : ys", arr(&[1.0, 2.0, 3.0]));
+
+    chk("sum(xs)", Ok(Val::Num(6.0)));
+    chk("sum(xs * ys, 1)", Ok(Val::Num(141.0)));
+    chk("mean([1, 2, 3, 6])", Ok(Val::Num(3.0)));
+    chk("count(empty)", Ok(Val::Num(0.0)));
+    chk("max(x)", Ok(Val::Num(2.0)));
+    chk("max(xs)", Ok(Val::Num(3.0)));
+    chk("max([1, 5, 3])", Ok(Val::Num(5.0)));
+    chk("min((xs))", Ok(Val::Num(1.0)));
+    chk("max(xs, 2.5)", Ok(Val::Num(3.0)));
+    chk("min(ys, xs + 5)", Ok(Val::Num(6.0)));
+    chk("min(-xs, 0) + max([1, 5, 2])", Ok(Val::Num(2.0)));
+    chk("max(empty, 1) == max(empty, 1)", Ok(Val::Num(0.0)));
+
+    chk(r#"str(xs) + "!""#, Ok(Val::from("[1, 2, 3]!")));
+
+    let mismatch = |msg:&str| Err(Error::TypeMismatch(msg.to_string()));
+    chk("xs + [1, 2]", mismatch("arrays have different lengths (3 and 2)"));
+    chk(r#"[1, "a"]"#, mismatch("arrays can only hold numbers"));
+    chk(r#"xs < "a""#, mismatch("cannot compare a string with an array"));
+    chk("sin([1, 2])", mismatch("expected a number, not an array"));
+    chk("sum(name)", mismatch("expected a number or an array, not a string"));
+    chk("ys[0] + zs[0]", Err(Error::Undefined("zs".to_string())));
+    chk("xs(0)", Err(Error::Undefined("xs".to_string())));  // Only square brackets index arrays.
+
+    // A failed function call only asks the namespace once:
+    let lookups = std::cell::Cell::new(0);
+    let mut cb = |_:&str, _:Vec<f64>| -> Option<f64> { lookups.set(lookups.get()+1); None };
+    let expr_i = Parser::new().parse("nope(1)", &mut slab.ps).unwrap();
+    assert_eq!(expr_i.from(&slab.ps).eval(&slab, &mut cb), Err(Error::Undefined("nope".to_string())));
+    assert_eq!(lookups.get(), 1);
+    let instr = expr_i.from(&slab.ps).compile(&slab.ps, &mut slab.cs);
+    assert_eq!(instr.eval(&slab, &mut cb), Err(Error::Undefined("nope".to_string())));
+    assert_eq!(lookups.get(), 2);
+
+    // eval() needs a number:
+    let expr_i = Parser::new().parse("[1, 2] * 2", &mut slab.ps).unwrap();
+    assert_eq!(expr_i.from(&slab.ps).eval(&slab, &mut ns), Err(Error::TypeMismatch("expected a number, not an array".to_string())));
+
+    assert_eq!(Parser::new().parse("[1, 2", &mut slab.ps), Err(Error::Spanned{err:Box::new(Error::EofWhileParsing("square brackets".to_string())), start:5, end:5}));
+
+    // Only names can be indexed:
+    assert_eq!(Parser::new().parse("[1, 2, 3][1]", &mut slab.ps), Err(Error::Spanned{err:Box::new(Error::UnparsedTokensRemaining("[1]".to_string())), start:9, end:12}));
+    assert_eq!(Parser::new().parse("(xs * 2)[0]", &mut slab.ps), Err(Error::Spanned{err:Box::new(Error::UnparsedTokensRemaining("[0]".to_string())), start:8, end:11}));
+    assert_eq!(Parser::new().parse("max(xs)[0]", &mut slab.ps), Err(Error::Spanned{err:Box::new(Error::UnparsedTokensRemaining("[0]".to_string())), start:7, end:10}));
+}
+
+#[test]
+fn bool_keywords() {
+    let mut slab = Slab::new();
+    let mut ns = BTreeMap::<String,f64>::new();
+    ns.insert("x".to_string(), 2.0);
+    ns.insert("notes".to_string(), 3.0);
+    ns.insert("trueness".to_string(), 4.0);
+    ns.insert("true".to_string(), 5.0);
+
+    let mut chk = |expr_str:&str, expect:f64| {
+        let expr_i = Parser::new().parse(expr_str, &mut slab.ps).unwrap();
+        assert_eq!(expr_i.from(&slab.ps).eval(&slab, &mut ns), Ok(expect), "{}", expr_str);
+    };
+    chk("true + false", 1.0);
+    chk("x > 1 == true", 1.0);
+    chk("not x", 0.0);
+    chk("not x and true", 0.0);  // Like '!', 'not' only applies to the next value.
+    chk("not (x > 3)", 1.0);
+    chk("-true", -1.0);
+    chk("notes + trueness", 7.0);  // Only whole words are keywords.
+    chk("`true`", 5.0);
+
+    // Keywords aren't variables:
+    let expr_i = Parser::new().parse("not true or false or x", &mut slab.ps).unwrap();
+    let mut expect = BTreeSet::<String>::new();
+    expect.insert("x".to_string());
+    assert_eq!(expr_i.from(&slab.ps).var_names(&slab), expect);
+    assert_eq!(Parser::quote_name("true"), "`true`");
+    assert_eq!(Parser::quote_name("not"), "`not`");
+}
+
 fn my_evalns_cb_function(_:&str, _:Vec<f64>) -> Option<f64> { None }
 #[test]
 fn evalns_cb_ownership() {
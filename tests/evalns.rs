@@ -136,3 +136,46 @@ fn custom_vector_funcs() {
     assert_eq!(val, 12.8);
 }
 
+
+#[test]
+fn print_sink() {
+    let mut ns = fasteval::StringToF64Namespace::new();
+    ns.insert("x".to_string(), 2.0);
+
+    let mut out = String::new();
+    {
+        let mut sink = fasteval::PrintSink::to_buffer(&mut ns, &mut out);
+        assert_eq!(ez_eval(r#"print("x is", x, "\tok") + print("%03d|%.2e\n", x, x*1000)"#, &mut sink).unwrap(), 2002.0);
+        assert_eq!(ez_eval("x + y", &mut sink), Err(fasteval::Error::Undefined("y".to_string())));
+    }
+    assert_eq!(out, "x is 2 \tok\n002|2.00e+03\n");
+
+    // The compiled form prints the same way:
+    {
+        use fasteval::{Compiler, Evaler};
+        let mut slab = fasteval::Slab::new();
+        let instr = fasteval::Parser::new().parse(r#"print("%g", x/4)"#, &mut slab.ps).unwrap().from(&slab.ps).compile(&slab.ps, &mut slab.cs);
+        let mut count = 0;
+        assert_eq!(instr.eval(&slab, &mut fasteval::PrintSink::new(&mut ns, |text| { assert_eq!(text, "0.5"); count+=1; })), Ok(0.5));
+        assert_eq!(count, 1);
+    }
+
+    let mut bytes = Vec::<u8>::new();
+    ez_eval(r#"print("a", 1, "b")"#, &mut fasteval::PrintSink::to_writer(&mut ns, &mut bytes)).unwrap();
+    assert_eq!(bytes, b"a 1 b\n");
+
+    assert_eq!(ez_eval(r#"print("hidden", x)"#, &mut fasteval::PrintSink::disabled(&mut ns)).unwrap(), 2.0);
+
+    // Cached namespaces stay cached:
+    let mut num_lookups = 0;
+    {
+        let mut cached = fasteval::CachedCallbackNamespace::new(|name:&str, _args:Vec<f64>| {
+            num_lookups += 1;
+            match name { "x" => Some(3.0), _ => None }
+        });
+        let mut sink = fasteval::PrintSink::disabled(&mut cached);
+        fasteval::Cached::cache_set(&mut sink, "y".to_string(), 4.0);
+        assert_eq!(ez_eval("print(x) + x + y", &mut sink).unwrap(), 10.0);
+    }
+    assert_eq!(num_lookups, 1);
+}
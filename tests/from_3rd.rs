@@ -34,10 +34,10 @@ fn chk_ok(expr_str:&str, expect_compile_str:&str, expect_slab_str:&str, expect_e
     })().unwrap();
 }
 
-fn chk_perr(expr_str:&str, expect_err:Error) {
+fn chk_perr(expr_str:&str, expect_err:Error, start:usize, end:usize) {
     let mut slab = Slab::new();
     let res = Parser::new().parse(expr_str, &mut slab.ps);
-    assert_eq!(res, Err(expect_err));
+    assert_eq!(res, Err(Error::Spanned{err:Box::new(expect_err), start, end}));
 }
 
 fn chk_eerr(expr_str:&str, expect_err:Error) {
@@ -50,18 +50,18 @@ fn chk_eerr(expr_str:&str, expect_err:Error) {
 
 #[test]
 fn meval() {
-    chk_perr("", Error::EofWhileParsing("value".to_string()));
-    chk_perr("(", Error::EofWhileParsing("value".to_string()));
-    chk_perr("0(", Error::UnparsedTokensRemaining("(".to_string()));
+    chk_perr("", Error::EofWhileParsing("value".to_string()), 0, 0);
+    chk_perr("(", Error::EofWhileParsing("value".to_string()), 1, 1);
+    chk_perr("0(", Error::UnparsedTokensRemaining("(".to_string()), 1, 2);
     chk_eerr("e", Error::Undefined("e".to_string()));
-    chk_perr("1E", Error::ParseF64("1E".to_string()));
-    chk_perr("1e+", Error::ParseF64("1e+".to_string()));
-    chk_perr("()", Error::InvalidValue);
-    chk_perr("2)", Error::UnparsedTokensRemaining(")".to_string()));
-    chk_perr("2^", Error::EofWhileParsing("value".to_string()));
-    chk_perr("(((2)", Error::EofWhileParsing("parentheses".to_string()));
-    chk_perr("f(2,)", Error::InvalidValue);
-    chk_perr("f(,2)", Error::InvalidValue);
+    chk_perr("1E", Error::ParseF64("1E".to_string()), 0, 2);
+    chk_perr("1e+", Error::ParseF64("1e+".to_string()), 0, 3);
+    chk_perr("()", Error::InvalidValue, 1, 2);
+    chk_perr("2)", Error::UnparsedTokensRemaining(")".to_string()), 1, 2);
+    chk_perr("2^", Error::EofWhileParsing("value".to_string()), 2, 2);
+    chk_perr("(((2)", Error::EofWhileParsing("parentheses".to_string()), 5, 5);
+    chk_perr("f(2,)", Error::InvalidValue, 4, 5);
+    chk_perr("f(,2)", Error::InvalidValue, 2, 3);
 
     chk_ok("round(sin (pi()) * cos(0))",
 "IConst(0.0)",
@@ -91,35 +91,37 @@ fn meval() {
 
 #[test]
 fn overflow_stack() {
-    chk_perr(from_utf8(&[b'('; 1]).unwrap(), Error::EofWhileParsing("value".to_string()));
-    chk_perr(from_utf8(&[b'('; 2]).unwrap(), Error::EofWhileParsing("value".to_string()));
-    chk_perr(from_utf8(&[b'('; 4]).unwrap(), Error::EofWhileParsing("value".to_string()));
-    chk_perr(from_utf8(&[b'('; 8]).unwrap(), Error::EofWhileParsing("value".to_string()));
-    chk_perr(from_utf8(&[b'('; 16]).unwrap(), Error::EofWhileParsing("value".to_string()));
-    chk_perr(from_utf8(&[b'('; 32]).unwrap(), Error::EofWhileParsing("value".to_string()));
-    chk_perr(from_utf8(&[b'('; 33]).unwrap(), Error::TooDeep);
-    chk_perr(from_utf8(&[b'('; 64]).unwrap(), Error::TooDeep);
-    chk_perr(from_utf8(&[b'('; 128]).unwrap(), Error::TooDeep);
-    chk_perr(from_utf8(&[b'('; 256]).unwrap(), Error::TooDeep);
-    chk_perr(from_utf8(&[b'('; 512]).unwrap(), Error::TooDeep);
-    chk_perr(from_utf8(&[b'('; 1024]).unwrap(), Error::TooDeep);
-    chk_perr(from_utf8(&[b'('; 2048]).unwrap(), Error::TooDeep);
-    chk_perr(from_utf8(&[b'('; 4096]).unwrap(), Error::TooDeep);
-    chk_perr(from_utf8(&[b'('; 8192]).unwrap(), Error::TooLong);
+    chk_perr(from_utf8(&[b'('; 1]).unwrap(), Error::EofWhileParsing("value".to_string()), 1, 1);
+    chk_perr(from_utf8(&[b'('; 2]).unwrap(), Error::EofWhileParsing("value".to_string()), 2, 2);
+    chk_perr(from_utf8(&[b'('; 4]).unwrap(), Error::EofWhileParsing("value".to_string()), 4, 4);
+    chk_perr(from_utf8(&[b'('; 8]).unwrap(), Error::EofWhileParsing("value".to_string()), 8, 8);
+    chk_perr(from_utf8(&[b'('; 16]).unwrap(), Error::EofWhileParsing("value".to_string()), 16, 16);
+    chk_perr(from_utf8(&[b'('; 32]).unwrap(), Error::EofWhileParsing("value".to_string()), 32, 32);
+    chk_perr(from_utf8(&[b'('; 33]).unwrap(), Error::TooDeep, 33, 33);
+    chk_perr(from_utf8(&[b'('; 64]).unwrap(), Error::TooDeep, 33, 33);
+    chk_perr(from_utf8(&[b'('; 128]).unwrap(), Error::TooDeep, 33, 33);
+    chk_perr(from_utf8(&[b'('; 256]).unwrap(), Error::TooDeep, 33, 33);
+    chk_perr(from_utf8(&[b'('; 512]).unwrap(), Error::TooDeep, 33, 33);
+    chk_perr(from_utf8(&[b'('; 1024]).unwrap(), Error::TooDeep, 33, 33);
+    chk_perr(from_utf8(&[b'('; 2048]).unwrap(), Error::TooDeep, 33, 33);
+    chk_perr(from_utf8(&[b'('; 4096]).unwrap(), Error::TooDeep, 33, 33);
+    chk_perr(from_utf8(&[b'('; 8192]).unwrap(), Error::TooLong, 0, 8192);
 
     // Test custom safety parse limits:
     assert_eq!(Parser{expr_len_limit:fasteval::parser::DEFAULT_EXPR_LEN_LIMIT,
-                      expr_depth_limit:31}.parse(
+                      expr_depth_limit:31,
+                      ..Parser::new()}.parse(
                         from_utf8(&[b'('; 32]).unwrap(),
                         &mut Slab::new().ps
                       ),
-               Err(Error::TooDeep));
+               Err(Error::Spanned{err:Box::new(Error::TooDeep), start:32, end:32}));
 
     assert_eq!(Parser{expr_len_limit:8,
-                      expr_depth_limit:fasteval::parser::DEFAULT_EXPR_DEPTH_LIMIT}.parse(
+                      expr_depth_limit:fasteval::parser::DEFAULT_EXPR_DEPTH_LIMIT,
+                      ..Parser::new()}.parse(
                         from_utf8(&[b'('; 32]).unwrap(),
                         &mut Slab::new().ps
                       ),
-               Err(Error::TooLong));
+               Err(Error::Spanned{err:Box::new(Error::TooLong), start:0, end:32}));
 }
 
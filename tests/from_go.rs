@@ -63,7 +63,7 @@ fn aaa_test_b0() {
 "Slab{ exprs:{ 0:Expression { first: EConstant(3.14), pairs: [ExprPair(EAdd, EConstant(5.0))] } }, vals:{}, instrs:{} }");
     // Go can parse this, but not Rust:
     assert_eq!(parse_raw("3.14 + 4.999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999", &mut slab),
-Err(Error::ParseF64("4.999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999".to_string())));
+Err(Error::Spanned{err:Box::new(Error::ParseF64("4.999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999".to_string())), start:7, end:257}));
     ok_parse("3.14 + 0.9999", &mut slab);
     assert_eq!(format!("{:?}",&slab),
 "Slab{ exprs:{ 0:Expression { first: EConstant(3.14), pairs: [ExprPair(EAdd, EConstant(0.9999))] } }, vals:{}, instrs:{} }");
@@ -80,9 +80,9 @@ fn aaa_test_b1() {
     let mut slab = Slab::new();
 
     assert_eq!(parse_raw("3.14 + 4.99999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999.9999", &mut slab),
-Err(Error::ParseF64("4.99999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999.9999".to_string())));
+Err(Error::Spanned{err:Box::new(Error::ParseF64("4.99999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999999.9999".to_string())), start:7, end:262}));
     assert_eq!(parse_raw("3.14 + 4.9999.9999", &mut slab),
-Err(Error::ParseF64("4.9999.9999".to_string())));
+Err(Error::Spanned{err:Box::new(Error::ParseF64("4.9999.9999".to_string())), start:7, end:18}));
 }
 
 #[test]
@@ -90,7 +90,7 @@ fn aaa_test_b2() {
     let mut slab = Slab::new();
 
     assert_eq!(parse_raw("3.14 + .", &mut slab),
-Err(Error::ParseF64(".".to_string())));
+Err(Error::Spanned{err:Box::new(Error::ParseF64(".".to_string())), start:7, end:8}));
 }
 
 #[test]
@@ -116,7 +116,7 @@ fn aaa_test_c1() {
     let mut slab = Slab::new();
 
     assert_eq!(parse_raw("3+5-XYZ_ab~c_def123", &mut slab),
-Err(Error::UnparsedTokensRemaining("~c_def123".to_string())));
+Err(Error::Spanned{err:Box::new(Error::UnparsedTokensRemaining("~c_def123".to_string())), start:10, end:19}));
 }
 
 #[test]
@@ -142,7 +142,7 @@ fn aaa_test_d1() {
     let mut slab = Slab::new();
 
     assert_eq!(parse_raw(" 3 + ( -x + y  ", &mut slab),
-Err(Error::EofWhileParsing("parentheses".to_string())));
+Err(Error::Spanned{err:Box::new(Error::EofWhileParsing("parentheses".to_string())), start:15, end:15}));
 }
 
 #[test]
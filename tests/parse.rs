@@ -1,4 +1,4 @@
-use fasteval::{Error, Slab, Parser};
+use fasteval::{Evaler, Error, Slab, Parser, EmptyNamespace};
 
 #[test]
 fn basics() {
@@ -49,9 +49,9 @@ fn consts() {
     assert_eq!(format!("{:?}",&slab),
 "Slab{ exprs:{ 0:Expression { first: EConstant(12.0), pairs: [] } }, vals:{}, instrs:{} }");
 
-    assert_eq!(Parser::new().parse(".", &mut slab.ps), Err(Error::ParseF64(".".to_string())));
+    assert_eq!(Parser::new().parse(".", &mut slab.ps), Err(Error::Spanned{err:Box::new(Error::ParseF64(".".to_string())), start:0, end:1}));
 
-    assert_eq!(Parser::new().parse("12..34", &mut slab.ps), Err(Error::ParseF64("12..34".to_string())));
+    assert_eq!(Parser::new().parse("12..34", &mut slab.ps), Err(Error::Spanned{err:Box::new(Error::ParseF64("12..34".to_string())), start:0, end:6}));
 
     Parser::new().parse("12.34k", &mut slab.ps).unwrap();
     assert_eq!(format!("{:?}",&slab),
@@ -155,12 +155,125 @@ fn consts() {
 
 
 
-    assert_eq!(Parser::new().parse("-infK", &mut slab.ps), Err(Error::UnparsedTokensRemaining("K".to_string())));
-    assert_eq!(Parser::new().parse("NaNK", &mut slab.ps), Err(Error::UnparsedTokensRemaining("K".to_string())));
-    assert_eq!(Parser::new().parse("12.34e56K", &mut slab.ps), Err(Error::UnparsedTokensRemaining("K".to_string())));
+    assert_eq!(Parser::new().parse("-infK", &mut slab.ps), Err(Error::Spanned{err:Box::new(Error::UnparsedTokensRemaining("K".to_string())), start:4, end:5}));
+    assert_eq!(Parser::new().parse("NaNK", &mut slab.ps), Err(Error::Spanned{err:Box::new(Error::UnparsedTokensRemaining("K".to_string())), start:3, end:4}));
+    assert_eq!(Parser::new().parse("12.34e56K", &mut slab.ps), Err(Error::Spanned{err:Box::new(Error::UnparsedTokensRemaining("K".to_string())), start:8, end:9}));
 
 }
 
+#[test]
+fn radix_consts() {
+    let mut slab = Slab::new();
+    let mut ns = EmptyNamespace;
+    let mut chk = |expr_str:&str, expect:f64| {
+        assert_eq!(Parser::new().parse(expr_str, &mut slab.ps).unwrap().from(&slab.ps).eval(&slab, &mut ns), Ok(expect), "{}", expr_str);
+    };
+    chk("0x1F", 31.0);
+    chk("0XfF", 255.0);
+    chk("-0x10", -16.0);
+    chk("+0x10", 16.0);
+    chk("0o17", 15.0);
+    chk("0b1010", 10.0);
+    chk("0B1010_1010", 170.0);
+    chk("0xFFFF_FFFF", 4294967295.0);
+    chk("0x10 + 0b1 * 0o10", 24.0);
+    chk("1_000_000", 1000000.0);
+    chk("1__0", 10.0);
+    chk("0.000_001", 0.000001);
+    chk("1_000.5e1_0", 1000.5e10);
+    chk("1_000k", 1000000.0);
+    chk("-1_0", -10.0);
+
+    let mut chk_err = |expr_str:&str, tok:&str, start:usize, end:usize| {
+        assert_eq!(Parser::new().parse(expr_str, &mut slab.ps), Err(Error::Spanned{err:Box::new(Error::ParseF64(tok.to_string())), start, end}), "{}", expr_str);
+    };
+    chk_err("0x", "0x", 0, 2);
+    chk_err("1 + 0x + 1", "0x", 4, 6);
+    chk_err("0b102", "0b102", 0, 5);
+    chk_err("0o18", "0o18", 0, 4);
+    chk_err("0x1G", "0x1G", 0, 4);
+    chk_err("0x1.8", "0x1.8", 0, 5);
+    chk_err("0x_1", "0x_1", 0, 4);
+    chk_err("0x1_", "0x1_", 0, 4);
+    chk_err("1_", "1_", 0, 2);
+    chk_err("1_.5", "1_.5", 0, 4);
+    chk_err("1_e5", "1_e5", 0, 4);
+
+    // A leading '_' makes a variable name:
+    assert_eq!(Parser::new().parse("_1", &mut slab.ps).unwrap().from(&slab.ps).var_names(&slab).len(), 1);
+}
+
+#[test]
+fn ternary() {
+    let mut slab = Slab::new();
+
+    Parser::new().parse("x > 0 ? 1 : 2", &mut slab.ps).unwrap();
+    assert_eq!(format!("{:?}",&slab),
+"Slab{ exprs:{ 0:Expression { first: EStdFunc(EVar(\"x\")), pairs: [ExprPair(EGT, EConstant(0.0))] }, 1:Expression { first: EConstant(1.0), pairs: [] }, 2:Expression { first: EConstant(2.0), pairs: [] }, 3:Expression { first: ETernary { cond: ExpressionI(0), then: ExpressionI(1), otherwise: ExpressionI(2) }, pairs: [] } }, vals:{}, instrs:{} }");
+
+    assert_eq!(Parser::new().parse("1 ? 2", &mut slab.ps), Err(Error::Spanned{err:Box::new(Error::EofWhileParsing("ternary".to_string())), start:5, end:5}));
+    assert_eq!(Parser::new().parse("1 ? 2 3", &mut slab.ps), Err(Error::Spanned{err:Box::new(Error::Expected(":".to_string())), start:6, end:7}));
+    assert_eq!(Parser::new().parse("1 ? 2 : 3 : 4", &mut slab.ps), Err(Error::Spanned{err:Box::new(Error::UnparsedTokensRemaining(": 4".to_string())), start:10, end:13}));
+}
+
+#[test]
+fn spans() {
+    let mut slab = Slab::new();
+
+    let e = Parser::new().parse("  sin(1,2) + 1", &mut slab.ps).unwrap_err();
+    assert_eq!(e.unspanned(), &Error::WrongArgs("sin: expected one arg".to_string()));
+    assert_eq!(e.span(), Some(2..10));
+
+    let e = Parser::new().parse("(1 x", &mut slab.ps).unwrap_err();
+    assert_eq!(e.unspanned(), &Error::Expected(")".to_string()));
+    assert_eq!(e.span(), Some(3..4));
+
+    let e = Parser::new().parse("f(1 2)", &mut slab.ps).unwrap_err();
+    assert_eq!(e.span(), Some(4..5));
+
+    // Spans never split a multi-byte char:
+    let e = Parser::new().parse("3 + €", &mut slab.ps).unwrap_err();
+    assert_eq!(e.unspanned(), &Error::InvalidValue);
+    assert_eq!(e.span(), Some(4..7));
+
+    assert_eq!(Error::InvalidValue.span(), None);
+}
+
+#[test]
+fn render() {
+    let mut slab = Slab::new();
+
+    let expr_str = "3 + €";
+    let e = Parser::new().parse(expr_str, &mut slab.ps).unwrap_err();
+    assert_eq!(e.render(expr_str),
+"error: expected a value (a number, string, variable, function call, or parenthesized expression)
+ --> 1:5
+  |
+1 | 3 + €
+  |     ^");
+
+    let expr_str = "1 +\n\tmin() + 2";
+    let e = Parser::new().parse(expr_str, &mut slab.ps).unwrap_err();
+    assert_eq!(e.render(expr_str),
+"error: wrong arguments: min: expected one or more args
+ --> 2:2
+  |
+2 | 	min() + 2
+  | 	^^^^^");
+
+    let expr_str = "(1+2";
+    let e = Parser::new().parse(expr_str, &mut slab.ps).unwrap_err();
+    assert_eq!(e.render(expr_str),
+"error: unexpected end of input while parsing parentheses
+ --> 1:5
+  |
+1 | (1+2
+  |     ^");
+
+    assert_eq!(Error::Undefined("x".to_string()).render("x+1"), "error: undefined variable or function: x");
+    assert_eq!(format!("{}", e), "unexpected end of input while parsing parentheses at byte 4");
+}
+
 #[test]
 #[cfg(feature="unsafe-vars")]
 fn unsafe_var() {
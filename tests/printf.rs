@@ -0,0 +1,116 @@
+use fasteval::printf::{sprintf, Arg::{self, Num, Str}};
+use fasteval::{Evaler, Error, Slab, Parser};
+
+use std::collections::BTreeMap;
+
+fn chk(fmt:&str, args:&[Arg], expect:&str) {
+    assert_eq!(sprintf(fmt, args), Ok(expect.to_string()), "{}", fmt);
+}
+fn chk_err(fmt:&str, args:&[Arg], expect:&str) {
+    assert_eq!(sprintf(fmt, args), Err(Error::WrongArgs(expect.to_string())), "{}", fmt);
+}
+
+#[test]
+fn integers() {
+    chk("%d", &[Num(3.7)], "3");
+    chk("%i", &[Num(-3.7)], "-3");
+    chk("%d", &[Num(-0.5)], "0");
+    chk("%d", &[Num(1e20)], "100000000000000000000");
+    chk("%5d|%-5d|%05d", &[Num(42.0), Num(42.0), Num(42.0)], "   42|42   |00042");
+    chk("%05d", &[Num(-42.0)], "-0042");
+    chk("%+d % d %+d", &[Num(5.0), Num(5.0), Num(-5.0)], "+5  5 -5");
+    chk("%.3d|%06.3d", &[Num(7.0), Num(7.0)], "007|   007");
+    chk("%ld %hd", &[Num(1.0), Num(2.0)], "1 2");
+
+    chk("%x %X %o", &[Num(255.0), Num(255.0), Num(8.0)], "ff FF 10");
+    chk("%04x", &[Num(-255.9)], "-0ff");
+    chk_err("%x", &[Num(1e20)], "print: 100000000000000000000 is too large for %x");
+}
+
+#[test]
+fn floats() {
+    chk("%f", &[Num(3.14159265)], "3.141593");
+    chk("%.3f", &[Num(3.14159265)], "3.142");
+    chk("%.0f", &[Num(7.6)], "8");
+    chk("%10.2f|", &[Num(-3.14159)], "     -3.14|");
+    chk("%010.2f", &[Num(-3.14159)], "-000003.14");
+    chk("%-8.1f|%+.1f", &[Num(2.0), Num(2.0)], "2.0     |+2.0");
+    chk("%.1f", &[Num(-0.0)], "-0.0");
+
+    chk("%e", &[Num(12345.678)], "1.234568e+04");
+    chk("%.2E", &[Num(0.000123)], "1.23E-04");
+    chk("%e", &[Num(0.0)], "0.000000e+00");
+    chk("%.1e", &[Num(-1e100)], "-1.0e+100");
+    chk("%12.3e|", &[Num(1.5)], "   1.500e+00|");
+
+    chk("%g", &[Num(100000.0)], "100000");
+    chk("%g", &[Num(1000000.0)], "1e+06");
+    chk("%g %g", &[Num(0.0001), Num(0.00001)], "0.0001 1e-05");
+    chk("%g", &[Num(3.14159265)], "3.14159");
+    chk("%.3g", &[Num(1234.5)], "1.23e+03");
+    chk("%.0g", &[Num(26.0)], "3e+01");
+    chk("%g %g", &[Num(0.0), Num(2.5)], "0 2.5");
+    chk("%G", &[Num(1.5e-10)], "1.5E-10");
+    chk("%8g|", &[Num(0.5)], "     0.5|");
+}
+
+#[test]
+fn special_values() {
+    chk("%5.2f|%d|%x", &[Num(std::f64::NAN), Num(std::f64::NAN), Num(std::f64::NAN)], "  NaN|NaN|NaN");
+    chk("%d %e %g", &[Num(std::f64::INFINITY), Num(std::f64::INFINITY), Num(std::f64::INFINITY)], "inf inf inf");
+    chk("%08.2f|%+f", &[Num(std::f64::NEG_INFINITY), Num(std::f64::INFINITY)], "    -inf|+inf");
+}
+
+#[test]
+fn strings() {
+    chk("", &[], "");
+    chk("no conversions", &[], "no conversions");
+    chk("100%% of %s", &[Str("it".to_string())], "100% of it");
+    chk("%%%d%%", &[Num(5.0)], "%5%");
+    chk("%.2s|%-6s|%6s|%06s", &[Str("hello".to_string()), Str("ab".to_string()), Str("ab".to_string()), Str("ab".to_string())], "he|ab    |    ab|    ab");
+    chk("é%dé", &[Num(1.0)], "é1é");
+    chk("%3s|", &[Str("€".to_string())], "  €|");
+}
+
+#[test]
+fn errors() {
+    chk_err("%d %d", &[Num(1.0)], "print: the format expects 2 values, but got 1");
+    chk_err("%d", &[Num(1.0), Num(2.0)], "print: the format expects 1 values, but got 2");
+    chk_err("%%", &[Num(1.0)], "print: the format expects 0 values, but got 1");
+    chk_err("%q", &[Num(1.0)], "print: unknown format conversion '%q'");
+    chk_err("%#x", &[Num(1.0)], "print: unknown format conversion '%#'");
+    chk_err("%5€", &[Num(1.0)], "print: unknown format conversion '%5€'");
+    chk_err("abc %", &[], "print: incomplete format conversion at the end of the string");
+    chk_err("%-08.", &[], "print: incomplete format conversion at the end of the string");
+    chk_err("%s", &[Num(1.0)], "print: %s expects a string");
+    chk_err("%d", &[Str("x".to_string())], "print: %d expects a number, not a string");
+    chk_err("%2000d", &[Num(1.0)], "print: format width or precision is larger than 1000");
+    chk_err("%.1001f", &[Num(1.0)], "print: format width or precision is larger than 1000");
+}
+
+#[test]
+fn print_func() {
+    let mut slab = Slab::new();
+    let mut ns = BTreeMap::<String,f64>::new();
+    ns.insert("x".to_string(), 2.5);
+
+    // printf mode evaluates to the last value, just like the normal mode:
+    for expr_str in [r#"print("%s=%.2f\n", "x", x)"#, r#"print("%d%%\n", 1, x)"#].iter() {
+        let expr_i = Parser::new().parse(expr_str, &mut slab.ps);
+        if expr_str.contains("1, x") {
+            // The args are checked against the format during parse:
+            assert_eq!(expr_i, Err(Error::Spanned{err:Box::new(Error::WrongArgs("print: the format expects 1 values, but got 2".to_string())), start:0, end:expr_str.len()}));
+            continue;
+        }
+        assert_eq!(expr_i.unwrap().from(&slab.ps).eval(&slab, &mut ns), Ok(2.5));
+    }
+
+    assert_eq!(Parser::new().parse(r#"1 + print("%d", "x")"#, &mut slab.ps),
+               Err(Error::Spanned{err:Box::new(Error::WrongArgs("print: %d expects a number, not a string".to_string())), start:4, end:20}));
+    assert_eq!(Parser::new().parse(r#"print("%z")"#, &mut slab.ps),
+               Err(Error::Spanned{err:Box::new(Error::WrongArgs("print: unknown format conversion '%z'".to_string())), start:0, end:11}));
+
+    // Strings without '%' still use the normal mode:
+    let expr_i = Parser::new().parse(r#"print("x is", x, "units")"#, &mut slab.ps).unwrap();
+    assert_eq!(expr_i.from(&slab.ps).eval(&slab, &mut ns), Ok(2.5));
+}